@@ -0,0 +1,66 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 1) in vec2 a_tex_coords;
+
+out vec2 TexCoords;
+
+void main()
+{
+	TexCoords = a_tex_coords;
+	gl_Position = vec4(a_pos, 1.0);
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+out vec3 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D src_texture;
+uniform vec2 src_resolution;
+// First step reads the gamma encoded scene, linearizes it and keeps only what is above threshold
+uniform bool prefilter;
+uniform float threshold;
+uniform float knee;
+
+vec3 threshold_color(vec3 color) {
+	float brightness = max(color.r, max(color.g, color.b));
+	float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+	soft = soft * soft / (4.0 * knee + 0.00001);
+	float contribution = max(soft, brightness - threshold) / max(brightness, 0.00001);
+	return color * contribution;
+}
+
+// 13 tap filter from Call of Duty: Advanced Warfare's bloom
+void main()
+{
+	vec2 texel = 1.0 / src_resolution;
+	float x = texel.x;
+	float y = texel.y;
+
+	vec3 a = texture(src_texture, TexCoords + vec2(-2.0 * x,  2.0 * y)).rgb;
+	vec3 b = texture(src_texture, TexCoords + vec2( 0.0,      2.0 * y)).rgb;
+	vec3 c = texture(src_texture, TexCoords + vec2( 2.0 * x,  2.0 * y)).rgb;
+	vec3 d = texture(src_texture, TexCoords + vec2(-2.0 * x,  0.0)).rgb;
+	vec3 e = texture(src_texture, TexCoords).rgb;
+	vec3 f = texture(src_texture, TexCoords + vec2( 2.0 * x,  0.0)).rgb;
+	vec3 g = texture(src_texture, TexCoords + vec2(-2.0 * x, -2.0 * y)).rgb;
+	vec3 h = texture(src_texture, TexCoords + vec2( 0.0,     -2.0 * y)).rgb;
+	vec3 i = texture(src_texture, TexCoords + vec2( 2.0 * x, -2.0 * y)).rgb;
+	vec3 j = texture(src_texture, TexCoords + vec2(-x,  y)).rgb;
+	vec3 k = texture(src_texture, TexCoords + vec2( x,  y)).rgb;
+	vec3 l = texture(src_texture, TexCoords + vec2(-x, -y)).rgb;
+	vec3 m = texture(src_texture, TexCoords + vec2( x, -y)).rgb;
+
+	vec3 color = e * 0.125;
+	color += (a + c + g + i) * 0.03125;
+	color += (b + d + f + h) * 0.0625;
+	color += (j + k + l + m) * 0.125;
+
+	if (prefilter) {
+		color = threshold_color(pow(max(color, vec3(0.0)), vec3(2.2)));
+	}
+
+	FragColor = max(color, vec3(0.0001));
+}
@@ -0,0 +1,44 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 1) in vec2 a_tex_coords;
+
+out vec2 TexCoords;
+
+void main()
+{
+	TexCoords = a_tex_coords;
+	gl_Position = vec4(a_pos, 1.0);
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+out vec3 FragColor;
+
+in vec2 TexCoords;
+
+// Smaller mip, additively blended onto the next larger one
+uniform sampler2D src_texture;
+uniform float filter_radius;
+
+// 3x3 tent filter
+void main()
+{
+	float x = filter_radius;
+	float y = filter_radius;
+
+	vec3 a = texture(src_texture, TexCoords + vec2(-x,  y)).rgb;
+	vec3 b = texture(src_texture, TexCoords + vec2( 0.0, y)).rgb;
+	vec3 c = texture(src_texture, TexCoords + vec2( x,  y)).rgb;
+	vec3 d = texture(src_texture, TexCoords + vec2(-x,  0.0)).rgb;
+	vec3 e = texture(src_texture, TexCoords).rgb;
+	vec3 f = texture(src_texture, TexCoords + vec2( x,  0.0)).rgb;
+	vec3 g = texture(src_texture, TexCoords + vec2(-x, -y)).rgb;
+	vec3 h = texture(src_texture, TexCoords + vec2( 0.0, -y)).rgb;
+	vec3 i = texture(src_texture, TexCoords + vec2( x, -y)).rgb;
+
+	vec3 color = e * 4.0;
+	color += (b + d + f + h) * 2.0;
+	color += (a + c + g + i);
+	FragColor = color / 16.0;
+}
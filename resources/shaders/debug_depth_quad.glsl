@@ -17,10 +17,11 @@ out vec4 FragColor;
 
 in vec2 TexCoords;
 
-uniform sampler2D depth_map;
+uniform sampler2DArray depth_map;
+uniform int layer;
 
 void main()
 {    
-	float depth_value = texture(depth_map, TexCoords).r;
+	float depth_value = texture(depth_map, vec3(TexCoords, layer)).r;
 	FragColor = vec4(vec3(depth_value), 1.0);
 }
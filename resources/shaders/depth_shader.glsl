@@ -6,15 +6,43 @@ layout (location = 4) in vec4 bone_weights;
 
 uniform mat4 light_space_mat;
 uniform mat4 model;
+uniform mat4 node_transform;
 uniform bool is_animated;
 
+// Instanced static models, see instancing.rs
+struct Instance {
+	mat4 model;
+	uvec4 flags;
+};
+layout (std430, binding = 7) readonly buffer InstanceBuffer {
+	Instance instances[];
+};
+uniform bool is_instanced;
+uniform int instance_offset;
+
 const int MAX_BONE_INFLUENCE = 4;
 const int MAX_BONES = 100;
 
 uniform mat4 bone_transforms[MAX_BONES];
 
+// Morph targets
+const int MAX_MORPH_TARGETS = 8;
+layout (std430, binding = 3) readonly buffer MorphDeltas {
+	// [target][vertex][position, normal]
+	vec4 morph_deltas[];
+};
+uniform int morph_target_count;
+uniform int morph_vertex_count;
+uniform float morph_weights[MAX_MORPH_TARGETS];
+
 void main()
 {
+	mat4 world = is_instanced ? instances[instance_offset + gl_InstanceID].model : model;
+	vec3 morphed_pos = a_pos;
+	for (int i = 0; i < morph_target_count; i++) {
+		morphed_pos += morph_deltas[(i * morph_vertex_count + gl_VertexID) * 2].xyz * morph_weights[i];
+	}
+
 	if (is_animated) {
 		vec4 totalPosition = vec4(0.0f);
 		for(int i = 0 ; i < MAX_BONE_INFLUENCE; i++)
@@ -23,15 +51,15 @@ void main()
 				continue;
 			if(bone_ids[i] >=MAX_BONES) 
 			{
-				totalPosition = vec4(a_pos,1.0f);
+				totalPosition = vec4(morphed_pos,1.0f);
 				break;
 			}
-			vec4 localPosition = bone_transforms[bone_ids[i]] * vec4(a_pos,1.0f);
+			vec4 localPosition = bone_transforms[bone_ids[i]] * vec4(morphed_pos,1.0f);
 			totalPosition += localPosition * bone_weights[i];
 		}
-		gl_Position = light_space_mat * model * totalPosition;
+		gl_Position = light_space_mat * world * node_transform * totalPosition;
 	} else {
-		gl_Position = light_space_mat * model * vec4(a_pos, 1.0);
+		gl_Position = light_space_mat * world * node_transform * vec4(morphed_pos, 1.0);
 	}
 }
 
@@ -5,16 +5,33 @@ layout (location = 1) in vec3 a_normal;
 layout (location = 2) in vec2 a_tex_coords;
 layout (location = 3) in ivec4 bone_ids;
 layout (location = 4) in vec4 bone_weights;
+// xyz tangent, w bitangent sign
+layout (location = 5) in vec4 a_tangent;
 
 out vec2 TexCoords;
 out vec3 Normal;
+out vec4 Tangent;
 out vec3 FragPos;
-out vec4 FragPosLightSpace;
+// Positive distance in front of the camera, picks the shadow cascade
+out float ViewDepth;
+flat out uint InstanceFlags;
 
 uniform mat4 projection;
 uniform mat4 view;
 uniform mat4 model;
-uniform mat4 light_space_mat;
+// Per submesh placement from the model file, identity for skinned submeshes
+uniform mat4 node_transform;
+
+// Instanced static models, see instancing.rs
+struct Instance {
+	mat4 model;
+	uvec4 flags;
+};
+layout (std430, binding = 7) readonly buffer InstanceBuffer {
+	Instance instances[];
+};
+uniform bool is_instanced;
+uniform int instance_offset;
 
 // Animated model stuff
 uniform bool is_animated;
@@ -23,44 +40,77 @@ const int MAX_BONES = 100;
 
 uniform mat4 bone_transforms[MAX_BONES];
 
+// Morph targets
+const int MAX_MORPH_TARGETS = 8;
+layout (std430, binding = 3) readonly buffer MorphDeltas {
+	// [target][vertex][position, normal]
+	vec4 morph_deltas[];
+};
+uniform int morph_target_count;
+uniform int morph_vertex_count;
+uniform float morph_weights[MAX_MORPH_TARGETS];
+
+vec3 morphed_pos;
+vec3 morphed_normal;
+
+void apply_morphs() {
+	morphed_pos = a_pos;
+	morphed_normal = a_normal;
+
+	for (int i = 0; i < morph_target_count; i++) {
+		int idx = (i * morph_vertex_count + gl_VertexID) * 2;
+		morphed_pos += morph_deltas[idx].xyz * morph_weights[i];
+		morphed_normal += morph_deltas[idx + 1].xyz * morph_weights[i];
+	}
+}
 
 
 void main()
 {
+	apply_morphs();
+	mat4 world = (is_instanced ? instances[instance_offset + gl_InstanceID].model : model) * node_transform;
+	InstanceFlags = is_instanced ? instances[instance_offset + gl_InstanceID].flags.x : 0u;
+
 	if (is_animated) {
 		vec4 totalPosition = vec4(0.0f);
 		vec3 totalNormal = vec3(0.0f);
+		vec3 totalTangent = vec3(0.0f);
 		for(int i = 0 ; i < MAX_BONE_INFLUENCE; i++)
 		{
 			if(bone_ids[i] == -1) 
 				continue;
 			if(bone_ids[i] >=MAX_BONES) 
 			{
-				totalPosition = vec4(a_pos,1.0f);
+				totalPosition = vec4(morphed_pos,1.0f);
+				totalNormal = morphed_normal;
+				totalTangent = a_tangent.xyz;
 				break;
 			}
-			vec4 localPosition = bone_transforms[bone_ids[i]] * vec4(a_pos,1.0f);
+			vec4 localPosition = bone_transforms[bone_ids[i]] * vec4(morphed_pos,1.0f);
 			totalPosition += localPosition * bone_weights[i];
 
 
 			mat3 boneNormalMatrix = transpose(inverse(mat3(bone_transforms[bone_ids[i]])));
-			totalNormal += boneNormalMatrix * a_normal * bone_weights[i];
+			totalNormal += boneNormalMatrix * morphed_normal * bone_weights[i];
+			totalTangent += mat3(bone_transforms[bone_ids[i]]) * a_tangent.xyz * bone_weights[i];
 		}
 
-		FragPos = vec3(model * totalPosition);
-		FragPosLightSpace = light_space_mat * vec4(FragPos, 1.0);
+		FragPos = vec3(world * totalPosition);
+		ViewDepth = -(view * vec4(FragPos, 1.0)).z;
 
-		mat3 normalMatrix = transpose(inverse(mat3(model)));
+		mat3 normalMatrix = transpose(inverse(mat3(world)));
 		Normal = normalize(normalMatrix * totalNormal);
+		Tangent = vec4(mat3(world) * totalTangent, a_tangent.w);
 
-		mat4 viewModel = view * model;
+		mat4 viewModel = view * world;
 		gl_Position =  projection * viewModel * totalPosition;
 		TexCoords = a_tex_coords;
 	} else {
-		FragPos = vec3(model * vec4(a_pos, 1.0));
-		Normal = mat3(transpose(inverse(model))) * a_normal;  
+		FragPos = vec3(world * vec4(morphed_pos, 1.0));
+		Normal = mat3(transpose(inverse(world))) * morphed_normal;  
+		Tangent = vec4(mat3(world) * a_tangent.xyz, a_tangent.w);
 		TexCoords = a_tex_coords;    
-		FragPosLightSpace = light_space_mat * vec4(FragPos, 1.0);
+		ViewDepth = -(view * vec4(FragPos, 1.0)).z;
 		gl_Position = projection * view * vec4(FragPos, 1.0);
 	}
 }
@@ -71,17 +121,79 @@ out vec4 FragColor;
 
 in vec3 FragPos;
 in vec3 Normal;
+in vec4 Tangent;
 in vec2 TexCoords;
-in vec4 FragPosLightSpace;
+in float ViewDepth;
+flat in uint InstanceFlags;
+
+// Must match INSTANCE_* in instancing.rs
+const uint INSTANCE_SELECTED = 1u;
+const uint INSTANCE_FLASHING = 2u;
 
 uniform bool has_opacity_texture;
-uniform sampler2D shadow_map;
+uniform bool has_normal_map;
+uniform bool has_displacement_map;
+uniform float parallax_scale;
+
+// Cascaded shadow maps, see shadows.rs
+const int SHADOW_CASCADES = 4;
+uniform sampler2DArray shadow_map;
+uniform mat4 light_space_mats[SHADOW_CASCADES];
+uniform float cascade_splits[SHADOW_CASCADES];
+uniform bool cascade_debug;
+
+// Point and spot lights, see GpuLight in lights.rs
+struct Light {
+	vec4 position;    // xyz position, w type
+	vec4 direction;   // xyz spot direction, w cos outer angle
+	vec4 color;       // rgb color, w cos inner angle
+	vec4 attenuation; // constant, linear, quadratic, range
+	vec4 params;      // x shadow cube index or -1
+};
+layout (std430, binding = 4) readonly buffer LightBuffer {
+	Light lights[];
+};
+uniform int light_count;
+uniform samplerCubeArray point_shadow_maps;
+const float LIGHT_SPOT = 1.0;
+
+// Clustered lighting, see clusters.rs. Each cluster is an (offset, count) range into the index list
+layout (std430, binding = 5) readonly buffer ClusterRanges {
+	uvec2 cluster_ranges[];
+};
+layout (std430, binding = 6) readonly buffer ClusterIndices {
+	uint cluster_light_indices[];
+};
+uniform bool use_clusters;
+uniform bool cluster_heatmap;
+uniform vec3 cluster_dims;
+uniform vec2 cluster_tile_size;
+uniform float cluster_slice_scale;
+uniform float cluster_slice_bias;
+// Lights per cluster that shows up fully red in the heatmap
+const float CLUSTER_HEATMAP_MAX = 16.0;
+
+// Screen space ambient occlusion, see ssao.rs. Same resolution as the framebuffer
+uniform bool use_ssao;
+uniform sampler2D ssao_map;
+
+// Exponential height fog, see sky.rs. Colors are linear
+uniform bool use_fog;
+uniform vec3 fog_color;
+uniform vec3 fog_sun_color;
+uniform float fog_density;
+uniform float fog_height_falloff;
+uniform float fog_base_height;
 
 struct Material {
 	sampler2D Diffuse;
 	sampler2D Specular;
 	sampler2D Emissive;
 	sampler2D Opacity;
+	sampler2D NormalMap;
+	sampler2D Roughness;
+	sampler2D Metalness;
+	sampler2D Displacement;
 };
 uniform Material material;
 
@@ -102,16 +214,65 @@ uniform bool selection_fresnel;
 uniform bool do_reg_fresnel;
 uniform float elapsed;
 uniform bool flash_white;
+// Past 1.0 so the flash blooms in the HDR target
+const float FLASH_WHITE_INTENSITY = 4.0;
+
+// Per model material params, see MaterialParams in entity_config.rs
+uniform bool use_pbr;
+uniform vec4 base_color_factor;
+uniform float roughness_factor;
+uniform float metalness_factor;
+uniform float emissive_strength;
+
+const float PI = 3.14159265359;
+
+float ambient_occlusion() {
+	return use_ssao ? texelFetch(ssao_map, ivec2(gl_FragCoord.xy), 0).r : 1.0;
+}
+
+// rgb is gamma encoded like everything else going into the scene target
+vec3 apply_fog(vec3 rgb) {
+	vec3 ray = FragPos - view_position;
+	float dist = length(ray);
+	if (!use_fog || dist < 0.0001) {
+		return rgb;
+	}
+
+	// Density falls off exponentially with height, integrated along the ray from the camera
+	float origin_density = fog_density * exp(-fog_height_falloff * (view_position.y - fog_base_height));
+	float falloff = fog_height_falloff * ray.y;
+	float integral = abs(falloff) > 0.0001 ? (1.0 - exp(-falloff)) / falloff : 1.0;
+	float amount = 1.0 - exp(-origin_density * dist * integral);
+
+	float sun_amount = max(dot(ray / dist, normalize(dir_light.direction)), 0.0);
+	vec3 color = mix(fog_color, fog_sun_color, pow(sun_amount, 8.0));
+
+	vec3 linear_rgb = pow(max(rgb, vec3(0.0)), vec3(2.2));
+	return pow(mix(linear_rgb, color, amount), vec3(1.0 / 2.2));
+}
 
 float fresnel_bias = 0.1; // minimum effect strength
 float fresnel_scale = 1.0; // how strong the effect is
 float fresnel_power = 3.0; // sharpness of the edge
 
+int select_cascade() {
+	for (int i = 0; i < SHADOW_CASCADES; i++) {
+		if (ViewDepth < cascade_splits[i]) {
+			return i;
+		}
+	}
+	return -1;
+}
+
 float ShadowCalculation(float dot_light_normal) {
-	vec3 pos = FragPosLightSpace.xyz * 0.5 + 0.5;
-	// if (pos.z > 1.0) {
-	// 	pos.z = 1.0;
-	// }
+	int cascade = select_cascade();
+	if (cascade == -1) {
+		// beyond the last cascade counts as lit
+		return 1.0;
+	}
+
+	vec4 light_space_pos = light_space_mats[cascade] * vec4(FragPos, 1.0);
+	vec3 pos = light_space_pos.xyz * 0.5 + 0.5;
 	if (pos.x < 0.0 || pos.x > 1.0 ||
 		pos.y < 0.0 || pos.y > 1.0 ||
 		pos.z < 0.0 || pos.z > 1.0) {
@@ -121,58 +282,277 @@ float ShadowCalculation(float dot_light_normal) {
 
 	float bias = max(bias_scalar * (1.0 - dot_light_normal), 0.0005);
 
+	// 5x5 PCF
 	float shadow = 0.0;
-	vec2 texel_size = 1.0 / textureSize(shadow_map, 0);
-	for (int x = -1; x <= 1; ++x) {
-		for (int y = -1; y <=1; ++y) {
-			float depth = texture(shadow_map, pos.xy + vec2(x, y) * texel_size).r;
+	vec2 texel_size = 1.0 / vec2(textureSize(shadow_map, 0).xy);
+	for (int x = -2; x <= 2; ++x) {
+		for (int y = -2; y <= 2; ++y) {
+			float depth = texture(shadow_map, vec3(pos.xy + vec2(x, y) * texel_size, cascade)).r;
 			shadow += (depth + bias) < pos.z ? 0.0 : 1.0;
 		}
 	}
 
-	return shadow / 9.0; 
+	return shadow / 25.0; 
+}
+
+mat3 tangent_frame() {
+	vec3 N = normalize(Normal);
+	// Re-orthogonalize, skinning and non-uniform scale can skew the interpolated tangent
+	vec3 T = normalize(Tangent.xyz - N * dot(N, Tangent.xyz));
+	vec3 B = cross(N, T) * Tangent.w;
+	return mat3(T, B, N);
+}
+
+// Parallax occlusion mapping, the displacement map stores height so depth is its inverse
+vec2 parallax_uv(vec2 uv, vec3 view_dir_tangent) {
+	const float min_layers = 8.0;
+	const float max_layers = 32.0;
+	float num_layers = mix(max_layers, min_layers, abs(view_dir_tangent.z));
+	float layer_depth = 1.0 / num_layers;
+	vec2 delta_uv = view_dir_tangent.xy / max(view_dir_tangent.z, 0.05) * parallax_scale / num_layers;
+
+	float current_layer_depth = 0.0;
+	float current_depth = 1.0 - texture(material.Displacement, uv).r;
+	while (current_layer_depth < current_depth) {
+		uv -= delta_uv;
+		current_depth = 1.0 - texture(material.Displacement, uv).r;
+		current_layer_depth += layer_depth;
+	}
 
+	// Interpolate between the last two layers to hide the stepping
+	vec2 prev_uv = uv + delta_uv;
+	float after = current_depth - current_layer_depth;
+	float before = (1.0 - texture(material.Displacement, prev_uv).r) - current_layer_depth + layer_depth;
+	float weight = after / (after - before);
+	return mix(uv, prev_uv, weight);
 }
 
-vec4 calculate_directional_light() {
+const vec3 point_shadow_offsets[20] = vec3[](
+	vec3( 1,  1,  1), vec3( 1, -1,  1), vec3(-1, -1,  1), vec3(-1,  1,  1),
+	vec3( 1,  1, -1), vec3( 1, -1, -1), vec3(-1, -1, -1), vec3(-1,  1, -1),
+	vec3( 1,  1,  0), vec3( 1, -1,  0), vec3(-1, -1,  0), vec3(-1,  1,  0),
+	vec3( 1,  0,  1), vec3(-1,  0,  1), vec3( 1,  0, -1), vec3(-1,  0, -1),
+	vec3( 0,  1,  1), vec3( 0, -1,  1), vec3( 0, -1, -1), vec3( 0,  1, -1)
+);
+
+// Cubes store distance to the light divided by the light's range
+float point_shadow(vec3 light_to_frag, float dist, float range, int index) {
+	float bias = 0.05;
+	// Wider filter the further away the fragment is from the viewer
+	float disk_radius = (1.0 + length(view_position - FragPos) / range) / 50.0;
+	float shadow = 0.0;
+	for (int i = 0; i < 20; i++) {
+		float closest = texture(point_shadow_maps, vec4(light_to_frag + point_shadow_offsets[i] * disk_radius, index)).r * range;
+		shadow += (dist - bias) > closest ? 0.0 : 1.0;
+	}
+	return shadow / 20.0;
+}
+
+// Attenuated, cone masked and shadowed color of a local light with the direction to it in L
+vec3 local_light_radiance(Light light, out vec3 L) {
+	vec3 to_light = light.position.xyz - FragPos;
+	float dist = length(to_light);
+	L = to_light / max(dist, 0.0001);
+
+	float range = light.attenuation.w;
+	if (dist > range) {
+		return vec3(0.0);
+	}
+
+	float attenuation = 1.0 / (light.attenuation.x + light.attenuation.y * dist + light.attenuation.z * dist * dist);
+	if (light.position.w == LIGHT_SPOT) {
+		float theta = dot(L, normalize(-light.direction.xyz));
+		float cos_outer = light.direction.w;
+		float cos_inner = light.color.w;
+		attenuation *= clamp((theta - cos_outer) / max(cos_inner - cos_outer, 0.0001), 0.0, 1.0);
+	}
+
+	int shadow_index = int(light.params.x);
+	if (shadow_index >= 0 && attenuation > 0.0) {
+		attenuation *= point_shadow(-to_light, dist, range, shadow_index);
+	}
+
+	return light.color.rgb * attenuation;
+}
+
+int cluster_index() {
+	ivec3 dims = ivec3(cluster_dims);
+	ivec2 tile = clamp(ivec2(gl_FragCoord.xy / cluster_tile_size), ivec2(0), dims.xy - 1);
+	int slice = clamp(int(floor(log(max(ViewDepth, 0.0001)) * cluster_slice_scale - cluster_slice_bias)), 0, dims.z - 1);
+	return tile.x + tile.y * dims.x + slice * dims.x * dims.y;
+}
+
+// Lights that can reach this fragment, its cluster's list or every light with clustering off
+uvec2 local_light_range() {
+	if (use_clusters) {
+		return cluster_ranges[cluster_index()];
+	}
+	return uvec2(0, light_count);
+}
+
+Light local_light(uvec2 range, uint i) {
+	return use_clusters ? lights[cluster_light_indices[range.x + i]] : lights[i];
+}
+
+// Black for an empty cluster, then blue through green to red at CLUSTER_HEATMAP_MAX
+vec3 cluster_heatmap_color() {
+	float count = float(cluster_ranges[cluster_index()].y);
+	if (count == 0.0) {
+		return vec3(0.0);
+	}
+	float t = clamp(count / CLUSTER_HEATMAP_MAX, 0.0, 1.0);
+	return t < 0.5 ? mix(vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0), t * 2.0) : mix(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), t * 2.0 - 1.0);
+}
+
+vec3 cascade_debug_color() {
+	const vec3 colors[SHADOW_CASCADES] = vec3[](
+		vec3(1.0, 0.3, 0.3),
+		vec3(0.3, 1.0, 0.3),
+		vec3(0.3, 0.3, 1.0),
+		vec3(1.0, 1.0, 0.3)
+	);
+	int cascade = select_cascade();
+	return cascade == -1 ? vec3(1.0) : colors[cascade];
+}
+
+// =============================================================
+// Cook-Torrance GGX
+// =============================================================
+float distribution_ggx(vec3 N, vec3 H, float roughness) {
+	float a = roughness * roughness;
+	float a2 = a * a;
+	float n_dot_h = max(dot(N, H), 0.0);
+	float denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+	return a2 / (PI * denom * denom);
+}
+
+float geometry_schlick_ggx(float n_dot_v, float roughness) {
+	float r = roughness + 1.0;
+	float k = (r * r) / 8.0;
+	return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+	return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 F0) {
+	return F0 + (1.0 - F0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 brdf_direct(vec3 albedo, vec3 F0, vec3 N, vec3 V, vec3 L, vec3 radiance, float roughness, float metalness) {
+	vec3 H = normalize(V + L);
+	float n_dot_v = max(dot(N, V), 0.0001);
+	float n_dot_l = max(dot(N, L), 0.0);
+
+	float D = distribution_ggx(N, H, roughness);
+	float G = geometry_smith(n_dot_v, n_dot_l, roughness);
+	vec3 F = fresnel_schlick(max(dot(H, V), 0.0), F0);
+
+	vec3 specular = (D * G * F) / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+	vec3 kD = (1.0 - F) * (1.0 - metalness);
+	return (kD * albedo / PI + specular) * radiance * PI * n_dot_l;
+}
+
+// Textures are authored in sRGB, so lighting happens on linearized albedo and gets re-encoded at the end
+vec3 shade_pbr(vec3 albedo, vec3 N, vec3 V, vec3 L, float shadow, vec2 uv) {
+	albedo = pow(albedo, vec3(2.2));
+	float roughness = clamp(texture(material.Roughness, uv).r * roughness_factor, 0.04, 1.0);
+	float metalness = clamp(texture(material.Metalness, uv).r * metalness_factor, 0.0, 1.0);
+	vec3 F0 = mix(vec3(0.04), albedo, metalness);
+
+	vec3 direct = shadow * brdf_direct(albedo, F0, N, V, L, dir_light.diffuse, roughness, metalness);
+	uvec2 range = local_light_range();
+	for (uint i = 0; i < range.y; i++) {
+		vec3 local_L;
+		vec3 radiance = local_light_radiance(local_light(range, i), local_L);
+		direct += brdf_direct(albedo, F0, N, V, local_L, radiance, roughness, metalness);
+	}
+
+	// No environment lighting yet, so the ambient term is the fresnel weighted split between diffuse and specular
+	float n_dot_v = max(dot(N, V), 0.0001);
+	vec3 F_ambient = fresnel_schlick(n_dot_v, F0);
+	vec3 ambient = dir_light.ambient * ((1.0 - F_ambient) * (1.0 - metalness) * albedo + F_ambient * (1.0 - roughness) * albedo);
+	ambient *= ambient_occlusion();
+
+	vec3 color = direct + ambient;
+	return pow(color, vec3(1.0 / 2.2));
+}
+
+// Old Blinn-Phong-ish look for the point and spot lights, summed before the albedo multiply
+vec3 shade_legacy_local(vec3 N, vec3 V, vec3 spec_color) {
+	vec3 result = vec3(0.0);
+	uvec2 range = local_light_range();
+	for (uint i = 0; i < range.y; i++) {
+		vec3 L;
+		vec3 radiance = local_light_radiance(local_light(range, i), L);
+		float diff = max(dot(N, L), 0.0);
+		float spec = pow(max(dot(V, reflect(-L, N)), 0.0), 36.0);
+		result += radiance * (diff + spec * spec_color);
+	}
+	return result;
+}
+
+vec4 calculate_lighting() {
     vec3 lightColor = dir_light.diffuse;
-	vec3 tex_color = texture(material.Diffuse, TexCoords).rgb;
-	vec3 spec_color = texture(material.Specular, TexCoords).rgb;
-	vec3 emiss_color = texture(material.Emissive, TexCoords).rgb;
+	vec3 viewDir = normalize(view_position - FragPos);
+
+	bool use_tangent_frame = has_normal_map || has_displacement_map;
+	mat3 TBN = use_tangent_frame ? tangent_frame() : mat3(1.0);
+
+	vec2 uv = TexCoords;
+	if (has_displacement_map) {
+		uv = parallax_uv(uv, normalize(transpose(TBN) * viewDir));
+	}
+
+	vec4 base_color = texture(material.Diffuse, uv) * base_color_factor;
+	vec3 tex_color = base_color.rgb;
+	vec3 spec_color = texture(material.Specular, uv).rgb;
+	vec3 emiss_color = texture(material.Emissive, uv).rgb * emissive_strength;
 	
-	float alpha = texture(material.Diffuse, TexCoords).a;
+	float alpha = base_color.a;
 
 	if (alpha_test_pass && alpha < 0.1)
 		discard;
 
-	if (flash_white) {
+	if (flash_white || (InstanceFlags & INSTANCE_FLASHING) != 0u) {
 		float t = mod(elapsed, 0.15);
 		if (t < 0.075) {
-			return vec4(1.0, 1.0, 1.0, alpha);	
+			return vec4(vec3(FLASH_WHITE_INTENSITY), alpha);
 		} else {
 			discard;
 		}
 	}
 	// Ambient
-    vec3 ambient = vec3(dir_light.ambient);
+    vec3 ambient = vec3(dir_light.ambient) * ambient_occlusion();
 	
 	// Diffuse
     // vec3 lightDir = normalize(dir_light.view_pos - FragPos);
 	vec3 lightDir = normalize(dir_light.direction);
     vec3 norm = normalize(Normal);
+	if (has_normal_map) {
+		vec3 tangent_normal = texture(material.NormalMap, uv).rgb * 2.0 - 1.0;
+		norm = normalize(TBN * tangent_normal);
+	}
 	float dot_light_normal = dot(lightDir, norm);
-    float diff = max(dot_light_normal, 0.0);
-    vec3 diffuse = diff * lightColor;
+	float shadow = ShadowCalculation(dot_light_normal);
 
-	// Specular
-	vec3 viewDir = normalize(view_position - FragPos);
-	vec3 reflectDir = reflect(-lightDir, norm);
-	float spec = pow(max(dot(viewDir, reflectDir), 0.0), 36.0);
-	vec3 specular = dir_light.specular * spec * spec_color;
+	vec3 result_rgb;
+	if (use_pbr) {
+		result_rgb = shade_pbr(tex_color, norm, viewDir, lightDir, shadow, uv) + emiss_color;
+	} else {
+		float diff = max(dot_light_normal, 0.0);
+		vec3 diffuse = diff * lightColor;
 
-	float shadow = ShadowCalculation(dot_light_normal);
+		// Specular
+		vec3 reflectDir = reflect(-lightDir, norm);
+		float spec = pow(max(dot(viewDir, reflectDir), 0.0), 36.0);
+		vec3 specular = dir_light.specular * spec * spec_color;
 
-    vec3 result_rgb = ((shadow * (diffuse + specular )) + ambient) * tex_color.rgb + emiss_color;
+		vec3 local = shade_legacy_local(norm, viewDir, spec_color);
+
+		result_rgb = ((shadow * (diffuse + specular )) + ambient + local) * tex_color.rgb + emiss_color;
+	}
 
 
 	if (do_reg_fresnel) {
@@ -181,7 +561,7 @@ vec4 calculate_directional_light() {
 		result_rgb = mix(result_rgb, reg_fresnel_color, reg_fresnel * 0.6);
 	}
 
-	if (selection_fresnel) {
+	if (selection_fresnel || (InstanceFlags & INSTANCE_SELECTED) != 0u) {
 		float fresnel = fresnel_bias + fresnel_scale * pow(1.0 - max(dot(norm, viewDir), 0.0), fresnel_power);
 		float pulse = 0.875 + 0.125 * sin(elapsed * 6.0);
 		pulse = pow(pulse, 3.0);
@@ -193,10 +573,19 @@ vec4 calculate_directional_light() {
 	}
 
 
+	if (cascade_debug) {
+		result_rgb *= cascade_debug_color();
+	}
+
+	if (cluster_heatmap) {
+		result_rgb = mix(result_rgb, cluster_heatmap_color(), 0.6);
+	}
+
 	return vec4(result_rgb, alpha);
 }
 
 void main() {    
-	vec4 result = calculate_directional_light();
+	vec4 result = calculate_lighting();
+	result.rgb = apply_fog(result.rgb);
 	FragColor = result;
 }
@@ -0,0 +1,82 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 3) in ivec4 bone_ids;
+layout (location = 4) in vec4 bone_weights;
+
+out vec3 FragPos;
+
+// View projection of the cube face being rendered
+uniform mat4 light_space_mat;
+uniform mat4 model;
+uniform mat4 node_transform;
+uniform bool is_animated;
+
+// Instanced static models, see instancing.rs
+struct Instance {
+	mat4 model;
+	uvec4 flags;
+};
+layout (std430, binding = 7) readonly buffer InstanceBuffer {
+	Instance instances[];
+};
+uniform bool is_instanced;
+uniform int instance_offset;
+
+const int MAX_BONE_INFLUENCE = 4;
+const int MAX_BONES = 100;
+
+uniform mat4 bone_transforms[MAX_BONES];
+
+// Morph targets
+const int MAX_MORPH_TARGETS = 8;
+layout (std430, binding = 3) readonly buffer MorphDeltas {
+	// [target][vertex][position, normal]
+	vec4 morph_deltas[];
+};
+uniform int morph_target_count;
+uniform int morph_vertex_count;
+uniform float morph_weights[MAX_MORPH_TARGETS];
+
+void main()
+{
+	vec3 morphed_pos = a_pos;
+	for (int i = 0; i < morph_target_count; i++) {
+		morphed_pos += morph_deltas[(i * morph_vertex_count + gl_VertexID) * 2].xyz * morph_weights[i];
+	}
+
+	vec4 local_pos = vec4(morphed_pos, 1.0);
+	if (is_animated) {
+		vec4 totalPosition = vec4(0.0f);
+		for(int i = 0 ; i < MAX_BONE_INFLUENCE; i++)
+		{
+			if(bone_ids[i] == -1) 
+				continue;
+			if(bone_ids[i] >=MAX_BONES) 
+			{
+				totalPosition = vec4(morphed_pos,1.0f);
+				break;
+			}
+			totalPosition += bone_transforms[bone_ids[i]] * vec4(morphed_pos,1.0f) * bone_weights[i];
+		}
+		local_pos = totalPosition;
+	}
+
+	mat4 world = is_instanced ? instances[instance_offset + gl_InstanceID].model : model;
+	vec4 world_pos = world * node_transform * local_pos;
+	FragPos = world_pos.xyz;
+	gl_Position = light_space_mat * world_pos;
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+in vec3 FragPos;
+
+uniform vec3 light_position;
+uniform float far_plane;
+
+void main()
+{
+	// Linear distance so model.glsl can compare against length(frag - light)
+	gl_FragDepth = length(FragPos - light_position) / far_plane;
+}
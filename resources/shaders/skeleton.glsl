@@ -0,0 +1,25 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 1) in vec3 a_color;
+
+out vec3 line_color;
+
+uniform mat4 projection;
+uniform mat4 view;
+
+void main()
+{
+	line_color = a_color;
+	gl_Position = projection * view * vec4(a_pos, 1.0);
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+out vec4 FragColor;
+
+in vec3 line_color;
+
+void main() {
+	FragColor = vec4(line_color, 1.0);
+}
@@ -3,6 +3,7 @@
 layout (location = 0) in vec3 aPos;
 
 out vec3 texCoords;
+out vec3 WorldDir;
 
 uniform mat4 projection;
 uniform mat4 view;
@@ -14,6 +15,7 @@ void main()
     gl_Position = vec4(pos.x, pos.y, pos.w, pos.w);
     // We want to flip the z axis due to the different coordinate systems (left hand vs right hand)
     texCoords = vec3(aPos.x, aPos.y, -aPos.z);
+    WorldDir = aPos;
 }    
 
 // FRAGMENT_SHADER
@@ -21,10 +23,76 @@ void main()
 out vec4 FragColor;
 
 in vec3 texCoords;
+in vec3 WorldDir;
 
 uniform samplerCube skybox;
+uniform vec3 cubemap_tint;
+
+// Analytic single scattering, see sky.rs which evaluates the same model for the fog colors
+uniform bool procedural_sky;
+uniform vec3 sun_direction;
+uniform float sun_intensity;
+uniform vec3 rayleigh;
+uniform float mie;
+uniform float mie_g;
+uniform vec3 ground_color;
+
+const float PI = 3.14159265359;
+const vec3 NIGHT_SKY = vec3(0.004, 0.006, 0.012);
+// Roughly the sun's angular radius
+const float SUN_DISK_COS = 0.99996;
+// Way past 1.0 so the disk blooms
+const float SUN_DISK_INTENSITY = 50.0;
+
+float air_mass(float cos_zenith) {
+    cos_zenith = clamp(cos_zenith, 0.0, 1.0);
+    float zenith_degrees = degrees(acos(cos_zenith));
+    return 1.0 / (cos_zenith + 0.50572 * pow(96.07995 - zenith_degrees, -1.6364));
+}
+
+float rayleigh_phase(float mu) {
+    return 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+}
+
+float mie_phase(float mu, float g) {
+    float g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * mu, 1.5));
+}
+
+vec3 sun_transmittance() {
+    vec3 total = rayleigh + vec3(mie);
+    return exp(-total * air_mass(sun_direction.y)) * smoothstep(-0.1, 0.02, sun_direction.y);
+}
+
+vec3 sky_radiance(vec3 dir) {
+    float mu = dot(dir, sun_direction);
+    vec3 total = rayleigh + vec3(mie);
+
+    vec3 scatter = (rayleigh * rayleigh_phase(mu) + vec3(mie * mie_phase(mu, mie_g))) / total;
+    vec3 in_scatter = 1.0 - exp(-total * air_mass(dir.y));
+
+    return sun_intensity * sun_transmittance() * scatter * in_scatter + NIGHT_SKY;
+}
 
 void main()
 {    
-    FragColor = texture(skybox, texCoords);
+    if (!procedural_sky) {
+        FragColor = texture(skybox, texCoords) * vec4(cubemap_tint, 1.0);
+        return;
+    }
+
+    vec3 dir = normalize(WorldDir);
+    vec3 color = sky_radiance(dir);
+
+    float sun_disk = smoothstep(SUN_DISK_COS, 1.0, dot(dir, sun_direction));
+    color += sun_disk * SUN_DISK_INTENSITY * sun_transmittance();
+
+    // Below the horizon the ground takes over, lit by the horizon above it
+    vec3 horizon_dir = vec3(dir.x, 0.0, dir.z);
+    horizon_dir = length(horizon_dir) > 0.0001 ? normalize(horizon_dir) : vec3(1.0, 0.0, 0.0);
+    vec3 horizon = sky_radiance(horizon_dir);
+    color = mix(color, horizon * ground_color, smoothstep(0.0, -0.05, dir.y));
+
+    // The scene target holds gamma encoded colors, see post_process.rs
+    FragColor = vec4(pow(color, vec3(1.0 / 2.2)), 1.0);
 }
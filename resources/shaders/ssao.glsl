@@ -0,0 +1,75 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 1) in vec2 a_tex_coords;
+
+out vec2 TexCoords;
+
+void main()
+{
+	TexCoords = a_tex_coords;
+	gl_Position = vec4(a_pos, 1.0);
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+// Filled by the prepass in ssao_prepass.glsl
+uniform sampler2D depth_map;
+uniform sampler2D normal_map;
+// Small tiled texture of random rotations around the normal, traded for noise the blur removes
+uniform sampler2D noise_map;
+
+// Must match SSAO_KERNEL_SIZE in ssao.rs
+const int KERNEL_SIZE = 32;
+uniform vec3 samples[KERNEL_SIZE];
+
+uniform mat4 projection;
+uniform mat4 inv_projection;
+uniform vec2 noise_scale;
+uniform float radius;
+uniform float bias;
+uniform float intensity;
+
+vec3 view_position(vec2 uv) {
+	float depth = texture(depth_map, uv).r;
+	vec4 view = inv_projection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+	return view.xyz / view.w;
+}
+
+void main()
+{
+	// Nothing drawn here, the sky is never occluded
+	if (texture(depth_map, TexCoords).r >= 1.0) {
+		FragColor = vec4(1.0);
+		return;
+	}
+
+	vec3 frag_pos = view_position(TexCoords);
+	vec3 normal = normalize(texture(normal_map, TexCoords).xyz);
+	vec3 random_vec = normalize(texture(noise_map, TexCoords * noise_scale).xyz);
+
+	// Gram-Schmidt the random vector into a tangent, the kernel is a hemisphere around +z
+	vec3 tangent = normalize(random_vec - normal * dot(random_vec, normal));
+	vec3 bitangent = cross(normal, tangent);
+	mat3 TBN = mat3(tangent, bitangent, normal);
+
+	float occlusion = 0.0;
+	for (int i = 0; i < KERNEL_SIZE; i++) {
+		vec3 sample_pos = frag_pos + TBN * samples[i] * radius;
+
+		vec4 offset = projection * vec4(sample_pos, 1.0);
+		offset.xy = (offset.xy / offset.w) * 0.5 + 0.5;
+
+		float sample_depth = view_position(offset.xy).z;
+		// Geometry far in front of the sample, like a silhouette edge, shouldn't darken it
+		float range_check = smoothstep(0.0, 1.0, radius / abs(frag_pos.z - sample_depth));
+		occlusion += (sample_depth >= sample_pos.z + bias ? 1.0 : 0.0) * range_check;
+	}
+
+	float ao = 1.0 - occlusion / float(KERNEL_SIZE);
+	FragColor = vec4(vec3(pow(ao, intensity)), 1.0);
+}
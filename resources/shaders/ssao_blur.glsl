@@ -0,0 +1,35 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 1) in vec2 a_tex_coords;
+
+out vec2 TexCoords;
+
+void main()
+{
+	TexCoords = a_tex_coords;
+	gl_Position = vec4(a_pos, 1.0);
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D ssao_input;
+
+// Box blur the size of the noise tile, which cancels its pattern out exactly
+const int NOISE_SIZE = 4;
+
+void main()
+{
+	vec2 texel_size = 1.0 / vec2(textureSize(ssao_input, 0));
+	float result = 0.0;
+	for (int x = -NOISE_SIZE / 2; x < NOISE_SIZE / 2; x++) {
+		for (int y = -NOISE_SIZE / 2; y < NOISE_SIZE / 2; y++) {
+			result += texture(ssao_input, TexCoords + vec2(x, y) * texel_size).r;
+		}
+	}
+	FragColor = vec4(vec3(result / float(NOISE_SIZE * NOISE_SIZE)), 1.0);
+}
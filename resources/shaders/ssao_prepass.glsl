@@ -0,0 +1,104 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 1) in vec3 a_normal;
+layout (location = 2) in vec2 a_tex_coords;
+layout (location = 3) in ivec4 bone_ids;
+layout (location = 4) in vec4 bone_weights;
+
+// View space, that is what ssao.glsl reconstructs positions in
+out vec3 ViewNormal;
+out vec2 TexCoords;
+
+uniform mat4 projection;
+uniform mat4 view;
+uniform mat4 model;
+uniform mat4 node_transform;
+uniform bool is_animated;
+
+// Instanced static models, see instancing.rs
+struct Instance {
+	mat4 model;
+	uvec4 flags;
+};
+layout (std430, binding = 7) readonly buffer InstanceBuffer {
+	Instance instances[];
+};
+uniform bool is_instanced;
+uniform int instance_offset;
+
+const int MAX_BONE_INFLUENCE = 4;
+const int MAX_BONES = 100;
+
+uniform mat4 bone_transforms[MAX_BONES];
+
+// Morph targets
+const int MAX_MORPH_TARGETS = 8;
+layout (std430, binding = 3) readonly buffer MorphDeltas {
+	// [target][vertex][position, normal]
+	vec4 morph_deltas[];
+};
+uniform int morph_target_count;
+uniform int morph_vertex_count;
+uniform float morph_weights[MAX_MORPH_TARGETS];
+
+void main()
+{
+	mat4 world = (is_instanced ? instances[instance_offset + gl_InstanceID].model : model) * node_transform;
+	vec3 morphed_pos = a_pos;
+	vec3 morphed_normal = a_normal;
+	for (int i = 0; i < morph_target_count; i++) {
+		int idx = (i * morph_vertex_count + gl_VertexID) * 2;
+		morphed_pos += morph_deltas[idx].xyz * morph_weights[i];
+		morphed_normal += morph_deltas[idx + 1].xyz * morph_weights[i];
+	}
+
+	vec4 position = vec4(morphed_pos, 1.0);
+	vec3 normal = morphed_normal;
+	if (is_animated) {
+		vec4 totalPosition = vec4(0.0f);
+		vec3 totalNormal = vec3(0.0f);
+		for(int i = 0 ; i < MAX_BONE_INFLUENCE; i++)
+		{
+			if(bone_ids[i] == -1) 
+				continue;
+			if(bone_ids[i] >=MAX_BONES) 
+			{
+				totalPosition = vec4(morphed_pos,1.0f);
+				totalNormal = morphed_normal;
+				break;
+			}
+			totalPosition += bone_transforms[bone_ids[i]] * vec4(morphed_pos,1.0f) * bone_weights[i];
+			totalNormal += transpose(inverse(mat3(bone_transforms[bone_ids[i]]))) * morphed_normal * bone_weights[i];
+		}
+		position = totalPosition;
+		normal = totalNormal;
+	}
+
+	mat4 view_model = view * world;
+	ViewNormal = transpose(inverse(mat3(view_model))) * normal;
+	TexCoords = a_tex_coords;
+	gl_Position = projection * view_model * position;
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+out vec4 FragNormal;
+
+in vec3 ViewNormal;
+in vec2 TexCoords;
+
+// Alpha tested batches (foliage cards), same cut out as model.glsl
+uniform bool alpha_test;
+uniform sampler2D diffuse;
+uniform vec4 base_color_factor;
+
+void main()
+{
+	if (alpha_test && texture(diffuse, TexCoords).a * base_color_factor.a < 0.1)
+		discard;
+
+	// Most meshes are drawn double sided, the back faces need to face the camera too
+	vec3 normal = normalize(ViewNormal);
+	FragNormal = vec4(gl_FrontFacing ? normal : -normal, 1.0);
+}
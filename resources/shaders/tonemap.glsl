@@ -0,0 +1,61 @@
+// VERTEX_SHADER
+#version 460 core
+layout (location = 0) in vec3 a_pos;
+layout (location = 1) in vec2 a_tex_coords;
+
+out vec2 TexCoords;
+
+void main()
+{
+	TexCoords = a_tex_coords;
+	gl_Position = vec4(a_pos, 1.0);
+}
+
+// FRAGMENT_SHADER
+#version 460 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+// Gamma encoded HDR scene, see post_process.rs
+uniform sampler2D scene;
+// Linear bloom from the mip chain
+uniform sampler2D bloom;
+uniform bool use_bloom;
+uniform float bloom_intensity;
+uniform float exposure;
+// Matches Tonemapper in post_process.rs
+uniform int tonemapper;
+
+const int TONEMAP_NONE = 0;
+const int TONEMAP_REINHARD = 1;
+const int TONEMAP_ACES = 2;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+	const float a = 2.51;
+	const float b = 0.03;
+	const float c = 2.43;
+	const float d = 0.59;
+	const float e = 0.14;
+	return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+void main()
+{
+	vec3 color = pow(max(texture(scene, TexCoords).rgb, vec3(0.0)), vec3(2.2));
+	if (use_bloom) {
+		color += texture(bloom, TexCoords).rgb * bloom_intensity;
+	}
+	color *= exposure;
+
+	if (tonemapper == TONEMAP_REINHARD) {
+		color = color / (color + vec3(1.0));
+	} else if (tonemapper == TONEMAP_ACES) {
+		color = aces(color);
+	} else {
+		color = clamp(color, 0.0, 1.0);
+	}
+
+	FragColor = vec4(pow(color, vec3(1.0 / 2.2)), 1.0);
+}
@@ -2,16 +2,18 @@
 use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
 use core::f32;
-use std::{collections::HashMap, ffi::c_void, mem::{self, offset_of}, path::Path, ptr, str::Lines};
+use std::{collections::HashMap, ffi::c_void, mem::{self, offset_of}, path::Path, ptr, str::{FromStr, Lines}};
 
-use crate::{enums_types::{AnimationType, TextureType}, gl_call, shaders::Shader, some_data::MAX_BONE_INFLUENCE, sound::sound_manager::{ContinuousSound, OneShot}};
+use crate::{animation::wise_cache::{self, CachedModel}, culling::Aabb, enums_types::{AnimationType, TextureType}, error::{LoadError, LoadResult}, gl_call, shaders::Shader, some_data::{MAX_BONE_INFLUENCE, MAX_MORPH_TARGETS, MORPH_DELTA_BINDING}, sound::sound_manager::{ContinuousSound, OneShot}};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 #[repr(C)]
 pub struct Vertex {
     pub position: Vec3,
     pub normal: Vec3,
     pub uv: Vec2,
+    // xyz along +u, w is the bitangent sign. See generate_tangents.
+    pub tangent: Vec4,
 
     pub bone_ids: [i32; MAX_BONE_INFLUENCE],
     pub bone_weights: [f32; MAX_BONE_INFLUENCE],
@@ -23,6 +25,7 @@ impl Vertex {
             position,
             normal,
             uv: Vec2::new(0.0, 0.0),
+            tangent: Vec4::X,
 
             bone_ids: [-1; MAX_BONE_INFLUENCE],
             bone_weights: [0.0; MAX_BONE_INFLUENCE],
@@ -38,15 +41,118 @@ pub struct Texture {
     pub path: String,
 }
 
+/// Per vertex position/normal offsets for a blend shape. Stored densely (one entry per vertex in
+/// Model.vertices) even though the file format only lists the vertices that actually move.
+#[derive(Debug, Clone)]
+pub struct MorphTarget {
+    pub name: String,
+    pub position_deltas: Vec<Vec3>,
+    pub normal_deltas: Vec<Vec3>,
+}
+
+/// Texture slots for one material. The slot index is also the texture unit it gets bound to.
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: String,
+    pub textures: [Option<Texture>; 9],
+    // How deep the displacement map reads in uv units, only used if there is one
+    pub parallax_scale: f32,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            textures: [None, None, None, None, None, None, None, None, None],
+            parallax_scale: 0.05,
+        }
+    }
+}
+
+/// Texture slot (and texture unit) for each TextureType. Slot 0 is left for the shadow map.
+pub fn texture_slot(texture_type: &TextureType) -> usize {
+    match texture_type {
+        TextureType::Diffuse => 1,
+        TextureType::Specular => 2,
+        TextureType::Emissive => 3,
+        TextureType::NormalMap => 4,
+        TextureType::Roughness => 5,
+        TextureType::Metalness => 6,
+        TextureType::Displacement => 7,
+        TextureType::Opacity => 8,
+    }
+}
+
+pub fn texture_type_for_slot(slot: usize) -> Option<TextureType> {
+    match slot {
+        1 => Some(TextureType::Diffuse),
+        2 => Some(TextureType::Specular),
+        3 => Some(TextureType::Emissive),
+        4 => Some(TextureType::NormalMap),
+        5 => Some(TextureType::Roughness),
+        6 => Some(TextureType::Metalness),
+        7 => Some(TextureType::Displacement),
+        8 => Some(TextureType::Opacity),
+        _ => None,
+    }
+}
+
+thread_local! {
+    // 1x1 stand ins for empty material slots so every sampler in model.glsl reads something neutral
+    // instead of whatever was last bound to its unit. GL objects belong to the context's thread.
+    static DEFAULT_TEXTURES: [u32; 9] = create_default_textures();
+}
+
+fn create_default_textures() -> [u32; 9] {
+    let mut ids = [0; 9];
+    for (slot, id) in ids.iter_mut().enumerate() {
+        let Some(texture_type) = texture_type_for_slot(slot) else {
+            continue;
+        };
+
+        let color = match texture_type {
+            // Roughness and metalness get scaled by the per model factors so white lets those through
+            TextureType::Diffuse | TextureType::Roughness | TextureType::Metalness | TextureType::Opacity => [255, 255, 255, 255],
+            // Straight up in tangent space
+            TextureType::NormalMap => [128, 128, 255, 255],
+            TextureType::Specular | TextureType::Emissive | TextureType::Displacement => [0, 0, 0, 255],
+        };
+
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba(color)));
+        *id = create_gl_texture(&img);
+    }
+
+    ids
+}
+
+/// A range of Model.indices drawn with one material. Every submesh indexes into the same vertex
+/// buffer, so bone ids and morph deltas stay model wide no matter how the model is split up.
+#[derive(Debug, Clone)]
+pub struct SubMesh {
+    pub name: String,
+    pub index_offset: u32,
+    pub index_count: u32,
+    pub material: usize,
+    // Placement from the node hierarchy in the source file. Only used for static draws, skinned
+    // vertices get placed by their bones.
+    pub transform: Mat4,
+}
+
 #[derive(Debug, Clone)]
 pub struct Model {
     pub vao: u32,
     pub vbo: u32,
     pub ebo: u32,
+    pub morph_ssbo: u32,
 
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
-    pub textures: [Option<Texture>; 9],
+    pub materials: Vec<Material>,
+    // Empty until setup_opengl, which falls back to a single submesh over all indices with material 0
+    pub submeshes: Vec<SubMesh>,
+    pub morph_targets: Vec<MorphTarget>,
+    // Model space bounds for culling, filled in by setup_opengl
+    pub bounds: Aabb,
 
     pub directory: String,
     pub full_path: String,
@@ -58,10 +164,14 @@ impl Model {
             vao: 0,
             vbo: 0,
             ebo: 0,
+            morph_ssbo: 0,
 
             vertices: vec![],
             indices: vec![],
-            textures: [None, None, None, None, None, None, None, None, None],
+            materials: vec![Material::new("default")],
+            submeshes: vec![],
+            morph_targets: vec![],
+            bounds: Aabb::EMPTY,
 
             directory: String::new(),
             full_path: String::new(),
@@ -69,6 +179,17 @@ impl Model {
     }
 
     pub fn setup_opengl(&mut self) {
+        if self.submeshes.is_empty() {
+            self.submeshes.push(SubMesh {
+                name: "default".to_string(),
+                index_offset: 0,
+                index_count: self.indices.len() as u32,
+                material: 0,
+                transform: Mat4::IDENTITY,
+            });
+        }
+        self.bounds = self.compute_bounds();
+
         unsafe {
             gl_call!(gl::GenVertexArrays(1, &mut self.vao));
             gl_call!(gl::GenBuffers(1, &mut self.vbo));
@@ -141,63 +262,261 @@ impl Model {
                 offset_of!(Vertex, bone_weights) as *const _
             ));
 
+            gl_call!(gl::EnableVertexAttribArray(5));
+            gl_call!(gl::VertexAttribPointer(
+                5,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                mem::size_of::<Vertex>() as i32,
+                offset_of!(Vertex, tangent) as *const _
+            ));
+
             gl::BindVertexArray(0);
         }
+
+        self.setup_morph_targets();
+    }
+
+    /// Bind pose bounds with each submesh's node transform applied. Skinned models get padded since
+    /// animation can swing limbs outside the bind pose, and morph targets by their largest offset.
+    fn compute_bounds(&self) -> Aabb {
+        let skinned = self.vertices.iter().any(|v| v.bone_ids[0] >= 0);
+        let mut bounds = Aabb::EMPTY;
+
+        for submesh in self.submeshes.iter() {
+            let start = submesh.index_offset as usize;
+            let end = (start + submesh.index_count as usize).min(self.indices.len());
+            for index in self.indices[start..end].iter() {
+                let Some(vertex) = self.vertices.get(*index as usize) else {
+                    continue;
+                };
+                let position = if skinned { vertex.position } else { submesh.transform.transform_point3(vertex.position) };
+                bounds.grow(position);
+            }
+        }
+
+        if bounds.is_empty() {
+            return bounds;
+        }
+
+        let morph_reach = self.morph_targets
+            .iter()
+            .flat_map(|t| t.position_deltas.iter())
+            .map(|d| d.length())
+            .fold(0.0, f32::max);
+        bounds = bounds.expanded(Vec3::splat(morph_reach));
+
+        if skinned {
+            bounds = bounds.expanded(Vec3::splat(bounds.extents().max_element() * 0.5));
+        }
+
+        bounds
+    }
+
+    // Deltas go in an SSBO laid out as [target][vertex][position, normal] so the vertex shader
+    // can look them up with gl_VertexID.
+    fn setup_morph_targets(&mut self) {
+        if self.morph_targets.is_empty() {
+            return;
+        }
+
+        if self.morph_targets.len() > MAX_MORPH_TARGETS {
+            println!(
+                "WARNING: {} has {} morph targets, only the first {} will be used",
+                self.full_path,
+                self.morph_targets.len(),
+                MAX_MORPH_TARGETS,
+            );
+            self.morph_targets.truncate(MAX_MORPH_TARGETS);
+        }
+
+        let mut data: Vec<Vec4> = Vec::with_capacity(self.morph_targets.len() * self.vertices.len() * 2);
+        for target in self.morph_targets.iter() {
+            for (pos, norm) in target.position_deltas.iter().zip(target.normal_deltas.iter()) {
+                data.push(pos.extend(0.0));
+                data.push(norm.extend(0.0));
+            }
+        }
+
+        unsafe {
+            gl_call!(gl::GenBuffers(1, &mut self.morph_ssbo));
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.morph_ssbo));
+            gl_call!(gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                (mem::size_of::<Vec4>() * data.len()) as isize,
+                data.as_ptr().cast(),
+                gl::STATIC_DRAW,
+            ));
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0));
+        }
+    }
+
+    /// Sets the morph uniforms and binds the delta buffer. Called by draw(), but the depth pass
+    /// binds the vao itself so it needs to call this directly.
+    pub fn bind_morph_targets(&self, shader: &Shader, weights: &HashMap<String, f32>) {
+        shader.set_int("morph_target_count", self.morph_targets.len() as u32);
+
+        if self.morph_targets.is_empty() {
+            return;
+        }
+
+        let mut final_weights = [0.0; MAX_MORPH_TARGETS];
+        for (i, target) in self.morph_targets.iter().enumerate() {
+            final_weights[i] = weights.get(&target.name).copied().unwrap_or(0.0);
+        }
+
+        shader.set_int("morph_vertex_count", self.vertices.len() as u32);
+        shader.set_float_array("morph_weights", &final_weights);
+
+        unsafe {
+            gl_call!(gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, MORPH_DELTA_BINDING, self.morph_ssbo));
+        }
     }
 
     pub fn draw(&self, shader: &mut Shader) {
+        self.draw_with_morphs(shader, &HashMap::new());
+    }
+
+    /// Frees the buffers and textures this model owns. Only the asset store should call this, once
+    /// nothing holds a handle to the mesh anymore.
+    pub fn release_gpu(&self) {
+        unsafe {
+            gl_call!(gl::DeleteVertexArrays(1, &self.vao));
+            gl_call!(gl::DeleteBuffers(1, &self.vbo));
+            gl_call!(gl::DeleteBuffers(1, &self.ebo));
+            if self.morph_ssbo != 0 {
+                gl_call!(gl::DeleteBuffers(1, &self.morph_ssbo));
+            }
+
+            for material in self.materials.iter() {
+                for texture in material.textures.iter().flatten() {
+                    gl_call!(gl::DeleteTextures(1, &texture.id));
+                }
+            }
+        }
+    }
+
+    /// One draw call per submesh, each with its own material bound.
+    pub fn draw_with_morphs(&self, shader: &mut Shader, morph_weights: &HashMap<String, f32>) {
         shader.activate();
-        if self.textures[8].is_some() {
-            shader.set_bool("has_opacity_texture", true);
+        self.bind_morph_targets(shader, morph_weights);
+        self.draw_materials(shader, 1);
+    }
+
+    /// Same as draw but every submesh is drawn count times. The shader reads per instance data
+    /// from the InstanceBuffer, morph targets are not supported.
+    pub fn draw_instanced(&self, shader: &mut Shader, count: u32) {
+        shader.activate();
+        shader.set_int("morph_target_count", 0);
+        self.draw_materials(shader, count);
+    }
+
+    fn draw_materials(&self, shader: &Shader, instances: u32) {
+        unsafe {
+            gl_call!(gl::BindVertexArray(self.vao));
         }
-        for (i, texture) in self.textures.iter().enumerate() {
-            if let Some(texture) = texture {
 
-                let gl_tex_key = i;
+        for submesh in self.submeshes.iter() {
+            let material = &self.materials[submesh.material];
 
-                unsafe { 
-                    gl_call!(gl::ActiveTexture(gl::TEXTURE0 + gl_tex_key as u32));
-                }
+            shader.set_bool("has_opacity_texture", material.textures[8].is_some());
+            shader.set_bool("has_normal_map", material.textures[4].is_some());
+            shader.set_bool("has_displacement_map", material.textures[7].is_some());
+            shader.set_float("parallax_scale", material.parallax_scale);
+
+            // Every slot gets something bound, empty ones get the defaults
+            DEFAULT_TEXTURES.with(|defaults| {
+                for (slot, texture) in material.textures.iter().enumerate().skip(1) {
+                    let Some(texture_type) = texture_type_for_slot(slot) else {
+                        continue;
+                    };
+                    let id = texture.as_ref().map(|t| t.id).unwrap_or(defaults[slot]);
+
+                    unsafe { 
+                        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + slot as u32));
+                    }
 
-                let final_str = format!("material.{}", texture._type);
-                shader.set_int(final_str.as_str(), gl_tex_key as u32);
-                unsafe {
-                    gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture.id));
+                    let final_str = format!("material.{}", texture_type);
+                    shader.set_int(final_str.as_str(), slot as u32);
+                    unsafe {
+                        gl_call!(gl::BindTexture(gl::TEXTURE_2D, id));
+                    }
                 }
+            });
+
+            shader.set_mat4("node_transform", submesh.transform);
+            self.draw_submesh(submesh, instances);
+        }
+
+        unsafe {
+            shader.set_bool("has_opacity_texture", false);
+            gl_call!(gl::BindVertexArray(0));
+
+            for slot in 1..9 {
+                gl_call!(gl::ActiveTexture(gl::TEXTURE0 + slot));
+                gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
             }
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
         }
+    }
+
+    /// Geometry only, for passes that don't care about materials (shadows). Morph targets still
+    /// have to be bound by the caller.
+    pub fn draw_depth(&self, shader: &Shader) {
+        self.draw_depth_instanced(shader, 1);
+    }
 
+    pub fn draw_depth_instanced(&self, shader: &Shader, count: u32) {
         unsafe {
             gl_call!(gl::BindVertexArray(self.vao));
-            gl_call!(gl::DrawElements(
-                gl::TRIANGLES, 
-                self.indices.len() as i32, 
-                gl::UNSIGNED_INT, 
-                ptr::null(), 
-            ));
+        }
 
-            shader.set_bool("has_opacity_texture", false);
+        for submesh in self.submeshes.iter() {
+            shader.set_mat4("node_transform", submesh.transform);
+            self.draw_submesh(submesh, count);
+        }
+
+        unsafe {
             gl_call!(gl::BindVertexArray(0));
+        }
+    }
 
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    /// draw_depth_instanced for alpha tested meshes, binds each submesh's diffuse map on unit 1 as
+    /// "diffuse" so the depth shader can discard the cut out parts.
+    pub fn draw_depth_alpha_tested(&self, shader: &Shader, count: u32) {
+        unsafe {
+            gl_call!(gl::BindVertexArray(self.vao));
             gl_call!(gl::ActiveTexture(gl::TEXTURE1));
+        }
+        shader.set_int("diffuse", 1);
+
+        for submesh in self.submeshes.iter() {
+            let texture = self.materials[submesh.material].textures[1].as_ref();
+            let id = texture.map(|t| t.id).unwrap_or_else(|| DEFAULT_TEXTURES.with(|defaults| defaults[1]));
+            unsafe {
+                gl_call!(gl::BindTexture(gl::TEXTURE_2D, id));
+            }
+            shader.set_mat4("node_transform", submesh.transform);
+            self.draw_submesh(submesh, count);
+        }
+
+        unsafe {
             gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE2));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE3));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE4));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE5));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE6));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE7));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE8));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            gl_call!(gl::ActiveTexture(gl::TEXTURE9));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+            gl_call!(gl::BindVertexArray(0));
+        }
+    }
+
+    fn draw_submesh(&self, submesh: &SubMesh, instances: u32) {
+        unsafe {
+            gl_call!(gl::DrawElementsInstanced(
+                gl::TRIANGLES, 
+                submesh.index_count as i32, 
+                gl::UNSIGNED_INT, 
+                (submesh.index_offset as usize * mem::size_of::<u32>()) as *const c_void, 
+                instances as i32,
+            ));
         }
     }
 }
@@ -205,11 +524,11 @@ impl Model {
 #[derive(Debug, Clone)]
 pub struct Bone {
     // id will be the position in the final bone array as well.
-    id: u32,
-    parent_index: Option<u32>,
-    name: String,
-    offset: Mat4,
-    children: Vec<Bone>,
+    pub id: u32,
+    pub parent_index: Option<u32>,
+    pub name: String,
+    pub offset: Mat4,
+    pub children: Vec<Bone>,
 }
 
 #[derive(Debug, Clone)]
@@ -220,13 +539,13 @@ pub struct BoneJoinInfo {
 
 #[derive(Debug, Clone)]
 pub struct BoneTransformTrack {
-    position_timestamps: Vec<f32>,
-    rotation_timestamps: Vec<f32>,
-    scale_timestamps: Vec<f32>,
+    pub position_timestamps: Vec<f32>,
+    pub rotation_timestamps: Vec<f32>,
+    pub scale_timestamps: Vec<f32>,
 
-    positions: Vec<Vec3>,
-    rotations: Vec<Quat>,
-    scales: Vec<Vec3>,
+    pub positions: Vec<Vec3>,
+    pub rotations: Vec<Quat>,
+    pub scales: Vec<Vec3>,
 }
 
 impl BoneTransformTrack {
@@ -243,13 +562,51 @@ impl BoneTransformTrack {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct MorphWeightTrack {
+    pub timestamps: Vec<f32>,
+    pub weights: Vec<f32>,
+}
+
+impl MorphWeightTrack {
+    pub fn default() -> Self {
+        Self {
+            timestamps: vec![],
+            weights: vec![],
+        }
+    }
+
+    pub fn sample(&self, time: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.timestamps.first(), self.timestamps.last()) else {
+            return 0.0;
+        };
+
+        if time <= *first {
+            return self.weights[0];
+        }
+        if time >= *last {
+            return self.weights[self.weights.len() - 1];
+        }
+
+        let (segment, fraction) = get_time_fraction(&self.timestamps, time);
+        let segment = segment as usize;
+
+        self.weights[segment - 1] + (self.weights[segment] - self.weights[segment - 1]) * fraction
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Animator {
     pub current_animation: AnimationType,
     pub next_animation: AnimationType,
     pub animations: HashMap<AnimationType, Animation>,
     pub blend_factor: f32,
     pub blend_time: f32,
+
+    // Playback controls, mostly driven from the imgui timeline inspector
+    pub paused: bool,
+    pub playback_speed: f32,
+    pub pending_step: f32,
 }
 
 impl Animator {
@@ -260,6 +617,17 @@ impl Animator {
             animations: HashMap::new(),
             blend_factor: 0.0,
             blend_time: 0.2,
+
+            paused: false,
+            playback_speed: 1.0,
+            pending_step: 0.0,
+        }
+    }
+
+    /// Advances a paused animator by a single frame of the current clip on the next update.
+    pub fn step(&mut self) {
+        if let Some(anim) = self.animations.get(&self.current_animation) {
+            self.pending_step += anim.frame_time();
         }
     }
 
@@ -272,6 +640,13 @@ impl Animator {
     }
 
     pub fn update(&mut self, skellington: &mut Bone, dt: f32) {
+        // Still run the pose calculation with a zero dt while paused so that scrubbing
+        // the clip time shows up immediately.
+        let dt = if self.paused {
+            std::mem::take(&mut self.pending_step)
+        } else {
+            dt * self.playback_speed
+        };
 
         // Check death conditioin:
         if self.current_animation == AnimationType::Death {
@@ -319,10 +694,17 @@ impl Animator {
 #[derive(Debug, Clone)]
 pub struct Animation {
     pub duration: f32,
-    ticks_per_second: f32,
+    pub ticks_per_second: f32,
     pub model_animation_join: Vec<BoneJoinInfo>,
     pub bone_transforms: HashMap<String, BoneTransformTrack>,
+    // Keyed by morph target name, matched against Model.morph_targets at draw time
+    pub morph_tracks: HashMap<String, MorphWeightTrack>,
     pub current_pose: Vec<Mat4>,
+    pub current_morph_weights: HashMap<String, f32>,
+    // Bone space transforms from the last pose calculation, kept around for debug drawing.
+    // Both are indexed by bone id just like current_pose.
+    pub current_locals: Vec<Mat4>,
+    pub current_globals: Vec<Mat4>,
 
     pub current_segment: u32,
     pub one_shots: Vec<OneShot>,
@@ -340,7 +722,11 @@ impl Animation {
             ticks_per_second: 0.0,
             model_animation_join: vec![],
             bone_transforms: HashMap::new(),
+            morph_tracks: HashMap::new(),
             current_pose: vec![],
+            current_morph_weights: HashMap::new(),
+            current_locals: vec![],
+            current_globals: vec![],
 
             current_segment: 0,
             one_shots: vec![],
@@ -351,6 +737,32 @@ impl Animation {
         }
     }
 
+    pub fn fps(&self) -> f32 {
+        self.ticks_per_second
+    }
+
+    pub fn frame_time(&self) -> f32 {
+        if self.ticks_per_second > 0.0 {
+            1.0 / self.ticks_per_second
+        } else {
+            1.0 / 30.0
+        }
+    }
+
+    /// The time at which a segment (as used by OneShot) starts. Segment n covers the time between
+    /// keyframe n - 1 and keyframe n, see get_time_fraction. Read from the root bone's track so
+    /// the answer doesn't depend on HashMap order when tracks have different keyframes.
+    pub fn segment_start_time(&self, segment: u32) -> Option<f32> {
+        let root = self.model_animation_join.first()?;
+        let track = self.bone_transforms.get(&root.name)?;
+
+        if segment == 0 {
+            return Some(0.0);
+        }
+
+        track.position_timestamps.get(segment as usize - 1).copied()
+    }
+
     pub fn calculate_pose(
         &mut self,
         skeleton: &mut Bone,
@@ -362,6 +774,9 @@ impl Animation {
         let local_transform = Mat4::from_scale_rotation_translation(local_scale, local_rot, local_position);
         let global_transform = parent_transform * local_transform;
 
+        self.current_locals[skeleton.id as usize] = local_transform;
+        self.current_globals[skeleton.id as usize] = global_transform;
+
         self.current_pose[skeleton.id as usize] =
             match 0 {
                 0 => global_inverse_transform * global_transform * skeleton.offset,
@@ -403,6 +818,9 @@ impl Animation {
         let local_transform = Mat4::from_scale_rotation_translation(final_scale, final_rot, final_pos);
         let global_transform = parent_transform * local_transform;
 
+        self.current_locals[skeleton.id as usize] = local_transform;
+        self.current_globals[skeleton.id as usize] = global_transform;
+
         self.current_pose[skeleton.id as usize] =
             match 0 {
                 0 => global_inverse_transform * global_transform * skeleton.offset,
@@ -504,6 +922,7 @@ impl Animation {
                 other_animation,
                 blend_factor,
             );
+            self.calculate_morph_weights_blended(other_animation, blend_factor);
 
             other_animation.current_time += dt;
             if other_animation.current_time > other_animation.duration {
@@ -515,6 +934,36 @@ impl Animation {
                 Mat4::IDENTITY,
                 Mat4::IDENTITY, 
             );
+            self.calculate_morph_weights();
+        }
+    }
+
+    pub fn calculate_morph_weights(&mut self) {
+        let delta = self.current_time % self.duration;
+
+        // Targets from a previous blend or clip would otherwise stay stuck at their last weight
+        self.current_morph_weights.clear();
+        for (name, track) in self.morph_tracks.iter() {
+            self.current_morph_weights.insert(name.clone(), track.sample(delta));
+        }
+    }
+
+    // Targets that only one of the two clips animates blend towards/away from zero.
+    pub fn calculate_morph_weights_blended(&mut self, other_animation: &Animation, blend_factor: f32) {
+        let delta1 = self.current_time % self.duration;
+        let delta2 = other_animation.current_time % other_animation.duration;
+
+        let names: Vec<String> = self.morph_tracks.keys()
+            .chain(other_animation.morph_tracks.keys())
+            .cloned()
+            .collect();
+
+        self.current_morph_weights.clear();
+        for name in names {
+            let w1 = self.morph_tracks.get(&name).map(|t| t.sample(delta1)).unwrap_or(0.0);
+            let w2 = other_animation.morph_tracks.get(&name).map(|t| t.sample(delta2)).unwrap_or(0.0);
+
+            self.current_morph_weights.insert(name, w1 + (w2 - w1) * blend_factor);
         }
     }
 }
@@ -537,9 +986,20 @@ pub fn get_time_fraction(times: &[f32], dt: f32) -> (u32, f32) {
     (segment as u32, frac)
 }
 
-pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
-    let data = std::fs::read_to_string(file_path).unwrap();
-    let mut lines = data.lines();
+pub fn import_bone_data(file_path: &str) -> LoadResult<(Bone, Animator, Animation)> {
+    if let Some(cached) = wise_cache::load_bones(file_path) {
+        return Ok(cached);
+    }
+
+    let result = parse_bone_data(file_path)?;
+    wise_cache::save_bones(file_path, &result.0, &result.1);
+
+    Ok(result)
+}
+
+pub(crate) fn parse_bone_data(file_path: &str) -> LoadResult<(Bone, Animator, Animation)> {
+    let data = std::fs::read_to_string(file_path).map_err(|e| LoadError::io(file_path, e))?;
+    let mut lines = WiseLines::new(file_path, &data);
 
     let mut bones_no_children = Vec::new();
     let mut bone_idx = 0;
@@ -561,19 +1021,21 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
                 // name = "DefaultAnimation".to_string();
             }
             "BONECOUNT:" => {
-                bone_count = parts[1].parse().unwrap();
+                bone_count = lines.parse(parts.get(1), "BONECOUNT")?;
             }
             "BONE_NAME:" => {
-                let name = parts[1].to_string();
-                let parsed_parent: i32 = lines.next().unwrap().split_whitespace().collect::<Vec<&str>>()[1].parse().unwrap();
+                let name = lines.part(parts.get(1), "BONE_NAME")?.to_string();
+                let parent_line = lines.expect_line("PARENT_INDEX")?;
+                let parsed_parent: i32 = lines.parse(parent_line.split_whitespace().nth(1).as_ref(), "PARENT_INDEX")?;
 
                 let parent_index = match parsed_parent {
                     -1 => None,
+                    p if p < 0 => return Err(lines.error("PARENT_INDEX", format!("bad parent index {}", p))),
                     _ => Some(parsed_parent as u32),
                 };
 
-                lines.next();
-                let offset = parse_bone_offset(&mut lines);
+                lines.expect_line("OFFSET_MATRIX")?;
+                let offset = lines.mat4("OFFSET_MATRIX")?;
 
                 bones_no_children.push(Bone {
                     id: bone_idx,
@@ -589,11 +1051,21 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
         }
     }
 
+    if bone_count as usize != bones_no_children.len() {
+        return Err(LoadError::parse(file_path, None, Some("BONECOUNT"), format!("says {} bones but the file has {}", bone_count, bones_no_children.len())));
+    }
+    if let Some(b) = bones_no_children.iter().find(|b| b.parent_index.is_some_and(|p| p >= bone_count)) {
+        return Err(LoadError::parse(file_path, None, Some("PARENT_INDEX"), format!("bone {} has a parent index past the bone count", b.name)));
+    }
+    if !bones_no_children.iter().any(|b| b.parent_index.is_none()) {
+        return Err(LoadError::parse(file_path, None, Some("PARENT_INDEX"), "no root bone (parent index -1)"));
+    }
+
     let bone = build_bone_hierarchy_top_down(bones_no_children.clone());
     // =============================================================
     // Get Animation Data
     // ============================================================
-    lines = data.lines();
+    let mut lines = WiseLines::new(file_path, &data);
     let mut animation = Animation::default();
     let mut current_anim_str = "";
 
@@ -609,6 +1081,8 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
         );
 
         animation.current_pose.push(b.offset);
+        animation.current_locals.push(Mat4::IDENTITY);
+        animation.current_globals.push(Mat4::IDENTITY);
         assert!(model_animation_join[b.id as usize].name == b.name);
         assert!(model_animation_join.len() == animation.current_pose.len());
         
@@ -617,6 +1091,7 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
 
     let mut animator = Animator::new();
     let mut ticks_per_second = 0.0;
+    let mut current_anim = None;
 
     while let Some(line) = lines.next() {
         let parts: Vec<&str> = line.split_whitespace().collect();
@@ -627,7 +1102,7 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
 
         match parts[0] {
             "ANIMATION_NAME:" => {
-                if !current_anim_str.is_empty() {
+                if let Some(anim_type) = current_anim.take() {
                     // Save the previous animation before creating a new one
                     animation.model_animation_join = model_animation_join.clone();
                     animation.ticks_per_second = ticks_per_second;
@@ -635,43 +1110,62 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
                         animation.looping = false;
                     }
 
-                    animator.animations.insert(AnimationType::from_str(current_anim_str).unwrap(), animation.clone());
+                    animator.animations.insert(anim_type, animation.clone());
                 }
 
                 animation = Animation::default();
-                current_anim_str = parts[1];
-
-                dbg!(&current_anim_str);
+                current_anim_str = lines.part(parts.get(1), "ANIMATION_NAME")?;
+                current_anim = Some(
+                    AnimationType::from_str(current_anim_str)
+                        .ok_or_else(|| lines.error("ANIMATION_NAME", format!("unknown animation {}", current_anim_str)))?
+                );
 
                 for b in &bones_no_children {
                     animation.current_pose.push(b.offset);
+                    animation.current_locals.push(Mat4::IDENTITY);
+                    animation.current_globals.push(Mat4::IDENTITY);
                 }
             }
             "DURATION:" => {
-                animation.duration = parts[1].parse().unwrap()
+                animation.duration = lines.parse(parts.get(1), "DURATION")?
+            }
+            // MORPH_KEY: <target name> <timestamp> <weight>
+            // Unlike the bone keyframes these are not offset by one, so nothing gets dropped below.
+            "MORPH_KEY:" => {
+                let name = lines.part(parts.get(1), "MORPH_KEY")?;
+                let timestamp = lines.parse(parts.get(2), "MORPH_KEY")?;
+                let weight = lines.parse(parts.get(3), "MORPH_KEY")?;
+
+                let track = animation
+                    .morph_tracks
+                    .entry(name.to_string())
+                    .or_insert_with(MorphWeightTrack::default);
+
+                track.timestamps.push(timestamp);
+                track.weights.push(weight);
             }
             "FPS:" => {
-                ticks_per_second = parts[1].parse().unwrap()
+                ticks_per_second = lines.parse(parts.get(1), "FPS")?
             }
             "TIMESTAMP:" => {
-                let time_stamp = parts[1].parse().unwrap();
+                let time_stamp = lines.parse(parts.get(1), "TIMESTAMP")?;
 
                 // let mut skipped_bones = HashSet::new(); 
 
                 for i in 0..bone_count {
                     let bone_name = model_animation_join[i as usize].name.clone();
 
+                    let position = lines.vec3("KEY_POSITION")?;
+                    let rotation = lines.quat("KEY_ROTATION")?;
+                    let scale = lines.vec3("KEY_SCALE")?;
+
+                    lines.next();
+
                     let track = animation
                         .bone_transforms
                         .entry(bone_name.clone())
                         .or_insert_with(BoneTransformTrack::default);
 
-                    let position = parse_vec3(lines.next().unwrap());
-                    let rotation = parse_quat(lines.next().unwrap());
-                    let scale = parse_vec3(lines.next().unwrap());
-
-                    lines.next();
-
                     //   if !skipped_bones.contains(&bone_name) {
                     //       skipped_bones.insert(bone_name);
                     //       continue;
@@ -693,15 +1187,24 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
         }
     }
 
+    let Some(anim_type) = current_anim else {
+        return Err(LoadError::parse(file_path, None, Some("ANIMATION_NAME"), "file has no animations"));
+    };
+
     animation.model_animation_join = model_animation_join.clone();
     animation.ticks_per_second = ticks_per_second;
 
-    animator.set_current_animation(AnimationType::from_str(current_anim_str).unwrap());
-    animator.set_next_animation(AnimationType::from_str(current_anim_str).unwrap());
-    animator.animations.insert(AnimationType::from_str(current_anim_str).unwrap(), animation.clone());
+    animator.set_current_animation(anim_type.clone());
+    animator.set_next_animation(anim_type.clone());
+    animator.animations.insert(anim_type.clone(), animation);
+
+    for (anim_type, animation) in animator.animations.iter_mut() {
+        for (bone_name, track) in animation.bone_transforms.iter_mut() {
+            // Blender starts at frame 1, so every track needs the throwaway key plus at least one real one
+            if track.positions.len() < 2 {
+                return Err(LoadError::parse(file_path, None, Some("TIMESTAMP"), format!("{} has fewer than 2 keys for bone {}", anim_type, bone_name)));
+            }
 
-    for (_, animation) in animator.animations.iter_mut() {
-        for (_, track) in animation.bone_transforms.iter_mut() {
             track.positions.remove(0);
             track.position_timestamps.remove(0);
             track.rotations.remove(0);
@@ -711,21 +1214,44 @@ pub fn import_bone_data(file_path: &str) -> (Bone, Animator, Animation) {
         }
     }
 
-    (bone, animator, animation)
+    // Taken after the throwaway keys are gone, which is also what the cache rebuilds it from
+    let animation = animator.animations[&anim_type].clone();
+
+    Ok((bone, animator, animation))
 }
 
-pub fn import_model_data(file_path: &str, animation: &Animation) -> Model {
-    let data = std::fs::read_to_string(file_path).unwrap();
-    let mut lines = data.lines();
+pub fn import_model_data(file_path: &str, animation: &Animation) -> LoadResult<Model> {
+    let directory = model_directory(file_path);
+
+    let cached = match wise_cache::load_model(file_path, animation) {
+        Some(cached) => cached,
+        None => {
+            let parsed = parse_model_data(file_path, animation)?;
+            wise_cache::save_model(file_path, &parsed, animation);
+            parsed
+        }
+    };
+
+    Ok(cached.into_model(file_path, &directory))
+}
 
-    let mut model = Model::new();
+/// CPU side of the text loader. The cache stores exactly this, so a cache hit builds the same Model
+/// through CachedModel::into_model. Textures only get loaded there, a broken file doesn't leak them.
+pub(crate) fn parse_model_data(file_path: &str, animation: &Animation) -> LoadResult<CachedModel> {
+    let data = std::fs::read_to_string(file_path).map_err(|e| LoadError::io(file_path, e))?;
+    let mut lines = WiseLines::new(file_path, &data);
 
-    let directory = Path::new(file_path).parent().unwrap().to_str().unwrap();
-    println!("Directory of Model is: {}", &directory);
+    println!("Directory of Model is: {}", model_directory(file_path));
     println!("=============================================================");
 
-    model.directory = directory.to_string();
-    model.full_path = file_path.to_string();
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    let mut morph_targets = vec![];
+    // Each one is (material index, path, slot).
+    let mut textures = vec![];
+    let mut materials: Vec<Material> = vec![];
+    let mut submeshes: Vec<SubMesh> = vec![];
+    let mut pending_submesh: Option<SubMesh> = None;
 
     while let Some(line) = lines.next() {
         let parts: Vec<&str> = line.split_whitespace().collect();
@@ -737,24 +1263,29 @@ pub fn import_model_data(file_path: &str, animation: &Animation) -> Model {
         match parts[0] {
             "MEME" => {}
             "VERT:" => {
-                let position = parse_vec3(lines.next().unwrap());
-                let normal = parse_vec3(lines.next().unwrap());
-                let uv = parse_vec2(lines.next().unwrap());
+                let position = lines.vec3("VERT_POSITION")?;
+                let normal = lines.vec3("VERT_NORMAL")?;
+                let uv = lines.vec2("VERT_UV")?;
 
                 let mut vertex = Vertex {
                     position,
                     normal,
                     uv,
+                    tangent: Vec4::X,
                     bone_ids: [-1; MAX_BONE_INFLUENCE],
                     bone_weights: [0.0; MAX_BONE_INFLUENCE],
                 };
 
-                let weight_parts: Vec<&str> = lines.next().unwrap().split_whitespace().collect();
+                let weight_parts: Vec<&str> = lines.expect_line("WEIGHTS")?.split_whitespace().collect();
+
+                if weight_parts.first() != Some(&"None") {
+                    if weight_parts.len() > MAX_BONE_INFLUENCE * 2 {
+                        return Err(lines.error("WEIGHTS", format!("more than {} bone influences", MAX_BONE_INFLUENCE)));
+                    }
 
-                if !weight_parts.first().unwrap().eq(&"None") {
                     for (i, pair) in weight_parts.chunks(2).enumerate() {
                         let bone_name = pair[0];
-                        let weight: f32 = pair[1].parse().unwrap_or(0.0);
+                        let weight: f32 = pair.get(1).and_then(|w| w.parse().ok()).unwrap_or(0.0);
 
                         let mut bone_id: i32 = -1;
 
@@ -776,191 +1307,391 @@ pub fn import_model_data(file_path: &str, animation: &Animation) -> Model {
                     }
                 }
 
-                model.vertices.push(vertex);
+                vertices.push(vertex);
+            }
+            // MORPH_TARGET: <name>
+            // DELTA_COUNT: <n>
+            // followed by n lines of: <vertex index> <dx dy dz> <dnx dny dnz>
+            // Has to come after the VERT: blocks since the deltas are indexed into them.
+            "MORPH_TARGET:" => {
+                let name = lines.part(parts.get(1), "MORPH_TARGET")?.to_string();
+                let count_line = lines.expect_line("DELTA_COUNT")?;
+                let delta_count: usize = lines.parse(count_line.split_whitespace().nth(1).as_ref(), "DELTA_COUNT")?;
+
+                let mut deltas = vec![];
+
+                for _ in 0..delta_count {
+                    let delta_line = lines.expect_line("MORPH_DELTA")?;
+                    let delta_parts = lines.floats(delta_line, 7, "MORPH_DELTA")?;
+                    let idx = delta_parts[0] as usize;
+
+                    if idx >= vertices.len() {
+                        return Err(lines.error("MORPH_DELTA", format!("vertex index {} out of range, morph targets go after the VERT blocks", idx)));
+                    }
+
+                    let position = Vec3::new(delta_parts[1], delta_parts[2], delta_parts[3]);
+                    let normal = Vec3::new(delta_parts[4], delta_parts[5], delta_parts[6]);
+                    deltas.push((idx as u32, position, normal));
+                }
+
+                morph_targets.push((name, deltas));
+            }
+            // MATERIAL: <name>
+            // Starts a new material, the TEXTURE_* lines after it belong to it. Materials are
+            // numbered in the order they show up. Files without any get a single "default" one.
+            "MATERIAL:" => {
+                let name = lines.part(parts.get(1), "MATERIAL")?;
+                materials.push(Material::new(name));
+            }
+            // SUBMESH: <name> <material index>
+            // optionally followed by NODE_TRANSFORM: and 4 column lines
+            // Applies to the next INDEX_COUNT block. Without one, every INDEX_COUNT block is its own
+            // submesh using material 0.
+            "SUBMESH:" => {
+                if let Some(submesh) = &pending_submesh {
+                    return Err(lines.error("SUBMESH", format!("submesh {} has no INDEX_COUNT block", submesh.name)));
+                }
+
+                pending_submesh = Some(SubMesh {
+                    name: lines.part(parts.get(1), "SUBMESH")?.to_string(),
+                    index_offset: 0,
+                    index_count: 0,
+                    material: lines.parse(parts.get(2), "SUBMESH")?,
+                    transform: Mat4::IDENTITY,
+                });
+            }
+            "NODE_TRANSFORM:" => {
+                let transform = lines.mat4("NODE_TRANSFORM")?;
+                match pending_submesh.as_mut() {
+                    Some(submesh) => submesh.transform = transform,
+                    None => return Err(lines.error("NODE_TRANSFORM", "has to follow a SUBMESH line")),
+                }
             }
             "INDEX_COUNT:" => {
-                let index_count: u32 = parts[1].parse().unwrap();
-                let indices: Vec<u32> = lines.next().unwrap().split_whitespace().map(|n| n.parse().unwrap()).collect();
-                
-                dbg!(indices.len());
-                dbg!(index_count);
-                assert!(index_count == indices.len() as u32);
-                model.indices = indices;
+                let index_count: usize = lines.parse(parts.get(1), "INDEX_COUNT")?;
+                let index_line = lines.expect_line("INDICES")?;
+                let block = index_line
+                    .split_whitespace()
+                    .map(|n| lines.parse(Some(&n), "INDICES"))
+                    .collect::<LoadResult<Vec<u32>>>()?;
+
+                if index_count != block.len() {
+                    return Err(lines.error("INDICES", format!("INDEX_COUNT says {} but found {}", index_count, block.len())));
+                }
+
+                let mut submesh = pending_submesh.take().unwrap_or_else(|| SubMesh {
+                    name: format!("submesh_{}", submeshes.len()),
+                    index_offset: 0,
+                    index_count: 0,
+                    material: 0,
+                    transform: Mat4::IDENTITY,
+                });
+                submesh.index_offset = indices.len() as u32;
+                submesh.index_count = block.len() as u32;
+
+                indices.extend(block);
+                submeshes.push(submesh);
             }
             "TEXTURE_DIFFUSE:" => {
-                let path = parts[1].to_string();
-                texture_from_file(&mut model, path, TextureType::Diffuse);
+                let path = lines.part(parts.get(1), "TEXTURE_DIFFUSE")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::Diffuse));
             }
             "TEXTURE_SPECULAR:" => {
-                let path = parts[1].to_string();
-                texture_from_file(&mut model, path, TextureType::Specular);
+                let path = lines.part(parts.get(1), "TEXTURE_SPECULAR")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::Specular));
             }
             "TEXTURE_EMISSIVE:" => {
-                let path = parts[1].to_string();
-                texture_from_file(&mut model, path, TextureType::Emissive);
+                let path = lines.part(parts.get(1), "TEXTURE_EMISSIVE")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::Emissive));
             }
             "TEXTURE_OPACITY:" => {
-                let path = parts[1].to_string();
-                texture_from_file(&mut model, path, TextureType::Opacity);
+                let path = lines.part(parts.get(1), "TEXTURE_OPACITY")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::Opacity));
+            }
+            "TEXTURE_NORMAL:" => {
+                let path = lines.part(parts.get(1), "TEXTURE_NORMAL")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::NormalMap));
+            }
+            "TEXTURE_ROUGHNESS:" => {
+                let path = lines.part(parts.get(1), "TEXTURE_ROUGHNESS")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::Roughness));
+            }
+            "TEXTURE_METALNESS:" => {
+                let path = lines.part(parts.get(1), "TEXTURE_METALNESS")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::Metalness));
+            }
+            "TEXTURE_DISPLACEMENT:" => {
+                let path = lines.part(parts.get(1), "TEXTURE_DISPLACEMENT")?.to_string();
+                textures.push((current_material(&mut materials), path, TextureType::Displacement));
+            }
+            "PARALLAX_SCALE:" => {
+                let scale = lines.parse(parts.get(1), "PARALLAX_SCALE")?;
+                let material = current_material(&mut materials);
+                materials[material].parallax_scale = scale;
             }
             _ => {}
         }
     }
 
-    model.setup_opengl();
+    if let Some(submesh) = pending_submesh {
+        return Err(LoadError::parse(file_path, None, Some("SUBMESH"), format!("submesh {} has no INDEX_COUNT block", submesh.name)));
+    }
+
+    if materials.is_empty() {
+        materials.push(Material::new("default"));
+    }
+
+    if let Some(bad) = submeshes.iter().find(|s| s.material >= materials.len()) {
+        return Err(LoadError::parse(
+            file_path,
+            None,
+            Some("SUBMESH"),
+            format!("submesh {} uses material {} but only {} are defined", bad.name, bad.material, materials.len()),
+        ));
+    }
+
+    if let Some(bad) = indices.iter().find(|i| **i as usize >= vertices.len()) {
+        return Err(LoadError::parse(file_path, None, Some("INDICES"), format!("index {} past the vertex count {}", bad, vertices.len())));
+    }
+
+    generate_tangents(&mut vertices, &indices);
 
-    model
+    let mut materials: Vec<(String, f32, Vec<(u32, String)>)> = materials
+        .into_iter()
+        .map(|m| (m.name, m.parallax_scale, vec![]))
+        .collect();
+    for (material, path, texture_type) in textures {
+        materials[material].2.push((texture_slot(&texture_type) as u32, path));
+    }
+
+    let submeshes = submeshes
+        .into_iter()
+        .map(|s| (s.name, s.index_offset, s.index_count, s.material as u32, s.transform))
+        .collect();
+
+    Ok(CachedModel { materials, submeshes, vertices, indices, morph_targets })
+}
+
+// Index of the material TEXTURE_* lines go to, textures before any MATERIAL line get a default one
+fn current_material(materials: &mut Vec<Material>) -> usize {
+    if materials.is_empty() {
+        materials.push(Material::new("default"));
+    }
+    materials.len() - 1
+}
+
+pub fn model_directory(file_path: &str) -> String {
+    Path::new(file_path)
+        .parent()
+        .and_then(|p| p.to_str())
+        .unwrap_or(".")
+        .to_string()
 }
 
-pub fn texture_from_file(model: &mut Model, path: String, texture_type: TextureType) {
+/// Loads an image relative to the model's directory into the slot for texture_type of one of its
+/// materials. If it can't be read, diffuse gets a plain grey 1x1 stand in (other slots stay empty)
+/// and the error is still handed back so the caller can report it.
+pub fn texture_from_file(model: &mut Model, material: usize, path: String, texture_type: TextureType) -> LoadResult<()> {
     println!("texture is {}", &path);
     let file_name = model.directory.clone() + "/" + path.as_str();
+    let material = &mut model.materials[material];
+
+    match image::open(&file_name) {
+        Ok(img) => {
+            upload_texture(material, &img, file_name, texture_type);
+            Ok(())
+        }
+        Err(e) => {
+            if texture_type == TextureType::Diffuse {
+                // TODO: Parse BSDF color instead or something.
+                let img = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([198, 198, 198, 255])));
+                upload_texture(material, &img, file_name.clone(), texture_type);
+            }
 
-    dbg!(&path);
-    dbg!(&file_name);
+            Err(LoadError::Image {
+                path: file_name,
+                message: e.to_string(),
+            })
+        }
+    }
+}
 
+/// Uploads an already decoded image and puts it in the material's slot for texture_type. Split out
+/// of texture_from_file so loaders with embedded images (glTF) don't have to go through the disk.
+pub fn upload_texture(material: &mut Material, img: &DynamicImage, file_name: String, texture_type: TextureType) {
+    let texture = Texture {
+        id: create_gl_texture(img),
+        _type: texture_type.to_string(),
+        path: file_name,
+    };
+
+    material.textures[texture_slot(&texture_type)] = Some(texture);
+}
+
+fn create_gl_texture(img: &DynamicImage) -> u32 {
     let mut texture_id = 0;
     unsafe {
         gl_call!(gl::GenTextures(1, &mut texture_id));
 
-        let img = match image::open(file_name.clone()) {
-            Ok(data) => Some(data),
-            Err(_) => {
-                if texture_type == TextureType::Diffuse {
-                    // TODO: Parse BSDF color instead or something.
-                    let mut imgbuf = ImageBuffer::new(1,1);
-                    let color_u8 = [
-                        198,
-                        198,
-                        198,
-                        255,
-                    ];
-
-                    for pixel in imgbuf.pixels_mut() {
-                        *pixel = Rgba(color_u8);
-                    }
-
-                    let color_path = format!("{:.3}-{:.3}-{:.3}.png" ,color_u8[0], color_u8[1], color_u8[2]);
-                    let save_loc = format!("{}/{}", model.directory, color_path);
+        let (img_width, img_height) = img.dimensions();
+        let rgba = img.to_rgba8();
+        let raw = rgba.as_raw();
+
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture_id));
+        gl_call!(gl::TexImage2D(
+            gl::TEXTURE_2D, 
+            0, 
+            gl::RGBA as i32, 
+            img_width as i32, 
+            img_height as i32, 
+            0, 
+            gl::RGBA, 
+            gl::UNSIGNED_BYTE, 
+            raw.as_ptr() as *const c_void
+        ));
+
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
+        gl_call!(gl::GenerateMipmap(gl::TEXTURE_2D));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    }
 
-                    imgbuf
-                        .save(save_loc)
-                        .expect("Failed to save texture image");
+    texture_id
+}
 
-                    Some(DynamicImage::ImageRgba8(imgbuf))
-                } else {
-                    None
-                }
-            }
-        };
+/// Fills in Vertex.tangent from the uv layout. Has to run once the indices are in since tangents
+/// get accumulated per triangle. Vertices without usable uvs get any tangent perpendicular to the
+/// normal, they can't show a normal map properly anyway.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
 
-        if let Some(img) = img {
-            let (img_width, img_height) = img.dimensions();
-            let rgba = img.to_rgba8();
-            let raw = rgba.as_raw();
+    for tri in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+        let (v0, v1, v2) = (&vertices[i0], &vertices[i1], &vertices[i2]);
 
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture_id));
-            gl_call!(gl::TexImage2D(
-                gl::TEXTURE_2D, 
-                0, 
-                gl::RGBA as i32, 
-                img_width as i32, 
-                img_height as i32, 
-                0, 
-                gl::RGBA, 
-                gl::UNSIGNED_BYTE, 
-                raw.as_ptr() as *const c_void
-            ));
+        let e1 = v1.position - v0.position;
+        let e2 = v2.position - v0.position;
+        let d1 = v1.uv - v0.uv;
+        let d2 = v2.uv - v0.uv;
 
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
-            gl_call!(gl::GenerateMipmap(gl::TEXTURE_2D));
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
 
-            let texture = Texture {
-                id: texture_id,
-                _type: texture_type.clone().to_string(),
-                path: file_name,
-            };
+        let tangent = (e1 * d2.y - e2 * d1.y) * r;
+        let bitangent = (e2 * d1.x - e1 * d2.x) * r;
 
-            match texture_type {
-                TextureType::Diffuse => {
-                    model.textures[1] = Some(texture);
-                }
-                TextureType::Specular => {
-                    model.textures[2] = Some(texture);
-                }
-                TextureType::Emissive => {
-                    model.textures[3] = Some(texture);
-                }
-                TextureType::NormalMap => {
-                    model.textures[4] = Some(texture);
-                }
-                TextureType::Roughness => {
-                    model.textures[5] = Some(texture);
-                }
-                TextureType::Metalness => {
-                    model.textures[6] = Some(texture);
-                }
-                TextureType::Displacement => {
-                    model.textures[7] = Some(texture);
-                }
-                TextureType::Opacity => {
-                    model.textures[8] = Some(texture);
-                }
-            }
+        for i in [i0, i1, i2] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
         }
     }
-}
 
-fn parse_bone_offset(lines: &mut Lines<'_>) -> Mat4 {
-    Mat4 {
-        x_axis: parse_vec4(lines.next().unwrap()),
-        y_axis: parse_vec4(lines.next().unwrap()),
-        z_axis: parse_vec4(lines.next().unwrap()),
-        w_axis: parse_vec4(lines.next().unwrap()),
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let n = vertex.normal.normalize_or_zero();
+        // Gram-Schmidt against the normal
+        let t = (tangents[i] - n * n.dot(tangents[i]))
+            .try_normalize()
+            .unwrap_or_else(|| n.any_orthonormal_vector());
+        let sign = if n.cross(t).dot(bitangents[i]) < 0.0 { -1.0 } else { 1.0 };
+
+        vertex.tangent = t.extend(sign);
     }
 }
 
-fn parse_vec4(input: &str) -> Vec4 {
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    Vec4::new( 
-        parts[0].parse().unwrap(),
-        parts[1].parse().unwrap(),
-        parts[2].parse().unwrap(),
-        parts[3].parse().unwrap(),
-    )
+/// Line iterator for the WiseModel text files that keeps count of where it is, so parse errors
+/// can point at a line.
+struct WiseLines<'a> {
+    path: &'a str,
+    lines: Lines<'a>,
+    line_no: usize,
 }
 
-fn parse_vec3(input: &str) -> Vec3 {
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    Vec3::new( 
-        parts[0].parse().unwrap(),
-        parts[1].parse::<f32>().unwrap(),
-        parts[2].parse().unwrap(),
-    )
-}
+impl<'a> WiseLines<'a> {
+    fn new(path: &'a str, data: &'a str) -> Self {
+        Self {
+            path,
+            lines: data.lines(),
+            line_no: 0,
+        }
+    }
 
-fn parse_vec2(input: &str) -> Vec2 {
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    Vec2::new( 
-        parts[0].parse::<f32>().unwrap(),
-        parts[1].parse::<f32>().unwrap(),
-    )
-}
+    fn next(&mut self) -> Option<&'a str> {
+        let line = self.lines.next()?;
+        self.line_no += 1;
+        Some(line)
+    }
+
+    fn expect_line(&mut self, field: &str) -> LoadResult<&'a str> {
+        match self.next() {
+            Some(line) => Ok(line),
+            None => Err(self.error(field, "unexpected end of file")),
+        }
+    }
+
+    fn error(&self, field: &str, message: impl Into<String>) -> LoadError {
+        LoadError::parse(self.path, Some(self.line_no), Some(field), message)
+    }
 
-fn parse_quat(input: &str) -> Quat {
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    Quat::from_xyzw(
-        parts[0].parse().unwrap(),
-        parts[1].parse().unwrap(),
-        parts[2].parse().unwrap(),
-        parts[3].parse().unwrap(),
-    )
+    fn part<'b>(&self, part: Option<&&'b str>, field: &str) -> LoadResult<&'b str> {
+        part.copied().ok_or_else(|| self.error(field, "missing value"))
+    }
+
+    fn parse<T: FromStr>(&self, part: Option<&&str>, field: &str) -> LoadResult<T> {
+        let part = self.part(part, field)?;
+        part.parse().map_err(|_| self.error(field, format!("could not parse '{}'", part)))
+    }
+
+    fn floats(&self, line: &str, count: usize, field: &str) -> LoadResult<Vec<f32>> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < count {
+            return Err(self.error(field, format!("expected {} numbers, found {}", count, parts.len())));
+        }
+
+        parts[..count].iter().map(|p| self.parse(Some(p), field)).collect()
+    }
+
+    fn vec2(&mut self, field: &str) -> LoadResult<Vec2> {
+        let line = self.expect_line(field)?;
+        let v = self.floats(line, 2, field)?;
+        Ok(Vec2::new(v[0], v[1]))
+    }
+
+    fn vec3(&mut self, field: &str) -> LoadResult<Vec3> {
+        let line = self.expect_line(field)?;
+        let v = self.floats(line, 3, field)?;
+        Ok(Vec3::new(v[0], v[1], v[2]))
+    }
+
+    fn vec4(&mut self, field: &str) -> LoadResult<Vec4> {
+        let line = self.expect_line(field)?;
+        let v = self.floats(line, 4, field)?;
+        Ok(Vec4::new(v[0], v[1], v[2], v[3]))
+    }
+
+    fn quat(&mut self, field: &str) -> LoadResult<Quat> {
+        let line = self.expect_line(field)?;
+        let v = self.floats(line, 4, field)?;
+        Ok(Quat::from_xyzw(v[0], v[1], v[2], v[3]))
+    }
+
+    // One column per line
+    fn mat4(&mut self, field: &str) -> LoadResult<Mat4> {
+        Ok(Mat4 {
+            x_axis: self.vec4(field)?,
+            y_axis: self.vec4(field)?,
+            z_axis: self.vec4(field)?,
+            w_axis: self.vec4(field)?,
+        })
+    }
 }
 
-fn build_bone_hierarchy_top_down(bones: Vec<Bone>) -> Bone {
+pub fn build_bone_hierarchy_top_down(bones: Vec<Bone>) -> Bone {
     let mut children_of = vec![Vec::new(); bones.len()];
 
     for bone in &bones {
@@ -0,0 +1,681 @@
+use std::{collections::HashMap, path::Path};
+
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+use gltf::{animation::{util::ReadOutputs, Interpolation}, image::Format, mesh::Mode, Document, Node};
+use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, Rgba, RgbImage, RgbaImage};
+
+use crate::{animation::animation::{build_bone_hierarchy_top_down, generate_tangents, model_directory, upload_texture, Animation, Animator, Bone, BoneJoinInfo, BoneTransformTrack, Material, Model, MorphTarget, MorphWeightTrack, SubMesh, Vertex}, enums_types::{AnimationType, TextureType}, error::{LoadError, LoadResult}, some_data::{MAX_BONES, MAX_BONE_INFLUENCE}};
+
+// glTF keys can sit at any time and translation/rotation/scale channels don't have to share them,
+// but BoneTransformTrack wants one set of timestamps for all three, so every clip gets resampled.
+const RESAMPLE_FPS: f32 = 30.0;
+
+// Only used when the skin has more than one root joint.
+const SYNTHETIC_ROOT_NAME: &str = "__gltf_root";
+
+// Past the shaders' bone_transforms array, which they treat as "don't skin this vertex"
+const UNSKINNED_BONE_ID: i32 = MAX_BONES as i32;
+
+/// What the vertices of a primitive without JOINTS_0 get in a model that has a skeleton. Left with
+/// no weights they would skin to the origin.
+#[derive(Clone, Copy)]
+enum RigidBinding {
+    // Model without a skeleton, nothing to do
+    None,
+    // Follows this joint with weight 1, the node's bind pose transform is baked into the vertices
+    Joint(i32, Mat4),
+    // Not under any joint, drawn with its node transform like a static mesh
+    Unskinned,
+}
+
+pub fn is_gltf(path: &str) -> bool {
+    matches!(
+        Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref(),
+        Some("gltf") | Some("glb")
+    )
+}
+
+fn load(file_path: &str) -> LoadResult<(Document, Vec<gltf::buffer::Data>, Vec<gltf::image::Data>)> {
+    println!("Loading glTF file {}", file_path);
+    gltf::import(file_path).map_err(|e| gltf_error(file_path, e.to_string()))
+}
+
+fn gltf_error(file_path: &str, message: impl Into<String>) -> LoadError {
+    LoadError::Gltf {
+        path: file_path.to_string(),
+        message: message.into(),
+    }
+}
+
+fn node_name(node: &Node) -> String {
+    match node.name() {
+        Some(name) => name.to_string(),
+        None => format!("node_{}", node.index()),
+    }
+}
+
+// Blender writes the shape key names into mesh.extras.targetNames, fall back to numbering them.
+fn morph_target_names(mesh: &gltf::Mesh, count: usize) -> Vec<String> {
+    let mesh_name = match mesh.name() {
+        Some(name) => name.to_string(),
+        None => format!("mesh_{}", mesh.index()),
+    };
+
+    let from_extras: Vec<String> = mesh
+        .extras()
+        .as_ref()
+        .and_then(|raw| serde_json::from_str::<serde_json::Value>(raw.get()).ok())
+        .and_then(|v| v.get("targetNames").cloned())
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+
+    (0..count)
+        .map(|i| from_extras.get(i).cloned().unwrap_or_else(|| format!("{}_{}", mesh_name, i)))
+        .collect()
+}
+
+fn local_trs(node: &Node) -> (Vec3, Quat, Vec3) {
+    let (t, r, s) = node.transform().decomposed();
+    (Vec3::from(t), Quat::from_array(r), Vec3::from(s))
+}
+
+fn parent_lookup(doc: &Document) -> HashMap<usize, usize> {
+    let mut parent_of = HashMap::new();
+    for node in doc.nodes() {
+        for child in node.children() {
+            parent_of.insert(child.index(), node.index());
+        }
+    }
+    parent_of
+}
+
+fn global_transform(doc: &Document, parent_of: &HashMap<usize, usize>, node_idx: usize) -> Mat4 {
+    let node = doc.nodes().nth(node_idx).unwrap();
+    let local = Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    match parent_of.get(&node_idx) {
+        Some(parent) => global_transform(doc, parent_of, *parent) * local,
+        None => local,
+    }
+}
+
+// =============================================================
+// Skeleton and clips
+// =============================================================
+
+struct Channel<T> {
+    times: Vec<f32>,
+    values: Vec<T>,
+    step: bool,
+}
+
+impl<T: Copy> Channel<T> {
+    fn sample(&self, t: f32, lerp: impl Fn(T, T, f32) -> T) -> T {
+        if t <= self.times[0] {
+            return self.values[0];
+        }
+        let last = self.times.len() - 1;
+        if t >= self.times[last] {
+            return self.values[last];
+        }
+
+        let next = self.times.iter().position(|time| *time >= t).unwrap();
+        let prev = next - 1;
+
+        if self.step {
+            return self.values[prev];
+        }
+
+        let fraction = (t - self.times[prev]) / (self.times[next] - self.times[prev]);
+        lerp(self.values[prev], self.values[next], fraction)
+    }
+}
+
+// Cubic spline outputs are stored as (in tangent, value, out tangent). The tangents get dropped
+// and the curve is treated as linear, which is close enough at RESAMPLE_FPS.
+fn channel_values<T: Copy>(values: Vec<T>, interpolation: Interpolation) -> Vec<T> {
+    match interpolation {
+        Interpolation::CubicSpline => values.chunks(3).map(|c| c[1]).collect(),
+        _ => values,
+    }
+}
+
+#[derive(Default)]
+struct NodeChannels {
+    translation: Option<Channel<Vec3>>,
+    rotation: Option<Channel<Quat>>,
+    scale: Option<Channel<Vec3>>,
+}
+
+pub fn import_gltf_bone_data(file_path: &str) -> LoadResult<(Bone, Animator, Animation)> {
+    let (doc, buffers, _) = load(file_path)?;
+
+    let skin = doc.skins().next().ok_or_else(|| gltf_error(file_path, "no skin to build a skeleton from"))?;
+    let joints: Vec<Node> = skin.joints().collect();
+
+    let inverse_binds: Vec<Mat4> = skin
+        .reader(|b| Some(&buffers[b.index()]))
+        .read_inverse_bind_matrices()
+        .map(|iter| iter.map(|m| Mat4::from_cols_array_2d(&m)).collect())
+        .unwrap_or_else(|| vec![Mat4::IDENTITY; joints.len()]);
+
+    let parent_of = parent_lookup(&doc);
+    let joint_of_node: HashMap<usize, u32> = joints.iter().enumerate().map(|(i, n)| (n.index(), i as u32)).collect();
+
+    // =============================================================
+    // Bones
+    // ============================================================
+    let mut bones_no_children = vec![];
+    let mut root_ids = vec![];
+
+    // Anything above the root joints in the node tree still moves the skeleton, so it gets baked
+    // into the root keys since calculate_pose starts from identity.
+    let mut root_corrections: HashMap<u32, Mat4> = HashMap::new();
+
+    for (i, joint) in joints.iter().enumerate() {
+        let parent_index = parent_of.get(&joint.index()).and_then(|p| joint_of_node.get(p)).copied();
+
+        if parent_index.is_none() {
+            root_ids.push(i as u32);
+            let correction = match parent_of.get(&joint.index()) {
+                Some(p) => global_transform(&doc, &parent_of, *p),
+                None => Mat4::IDENTITY,
+            };
+            root_corrections.insert(i as u32, correction);
+        }
+
+        bones_no_children.push(Bone {
+            id: i as u32,
+            parent_index,
+            name: node_name(joint),
+            offset: inverse_binds[i],
+            children: vec![],
+        });
+    }
+
+    if root_ids.len() > 1 {
+        let root_id = bones_no_children.len() as u32;
+        for id in root_ids.iter() {
+            bones_no_children[*id as usize].parent_index = Some(root_id);
+        }
+
+        bones_no_children.push(Bone {
+            id: root_id,
+            parent_index: None,
+            name: SYNTHETIC_ROOT_NAME.to_string(),
+            offset: Mat4::IDENTITY,
+            children: vec![],
+        });
+    }
+
+    let bone = build_bone_hierarchy_top_down(bones_no_children.clone());
+
+    let model_animation_join: Vec<BoneJoinInfo> = bones_no_children
+        .iter()
+        .map(|b| BoneJoinInfo { name: b.name.clone() })
+        .collect();
+
+    let bind_pose: Vec<(Vec3, Quat, Vec3)> = bones_no_children
+        .iter()
+        .map(|b| match joints.get(b.id as usize) {
+            Some(joint) => local_trs(joint),
+            None => (Vec3::ZERO, Quat::IDENTITY, Vec3::ONE),
+        })
+        .collect();
+
+    let new_animation = || {
+        let mut animation = Animation::default();
+        animation.model_animation_join = model_animation_join.clone();
+        animation.ticks_per_second = RESAMPLE_FPS;
+
+        for b in &bones_no_children {
+            animation.current_pose.push(b.offset);
+            animation.current_locals.push(Mat4::IDENTITY);
+            animation.current_globals.push(Mat4::IDENTITY);
+        }
+
+        animation
+    };
+
+    let push_key = |animation: &mut Animation, bone_id: usize, time: f32, (pos, rot, scale): (Vec3, Quat, Vec3)| {
+        let (pos, rot, scale) = match root_corrections.get(&(bone_id as u32)) {
+            Some(correction) if *correction != Mat4::IDENTITY => {
+                let corrected = *correction * Mat4::from_scale_rotation_translation(scale, rot, pos);
+                let (s, r, p) = corrected.to_scale_rotation_translation();
+                (p, r, s)
+            }
+            _ => (pos, rot, scale),
+        };
+
+        let track = animation
+            .bone_transforms
+            .entry(bones_no_children[bone_id].name.clone())
+            .or_insert_with(BoneTransformTrack::default);
+
+        track.position_timestamps.push(time);
+        track.rotation_timestamps.push(time);
+        track.scale_timestamps.push(time);
+
+        track.positions.push(pos);
+        track.rotations.push(rot);
+        track.scales.push(scale);
+    };
+
+    // =============================================================
+    // Clips
+    // ============================================================
+    let mut animator = Animator::new();
+    let mut first_clip = None;
+
+    for gltf_anim in doc.animations() {
+        let clip_name = gltf_anim.name().unwrap_or("").to_string();
+        let Some(anim_type) = AnimationType::from_str(&clip_name) else {
+            println!("WARNING: Skipping glTF animation '{}', it is not a known AnimationType", clip_name);
+            continue;
+        };
+
+        let mut animation = new_animation();
+        let mut node_channels: HashMap<usize, NodeChannels> = HashMap::new();
+        let mut duration: f32 = 0.0;
+
+        for channel in gltf_anim.channels() {
+            let reader = channel.reader(|b| Some(&buffers[b.index()]));
+            let target = channel.target();
+            let interpolation = channel.sampler().interpolation();
+            let step = interpolation == Interpolation::Step;
+
+            let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+                continue;
+            };
+            let times: Vec<f32> = inputs.collect();
+            if times.is_empty() {
+                continue;
+            }
+            duration = duration.max(*times.last().unwrap());
+
+            let entry = node_channels.entry(target.node().index()).or_default();
+
+            match outputs {
+                ReadOutputs::Translations(values) => {
+                    let values = channel_values(values.map(Vec3::from).collect(), interpolation);
+                    entry.translation = Some(Channel { times, values, step });
+                }
+                ReadOutputs::Rotations(values) => {
+                    let values = channel_values(values.into_f32().map(Quat::from_array).collect(), interpolation);
+                    entry.rotation = Some(Channel { times, values, step });
+                }
+                ReadOutputs::Scales(values) => {
+                    let values = channel_values(values.map(Vec3::from).collect(), interpolation);
+                    entry.scale = Some(Channel { times, values, step });
+                }
+                ReadOutputs::MorphTargetWeights(values) => {
+                    let Some(mesh) = target.node().mesh() else {
+                        continue;
+                    };
+                    let weights: Vec<f32> = values.into_f32().collect();
+                    let per_key = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+                    let target_count = weights.len() / (times.len() * per_key);
+                    let names = morph_target_names(&mesh, target_count);
+
+                    for (key, time) in times.iter().enumerate() {
+                        let key_start = (key * per_key + per_key / 2) * target_count;
+
+                        for (t, name) in names.iter().enumerate() {
+                            let track = animation
+                                .morph_tracks
+                                .entry(name.clone())
+                                .or_insert_with(MorphWeightTrack::default);
+
+                            track.timestamps.push(*time);
+                            track.weights.push(weights[key_start + t]);
+                        }
+                    }
+                }
+            }
+        }
+
+        // A single key clip still needs two keys for get_time_fraction to work with.
+        if duration <= 0.0 {
+            duration = 1.0 / RESAMPLE_FPS;
+        }
+
+        animation.duration = duration;
+        if anim_type == AnimationType::Death {
+            animation.looping = false;
+        }
+
+        let frame_count = (duration * RESAMPLE_FPS).ceil() as usize;
+        for frame in 0..=frame_count {
+            let time = (frame as f32 / RESAMPLE_FPS).min(duration);
+
+            for (bone_id, b) in bones_no_children.iter().enumerate() {
+                let (bind_pos, bind_rot, bind_scale) = bind_pose[bone_id];
+
+                let key = match joints.get(b.id as usize).and_then(|j| node_channels.get(&j.index())) {
+                    Some(channels) => (
+                        channels.translation.as_ref().map(|c| c.sample(time, |x, y, f| x.lerp(y, f))).unwrap_or(bind_pos),
+                        channels.rotation.as_ref().map(|c| c.sample(time, |x, y, f| x.slerp(y, f))).unwrap_or(bind_rot),
+                        channels.scale.as_ref().map(|c| c.sample(time, |x, y, f| x.lerp(y, f))).unwrap_or(bind_scale),
+                    ),
+                    None => (bind_pos, bind_rot, bind_scale),
+                };
+
+                push_key(&mut animation, bone_id, time, key);
+            }
+        }
+
+        if first_clip.is_none() {
+            first_clip = Some(anim_type.clone());
+        }
+        animator.animations.insert(anim_type, animation);
+    }
+
+    // Static skinned meshes still need something to play.
+    if animator.animations.is_empty() {
+        println!("WARNING: {} has no usable animations, using the bind pose as Idle", file_path);
+        let mut animation = new_animation();
+        animation.duration = 1.0;
+
+        for time in [0.0, 1.0] {
+            for (bone_id, key) in bind_pose.iter().enumerate() {
+                push_key(&mut animation, bone_id, time, *key);
+            }
+        }
+
+        first_clip = Some(AnimationType::Idle);
+        animator.animations.insert(AnimationType::Idle, animation);
+    }
+
+    let starting = if animator.animations.contains_key(&AnimationType::Idle) {
+        AnimationType::Idle
+    } else {
+        first_clip.unwrap()
+    };
+
+    animator.set_current_animation(starting.clone());
+    animator.set_next_animation(starting.clone());
+    let animation = animator.animations.get(&starting).unwrap().clone();
+
+    Ok((bone, animator, animation))
+}
+
+// =============================================================
+// Meshes
+// =============================================================
+
+// Sparse deltas per morph target name, gathered while the vertex count is still growing.
+type MorphDeltas = Vec<(String, Vec<(usize, Vec3, Vec3)>)>;
+
+/// Every primitive in the default scene becomes a submesh of one Model, with its node's global
+/// transform and its own material. Skinned primitives ignore the node transform per the spec.
+pub fn import_gltf_model_data(file_path: &str, animation: &Animation) -> LoadResult<Model> {
+    let (doc, buffers, images) = load(file_path)?;
+
+    let mut model = Model::new();
+    model.directory = model_directory(file_path);
+    model.full_path = file_path.to_string();
+    model.materials.clear();
+
+    let mut morph_deltas: MorphDeltas = vec![];
+    // glTF material index (None is the spec's default material) -> index into model.materials
+    let mut material_ids: HashMap<Option<usize>, usize> = HashMap::new();
+
+    let scene = doc.default_scene().or_else(|| doc.scenes().next()).ok_or_else(|| gltf_error(file_path, "no scenes"))?;
+
+    let parent_of = parent_lookup(&doc);
+    let joint_index = |name: &str| animation.model_animation_join.iter().position(|info| info.name == name).map(|i| i as i32);
+
+    let mut stack: Vec<(Node, Mat4)> = scene.nodes().map(|n| (n, Mat4::IDENTITY)).collect();
+    while let Some((node, parent_transform)) = stack.pop() {
+        let global = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            // Skinned meshes are placed by their joints, the node transform is ignored per the spec.
+            let mesh_transform = if node.skin().is_some() { Mat4::IDENTITY } else { global };
+
+            let joint_ids: Vec<i32> = match node.skin() {
+                Some(skin) => skin
+                    .joints()
+                    .map(|joint| {
+                        joint_index(&node_name(&joint)).unwrap_or(-1)
+                    })
+                    .collect(),
+                None => vec![],
+            };
+
+            // Props parented somewhere under the skeleton, the closest joint above them carries them
+            let rigid = if animation.model_animation_join.is_empty() {
+                RigidBinding::None
+            } else {
+                let mut current = Some(node.index());
+                let mut joint = None;
+                while let (Some(idx), None) = (current, joint) {
+                    joint = doc.nodes().nth(idx).and_then(|n| joint_index(&node_name(&n)));
+                    current = parent_of.get(&idx).copied();
+                }
+                match joint {
+                    Some(joint) => RigidBinding::Joint(joint, global),
+                    None => RigidBinding::Unskinned,
+                }
+            };
+
+            for (i, primitive) in mesh.primitives().enumerate() {
+                if primitive.mode() != Mode::Triangles {
+                    println!("WARNING: Skipping non triangle primitive in {}", file_path);
+                    continue;
+                }
+
+                let gltf_material = primitive.material();
+                let material = *material_ids.entry(gltf_material.index()).or_insert_with(|| {
+                    model.materials.push(convert_material(file_path, &gltf_material, &images));
+                    model.materials.len() - 1
+                });
+
+                let index_offset = model.indices.len() as u32;
+                let baked = append_primitive(&mut model, &mut morph_deltas, &mesh, &primitive, &buffers, &joint_ids, rigid);
+
+                model.submeshes.push(SubMesh {
+                    name: format!("{}_{}", mesh.name().unwrap_or("mesh"), i),
+                    index_offset,
+                    index_count: model.indices.len() as u32 - index_offset,
+                    material,
+                    transform: if baked { Mat4::IDENTITY } else { mesh_transform },
+                });
+            }
+        }
+
+        for child in node.children() {
+            stack.push((child, global));
+        }
+    }
+
+    if model.materials.is_empty() {
+        model.materials.push(Material::new("default"));
+    }
+
+    let vertex_count = model.vertices.len();
+    for (name, deltas) in morph_deltas {
+        let mut target = MorphTarget {
+            name,
+            position_deltas: vec![Vec3::ZERO; vertex_count],
+            normal_deltas: vec![Vec3::ZERO; vertex_count],
+        };
+
+        for (idx, pos, norm) in deltas {
+            target.position_deltas[idx] = pos;
+            target.normal_deltas[idx] = norm;
+        }
+
+        model.morph_targets.push(target);
+    }
+
+    model.setup_opengl();
+
+    Ok(model)
+}
+
+fn convert_material(file_path: &str, gltf_material: &gltf::Material, images: &[gltf::image::Data]) -> Material {
+    let name = gltf_material.name().map(|n| n.to_string()).unwrap_or_else(|| match gltf_material.index() {
+        Some(i) => format!("material_{}", i),
+        None => "default".to_string(),
+    });
+    let mut material = Material::new(&name);
+    let pbr = gltf_material.pbr_metallic_roughness();
+
+    let diffuse_image = pbr.base_color_texture().map(|info| info.texture().source().index());
+    match diffuse_image.and_then(|i| gltf_image_to_dynamic(&images[i])) {
+        Some(img) => upload_texture(&mut material, &img, format!("{}#{}_diffuse", file_path, name), TextureType::Diffuse),
+        None => {
+            // Untextured material, bake the base color into a 1x1 texture like texture_from_file does
+            let color = pbr.base_color_factor();
+            let pixel = Rgba(color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8));
+            let img = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, pixel));
+            upload_texture(&mut material, &img, format!("{}#{}_base_color", file_path, name), TextureType::Diffuse);
+        }
+    }
+
+    let emissive_image = gltf_material.emissive_texture().map(|info| info.texture().source().index());
+    if let Some(img) = emissive_image.and_then(|i| gltf_image_to_dynamic(&images[i])) {
+        upload_texture(&mut material, &img, format!("{}#{}_emissive", file_path, name), TextureType::Emissive);
+    }
+
+    let normal_image = gltf_material.normal_texture().map(|info| info.texture().source().index());
+    if let Some(img) = normal_image.and_then(|i| gltf_image_to_dynamic(&images[i])) {
+        upload_texture(&mut material, &img, format!("{}#{}_normal", file_path, name), TextureType::NormalMap);
+    }
+
+    // glTF packs roughness into green and metalness into blue, model.glsl reads both from red
+    let metallic_roughness_image = pbr.metallic_roughness_texture().map(|info| info.texture().source().index());
+    if let Some(img) = metallic_roughness_image.and_then(|i| gltf_image_to_dynamic(&images[i])) {
+        let rgba = img.to_rgba8();
+        for (channel, texture_type, suffix) in [(1, TextureType::Roughness, "roughness"), (2, TextureType::Metalness, "metalness")] {
+            let single = ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+                let v = rgba.get_pixel(x, y)[channel];
+                Rgba([v, v, v, 255])
+            });
+            upload_texture(&mut material, &DynamicImage::ImageRgba8(single), format!("{}#{}_{}", file_path, name, suffix), texture_type);
+        }
+    }
+
+    material
+}
+
+/// Returns whether the node transform got baked into the vertices, the submesh then needs none.
+fn append_primitive(
+    model: &mut Model,
+    morph_deltas: &mut MorphDeltas,
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    joint_ids: &[i32],
+    rigid: RigidBinding,
+) -> bool {
+    let reader = primitive.reader(|b| Some(&buffers[b.index()]));
+
+    let Some(positions) = reader.read_positions() else {
+        return false;
+    };
+    let positions: Vec<Vec3> = positions.map(Vec3::from).collect();
+    let count = positions.len();
+
+    let normals: Vec<Vec3> = reader.read_normals().map(|n| n.map(Vec3::from).collect()).unwrap_or_else(|| vec![Vec3::Y; count]);
+    let uvs: Vec<Vec2> = reader.read_tex_coords(0).map(|t| t.into_f32().map(Vec2::from).collect()).unwrap_or_else(|| vec![Vec2::ZERO; count]);
+    let joints: Option<Vec<[u16; 4]>> = reader.read_joints(0).map(|j| j.into_u16().collect());
+    let weights: Option<Vec<[f32; 4]>> = reader.read_weights(0).map(|w| w.into_f32().collect());
+    let tangents: Option<Vec<Vec4>> = reader.read_tangents().map(|t| t.map(Vec4::from).collect());
+
+    let rigid = if joints.is_some() && weights.is_some() { RigidBinding::None } else { rigid };
+    // Identity unless the primitive rides on a joint, deltas go through the same transform
+    let bake = match rigid {
+        RigidBinding::Joint(_, transform) => transform,
+        _ => Mat4::IDENTITY,
+    };
+    let normal_bake = Mat3::from_mat4(bake).inverse().transpose();
+
+    let base = model.vertices.len();
+
+    for i in 0..count {
+        let tangent = tangents.as_ref().map(|t| t[i]).unwrap_or(Vec4::X);
+        let mut vertex = Vertex {
+            position: bake.transform_point3(positions[i]),
+            normal: (normal_bake * normals[i]).normalize_or_zero(),
+            uv: uvs[i],
+            tangent: bake.transform_vector3(tangent.truncate()).normalize_or_zero().extend(tangent.w),
+            bone_ids: [-1; MAX_BONE_INFLUENCE],
+            bone_weights: [0.0; MAX_BONE_INFLUENCE],
+        };
+
+        match rigid {
+            RigidBinding::None => (),
+            RigidBinding::Joint(joint, _) => {
+                vertex.bone_ids[0] = joint;
+                vertex.bone_weights[0] = 1.0;
+            }
+            RigidBinding::Unskinned => vertex.bone_ids[0] = UNSKINNED_BONE_ID,
+        }
+
+        if let (Some(joints), Some(weights)) = (&joints, &weights) {
+            for j in 0..MAX_BONE_INFLUENCE {
+                if weights[i][j] <= 0.0 {
+                    continue;
+                }
+                vertex.bone_ids[j] = joint_ids.get(joints[i][j] as usize).copied().unwrap_or(-1);
+                vertex.bone_weights[j] = weights[i][j];
+            }
+
+            let total_weight = vertex.bone_weights.iter().sum::<f32>();
+            if total_weight > 0.0 {
+                for w in vertex.bone_weights.iter_mut() {
+                    *w /= total_weight;
+                }
+            }
+        }
+
+        model.vertices.push(vertex);
+    }
+
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..count as u32).collect(),
+    };
+
+    if tangents.is_none() {
+        generate_tangents(&mut model.vertices[base..], &indices);
+    }
+    model.indices.extend(indices.iter().map(|i| i + base as u32));
+
+    let targets: Vec<_> = reader.read_morph_targets().collect();
+    let names = morph_target_names(mesh, targets.len());
+
+    for ((positions, normals, _), name) in targets.into_iter().zip(names) {
+        let positions: Vec<Vec3> = positions.map(|p| p.map(Vec3::from).collect()).unwrap_or_else(|| vec![Vec3::ZERO; count]);
+        let normals: Vec<Vec3> = normals.map(|n| n.map(Vec3::from).collect()).unwrap_or_else(|| vec![Vec3::ZERO; count]);
+
+        let deltas: Vec<(usize, Vec3, Vec3)> = (0..count)
+            .filter(|i| positions[*i] != Vec3::ZERO || normals[*i] != Vec3::ZERO)
+            .map(|i| (base + i, bake.transform_vector3(positions[i]), normal_bake * normals[i]))
+            .collect();
+
+        match morph_deltas.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => existing.extend(deltas),
+            None => morph_deltas.push((name, deltas)),
+        }
+    }
+
+    matches!(rigid, RigidBinding::Joint(..))
+}
+
+fn gltf_image_to_dynamic(data: &gltf::image::Data) -> Option<DynamicImage> {
+    let (w, h, pixels) = (data.width, data.height, data.pixels.clone());
+
+    match data.format {
+        Format::R8G8B8A8 => RgbaImage::from_raw(w, h, pixels).map(DynamicImage::ImageRgba8),
+        Format::R8G8B8 => RgbImage::from_raw(w, h, pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8 => GrayAlphaImage::from_raw(w, h, pixels).map(DynamicImage::ImageLumaA8),
+        Format::R8 => GrayImage::from_raw(w, h, pixels).map(DynamicImage::ImageLuma8),
+        _ => {
+            println!("WARNING: Unsupported glTF image format {:?}, skipping texture", data.format);
+            None
+        }
+    }
+}
@@ -6,3 +6,5 @@
 // pub mod animator;
 pub mod animation;
 pub mod animation_system;
+pub mod gltf_import;
+pub mod wise_cache;
@@ -0,0 +1,859 @@
+// Binary cache for the WiseModel text files. Parsing the text files with split_whitespace is most of
+// our startup time, so the first load writes a <file>.wbin next to the source and later loads use
+// that as long as the source contents haven't changed.
+use std::{collections::hash_map::DefaultHasher, fs, hash::{Hash, Hasher}};
+
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+
+use crate::{animation::animation::{build_bone_hierarchy_top_down, texture_from_file, texture_type_for_slot, Animation, Animator, Bone, BoneJoinInfo, BoneTransformTrack, Material, Model, MorphTarget, MorphWeightTrack, SubMesh, Vertex}, enums_types::AnimationType, some_data::MAX_BONE_INFLUENCE};
+
+const MODEL_MAGIC: &[u8; 4] = b"WMDL";
+const BONES_MAGIC: &[u8; 4] = b"WBON";
+// Bump whenever the layout below changes, old caches then just get rebuilt.
+const CACHE_VERSION: u32 = 3;
+
+fn cache_path(file_path: &str) -> String {
+    format!("{}.wbin", file_path)
+}
+
+// Content hash rather than mtime so a git checkout doesn't throw every cache away. DefaultHasher
+// isn't stable across rust versions, which only means a rebuild after a toolchain update.
+fn source_key(file_path: &str) -> Option<(u64, u64)> {
+    let data = fs::read(file_path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    Some((data.len() as u64, hasher.finish()))
+}
+
+// The bone ids baked into the vertices come from the skeleton, so model caches are only valid for
+// the same bone order.
+fn join_key(animation: &Animation) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for info in animation.model_animation_join.iter() {
+        info.name.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// =============================================================
+// Byte helpers
+// =============================================================
+
+struct ByteWriter {
+    buf: Vec<u8>,
+}
+
+impl ByteWriter {
+    fn new() -> Self {
+        Self { buf: vec![] }
+    }
+
+    fn bytes(&mut self, v: &[u8]) { self.buf.extend_from_slice(v); }
+    fn u8(&mut self, v: u8) { self.buf.push(v); }
+    fn u32(&mut self, v: u32) { self.bytes(&v.to_le_bytes()); }
+    fn i32(&mut self, v: i32) { self.bytes(&v.to_le_bytes()); }
+    fn u64(&mut self, v: u64) { self.bytes(&v.to_le_bytes()); }
+    fn f32(&mut self, v: f32) { self.bytes(&v.to_le_bytes()); }
+
+    fn str(&mut self, v: &str) {
+        self.u32(v.len() as u32);
+        self.bytes(v.as_bytes());
+    }
+
+    fn f32s(&mut self, v: &[f32]) {
+        for f in v {
+            self.f32(*f);
+        }
+    }
+
+    fn vec3(&mut self, v: Vec3) { self.f32s(&v.to_array()); }
+    fn quat(&mut self, v: Quat) { self.f32s(&v.to_array()); }
+    fn mat4(&mut self, v: &Mat4) { self.f32s(&v.to_cols_array()); }
+}
+
+// Every read returns an Option so a truncated or corrupt cache falls back to the text loader.
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let out = self.data.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(out)
+    }
+
+    fn u8(&mut self) -> Option<u8> { Some(self.bytes(1)?[0]) }
+    fn u32(&mut self) -> Option<u32> { Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?)) }
+    fn i32(&mut self) -> Option<i32> { Some(i32::from_le_bytes(self.bytes(4)?.try_into().ok()?)) }
+    fn u64(&mut self) -> Option<u64> { Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?)) }
+    fn f32(&mut self) -> Option<f32> { Some(f32::from_le_bytes(self.bytes(4)?.try_into().ok()?)) }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn vec2(&mut self) -> Option<Vec2> { Some(Vec2::new(self.f32()?, self.f32()?)) }
+    fn vec3(&mut self) -> Option<Vec3> { Some(Vec3::new(self.f32()?, self.f32()?, self.f32()?)) }
+    fn quat(&mut self) -> Option<Quat> { Some(Quat::from_xyzw(self.f32()?, self.f32()?, self.f32()?, self.f32()?)) }
+
+    fn mat4(&mut self) -> Option<Mat4> {
+        let mut cols = [0.0; 16];
+        for c in cols.iter_mut() {
+            *c = self.f32()?;
+        }
+        Some(Mat4::from_cols_array(&cols))
+    }
+
+    fn header(&mut self, magic: &[u8; 4]) -> Option<()> {
+        if self.bytes(4)? != magic || self.u32()? != CACHE_VERSION {
+            return None;
+        }
+        Some(())
+    }
+}
+
+// A cache that exists but doesn't match its source or this build gets deleted so the caller's
+// rebuild starts clean, rather than leaving a file behind that fails to load on every start.
+fn discard_stale_cache(file_path: &str) {
+    let path = cache_path(file_path);
+    println!("Cache {} is stale, rebuilding", path);
+    if let Err(e) = fs::remove_file(&path) {
+        println!("WARNING: Could not remove {}: {}", path, e);
+    }
+}
+
+fn write_cache(file_path: &str, data: &[u8]) {
+    if let Err(e) = fs::write(cache_path(file_path), data) {
+        println!("WARNING: Could not write cache for {}: {}", file_path, e);
+    }
+}
+
+// =============================================================
+// Model
+// =============================================================
+
+/// Everything in a Model that doesn't live on the gpu, as parse_model_data reads it from the text.
+#[derive(Debug, PartialEq)]
+pub struct CachedModel {
+    // Material name, parallax scale and its (slot, path) textures
+    pub materials: Vec<(String, f32, Vec<(u32, String)>)>,
+    // Name, index offset, index count, material, node transform
+    pub submeshes: Vec<(String, u32, u32, u32, Mat4)>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub morph_targets: Vec<(String, Vec<(u32, Vec3, Vec3)>)>,
+}
+
+impl CachedModel {
+    fn encode(&self, source: (u64, u64), join: u64) -> Vec<u8> {
+        let mut w = ByteWriter::new();
+        w.bytes(MODEL_MAGIC);
+        w.u32(CACHE_VERSION);
+        w.u64(source.0);
+        w.u64(source.1);
+        w.u64(join);
+
+        w.u32(self.materials.len() as u32);
+        for (name, parallax_scale, textures) in self.materials.iter() {
+            w.str(name);
+            w.f32(*parallax_scale);
+            w.u32(textures.len() as u32);
+            for (slot, path) in textures.iter() {
+                w.u32(*slot);
+                w.str(path);
+            }
+        }
+
+        w.u32(self.submeshes.len() as u32);
+        for (name, offset, count, material, transform) in self.submeshes.iter() {
+            w.str(name);
+            w.u32(*offset);
+            w.u32(*count);
+            w.u32(*material);
+            w.mat4(transform);
+        }
+
+        w.u32(self.vertices.len() as u32);
+        for v in self.vertices.iter() {
+            w.vec3(v.position);
+            w.vec3(v.normal);
+            w.f32s(&v.uv.to_array());
+            w.f32s(&v.tangent.to_array());
+            for id in v.bone_ids {
+                w.i32(id);
+            }
+            w.f32s(&v.bone_weights);
+        }
+
+        w.u32(self.indices.len() as u32);
+        for i in self.indices.iter() {
+            w.u32(*i);
+        }
+
+        w.u32(self.morph_targets.len() as u32);
+        for (name, deltas) in self.morph_targets.iter() {
+            w.str(name);
+            w.u32(deltas.len() as u32);
+            for (i, p, n) in deltas {
+                w.u32(*i);
+                w.vec3(*p);
+                w.vec3(*n);
+            }
+        }
+
+        w.buf
+    }
+
+    fn decode(data: &[u8], source: (u64, u64), join: u64) -> Option<Self> {
+        let mut r = ByteReader::new(data);
+        r.header(MODEL_MAGIC)?;
+        if (r.u64()?, r.u64()?) != source || r.u64()? != join {
+            return None;
+        }
+
+        let mut materials = vec![];
+        for _ in 0..r.u32()? {
+            let name = r.str()?;
+            let parallax_scale = r.f32()?;
+            let mut textures = vec![];
+            for _ in 0..r.u32()? {
+                textures.push((r.u32()?, r.str()?));
+            }
+            materials.push((name, parallax_scale, textures));
+        }
+
+        let mut submeshes = vec![];
+        for _ in 0..r.u32()? {
+            submeshes.push((r.str()?, r.u32()?, r.u32()?, r.u32()?, r.mat4()?));
+        }
+
+        let vertex_count = r.u32()? as usize;
+        let mut vertices = Vec::with_capacity(vertex_count);
+        for _ in 0..vertex_count {
+            let position = r.vec3()?;
+            let normal = r.vec3()?;
+            let uv = r.vec2()?;
+            let tangent = Vec4::new(r.f32()?, r.f32()?, r.f32()?, r.f32()?);
+
+            let mut bone_ids = [-1; MAX_BONE_INFLUENCE];
+            for id in bone_ids.iter_mut() {
+                *id = r.i32()?;
+            }
+            let mut bone_weights = [0.0; MAX_BONE_INFLUENCE];
+            for w in bone_weights.iter_mut() {
+                *w = r.f32()?;
+            }
+
+            vertices.push(Vertex { position, normal, uv, tangent, bone_ids, bone_weights });
+        }
+
+        let index_count = r.u32()? as usize;
+        let mut indices = Vec::with_capacity(index_count);
+        for _ in 0..index_count {
+            indices.push(r.u32()?);
+        }
+
+        let mut morph_targets = vec![];
+        for _ in 0..r.u32()? {
+            let name = r.str()?;
+            let mut deltas = vec![];
+            for _ in 0..r.u32()? {
+                deltas.push((r.u32()?, r.vec3()?, r.vec3()?));
+            }
+            morph_targets.push((name, deltas));
+        }
+
+        // Submeshes index into both of these at draw time, a bad range would read past the buffers
+        let in_range = submeshes
+            .iter()
+            .all(|(_, offset, count, material, _)| (*material as usize) < materials.len() && (*offset as usize + *count as usize) <= indices.len());
+        if materials.is_empty() || !in_range {
+            return None;
+        }
+
+        Some(Self { materials, submeshes, vertices, indices, morph_targets })
+    }
+
+    /// Loads the textures and uploads everything to the gpu.
+    pub fn into_model(self, file_path: &str, directory: &str) -> Model {
+        let mut model = Model::new();
+        model.directory = directory.to_string();
+        model.full_path = file_path.to_string();
+
+        model.materials = self.materials
+            .iter()
+            .map(|(name, parallax_scale, _)| Material { parallax_scale: *parallax_scale, ..Material::new(name) })
+            .collect();
+        for (i, (_, _, textures)) in self.materials.into_iter().enumerate() {
+            for (slot, path) in textures {
+                if let Some(texture_type) = texture_type_for_slot(slot as usize) {
+                    if let Err(e) = texture_from_file(&mut model, i, path, texture_type) {
+                        println!("WARNING: {}", e);
+                    }
+                }
+            }
+        }
+
+        model.submeshes = self.submeshes
+            .into_iter()
+            .map(|(name, index_offset, index_count, material, transform)| SubMesh {
+                name,
+                index_offset,
+                index_count,
+                material: material as usize,
+                transform,
+            })
+            .collect();
+
+        let vertex_count = self.vertices.len();
+        model.vertices = self.vertices;
+        model.indices = self.indices;
+
+        for (name, deltas) in self.morph_targets {
+            let mut target = MorphTarget {
+                name,
+                position_deltas: vec![Vec3::ZERO; vertex_count],
+                normal_deltas: vec![Vec3::ZERO; vertex_count],
+            };
+            for (i, p, n) in deltas {
+                target.position_deltas[i as usize] = p;
+                target.normal_deltas[i as usize] = n;
+            }
+            model.morph_targets.push(target);
+        }
+
+        model.setup_opengl();
+        model
+    }
+}
+
+pub fn load_model(file_path: &str, animation: &Animation) -> Option<CachedModel> {
+    let data = fs::read(cache_path(file_path)).ok()?;
+    let Some(cached) = CachedModel::decode(&data, source_key(file_path)?, join_key(animation)) else {
+        discard_stale_cache(file_path);
+        return None;
+    };
+
+    println!("Loaded {} from cache", file_path);
+    Some(cached)
+}
+
+pub fn save_model(file_path: &str, model: &CachedModel, animation: &Animation) {
+    let Some(source) = source_key(file_path) else {
+        return;
+    };
+
+    let data = model.encode(source, join_key(animation));
+    write_cache(file_path, &data);
+}
+
+// =============================================================
+// Skeleton and clips
+// =============================================================
+
+fn flatten(bone: &Bone, out: &mut Vec<Bone>) {
+    out.push(Bone { children: vec![], ..bone.clone() });
+    for child in bone.children.iter() {
+        flatten(child, out);
+    }
+}
+
+fn encode_bones(source: (u64, u64), skellington: &Bone, animator: &Animator) -> Vec<u8> {
+    let mut bones = vec![];
+    flatten(skellington, &mut bones);
+    bones.sort_by_key(|b| b.id);
+
+    let mut w = ByteWriter::new();
+    w.bytes(BONES_MAGIC);
+    w.u32(CACHE_VERSION);
+    w.u64(source.0);
+    w.u64(source.1);
+
+    w.u32(bones.len() as u32);
+    for b in bones.iter() {
+        w.u32(b.id);
+        w.i32(b.parent_index.map(|p| p as i32).unwrap_or(-1));
+        w.str(&b.name);
+        w.mat4(&b.offset);
+    }
+
+    w.str(&animator.current_animation.to_string());
+
+    w.u32(animator.animations.len() as u32);
+    for (anim_type, animation) in animator.animations.iter() {
+        w.str(&anim_type.to_string());
+        w.f32(animation.duration);
+        w.f32(animation.ticks_per_second);
+        w.u8(animation.looping as u8);
+
+        w.u32(animation.bone_transforms.len() as u32);
+        for (name, track) in animation.bone_transforms.iter() {
+            // import_bone_data always writes all three channels with the same timestamps
+            w.str(name);
+            w.u32(track.position_timestamps.len() as u32);
+            for i in 0..track.position_timestamps.len() {
+                w.f32(track.position_timestamps[i]);
+                w.vec3(track.positions[i]);
+                w.quat(track.rotations[i]);
+                w.vec3(track.scales[i]);
+            }
+        }
+
+        w.u32(animation.morph_tracks.len() as u32);
+        for (name, track) in animation.morph_tracks.iter() {
+            w.str(name);
+            w.u32(track.timestamps.len() as u32);
+            for (t, weight) in track.timestamps.iter().zip(track.weights.iter()) {
+                w.f32(*t);
+                w.f32(*weight);
+            }
+        }
+    }
+
+    w.buf
+}
+
+fn decode_bones(data: &[u8], source: (u64, u64)) -> Option<(Bone, Animator, Animation)> {
+    let mut r = ByteReader::new(data);
+    r.header(BONES_MAGIC)?;
+    if (r.u64()?, r.u64()?) != source {
+        return None;
+    }
+
+    let mut bones_no_children = vec![];
+    for _ in 0..r.u32()? {
+        let id = r.u32()?;
+        let parent = r.i32()?;
+        bones_no_children.push(Bone {
+            id,
+            parent_index: if parent < 0 { None } else { Some(parent as u32) },
+            name: r.str()?,
+            offset: r.mat4()?,
+            children: vec![],
+        });
+    }
+
+    let model_animation_join: Vec<BoneJoinInfo> = bones_no_children
+        .iter()
+        .map(|b| BoneJoinInfo { name: b.name.clone() })
+        .collect();
+
+    let current = AnimationType::from_str(&r.str()?)?;
+    let mut animator = Animator::new();
+
+    for _ in 0..r.u32()? {
+        let anim_type = AnimationType::from_str(&r.str()?)?;
+
+        let mut animation = Animation::default();
+        animation.model_animation_join = model_animation_join.clone();
+        animation.duration = r.f32()?;
+        animation.ticks_per_second = r.f32()?;
+        animation.looping = r.u8()? != 0;
+
+        for b in bones_no_children.iter() {
+            animation.current_pose.push(b.offset);
+            animation.current_locals.push(Mat4::IDENTITY);
+            animation.current_globals.push(Mat4::IDENTITY);
+        }
+
+        for _ in 0..r.u32()? {
+            let name = r.str()?;
+            let mut track = BoneTransformTrack::default();
+
+            for _ in 0..r.u32()? {
+                let t = r.f32()?;
+                track.position_timestamps.push(t);
+                track.rotation_timestamps.push(t);
+                track.scale_timestamps.push(t);
+
+                track.positions.push(r.vec3()?);
+                track.rotations.push(r.quat()?);
+                track.scales.push(r.vec3()?);
+            }
+
+            animation.bone_transforms.insert(name, track);
+        }
+
+        for _ in 0..r.u32()? {
+            let name = r.str()?;
+            let mut track = MorphWeightTrack::default();
+
+            for _ in 0..r.u32()? {
+                track.timestamps.push(r.f32()?);
+                track.weights.push(r.f32()?);
+            }
+
+            animation.morph_tracks.insert(name, track);
+        }
+
+        animator.animations.insert(anim_type, animation);
+    }
+
+    let animation = animator.animations.get(&current)?.clone();
+    animator.set_current_animation(current.clone());
+    animator.set_next_animation(current);
+
+    Some((build_bone_hierarchy_top_down(bones_no_children), animator, animation))
+}
+
+pub fn load_bones(file_path: &str) -> Option<(Bone, Animator, Animation)> {
+    let data = fs::read(cache_path(file_path)).ok()?;
+    let Some(result) = decode_bones(&data, source_key(file_path)?) else {
+        discard_stale_cache(file_path);
+        return None;
+    };
+
+    println!("Loaded {} from cache", file_path);
+    Some(result)
+}
+
+pub fn save_bones(file_path: &str, skellington: &Bone, animator: &Animator) {
+    let Some(source) = source_key(file_path) else {
+        return;
+    };
+
+    let data = encode_bones(source, skellington, animator);
+    write_cache(file_path, &data);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::animation::animation::{parse_bone_data, parse_model_data};
+
+    use super::*;
+
+    const SOURCE: (u64, u64) = (1234, 0xfeed);
+    const JOIN: u64 = 42;
+
+    fn vertex(x: f32, bone: i32) -> Vertex {
+        let mut bone_ids = [-1; MAX_BONE_INFLUENCE];
+        let mut bone_weights = [0.0; MAX_BONE_INFLUENCE];
+        bone_ids[0] = bone;
+        bone_weights[0] = 1.0;
+        Vertex {
+            position: Vec3::new(x, 1.0, -2.0),
+            normal: Vec3::Y,
+            uv: Vec2::new(x, 0.5),
+            tangent: Vec4::new(1.0, 0.0, 0.0, -1.0),
+            bone_ids,
+            bone_weights,
+        }
+    }
+
+    fn cached_model() -> CachedModel {
+        CachedModel {
+            materials: vec![
+                ("body".to_string(), 0.05, vec![(0, "body_diffuse.png".to_string()), (1, "body_normal.png".to_string())]),
+                ("eyes".to_string(), 0.0, vec![]),
+            ],
+            submeshes: vec![
+                ("body".to_string(), 0, 3, 0, Mat4::IDENTITY),
+                ("eyes".to_string(), 3, 3, 1, Mat4::from_translation(Vec3::new(0.0, 1.7, 0.1))),
+            ],
+            vertices: vec![vertex(0.0, 0), vertex(1.0, 1), vertex(2.0, 1), vertex(3.0, 0)],
+            indices: vec![0, 1, 2, 1, 2, 3],
+            morph_targets: vec![("blink".to_string(), vec![(3, Vec3::new(0.0, -0.01, 0.0), Vec3::ZERO)])],
+        }
+    }
+
+    fn skeleton() -> (Bone, Animator) {
+        let child = Bone { id: 1, parent_index: Some(0), name: "spine".to_string(), offset: Mat4::from_translation(Vec3::Y), children: vec![] };
+        let root = Bone { id: 0, parent_index: None, name: "hips".to_string(), offset: Mat4::IDENTITY, children: vec![child] };
+
+        let mut track = BoneTransformTrack::default();
+        for (i, t) in [0.0, 12.5].into_iter().enumerate() {
+            track.position_timestamps.push(t);
+            track.rotation_timestamps.push(t);
+            track.scale_timestamps.push(t);
+            track.positions.push(Vec3::new(i as f32, 0.0, 0.0));
+            track.rotations.push(Quat::from_rotation_y(i as f32));
+            track.scales.push(Vec3::ONE);
+        }
+        let mut morph = MorphWeightTrack::default();
+        morph.timestamps = vec![0.0, 6.0, 12.5];
+        morph.weights = vec![0.0, 1.0, 0.0];
+
+        let mut animation = Animation::default();
+        animation.duration = 12.5;
+        animation.ticks_per_second = 25.0;
+        animation.looping = true;
+        animation.bone_transforms.insert("spine".to_string(), track);
+        animation.morph_tracks.insert("blink".to_string(), morph);
+
+        let mut animator = Animator::new();
+        animator.animations.insert(AnimationType::Idle, animation);
+        (root, animator)
+    }
+
+    fn bump_version(data: &mut [u8]) {
+        data[4..8].copy_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+    }
+
+    #[test]
+    fn model_round_trip() {
+        let cached = cached_model();
+        let data = cached.encode(SOURCE, JOIN);
+
+        assert_eq!(CachedModel::decode(&data, SOURCE, JOIN), Some(cached));
+    }
+
+    #[test]
+    fn model_rejects_other_source_join_or_version() {
+        let mut data = cached_model().encode(SOURCE, JOIN);
+
+        assert_eq!(CachedModel::decode(&data, (SOURCE.0, SOURCE.1 + 1), JOIN), None);
+        assert_eq!(CachedModel::decode(&data, SOURCE, JOIN + 1), None);
+        bump_version(&mut data);
+        assert_eq!(CachedModel::decode(&data, SOURCE, JOIN), None);
+    }
+
+    #[test]
+    fn bones_round_trip() {
+        let (root, animator) = skeleton();
+        let data = encode_bones(SOURCE, &root, &animator);
+        let (decoded_root, decoded, _) = decode_bones(&data, SOURCE).expect("bones decode");
+
+        let (mut expected, mut actual) = (vec![], vec![]);
+        flatten(&root, &mut expected);
+        flatten(&decoded_root, &mut actual);
+        actual.sort_by_key(|b| b.id);
+        assert_eq!(expected.len(), actual.len());
+        for (e, a) in expected.iter().zip(actual.iter()) {
+            assert_eq!((e.id, e.parent_index, &e.name, e.offset), (a.id, a.parent_index, &a.name, a.offset));
+        }
+
+        let counts = |a: &Animator| a.animations.iter().map(|(k, a)| (k.clone(), (a.bone_transforms.len(), a.morph_tracks.len()))).collect::<HashMap<_, _>>();
+        assert_eq!(counts(&animator), counts(&decoded));
+
+        let (original, cached) = (&animator.animations[&AnimationType::Idle], &decoded.animations[&AnimationType::Idle]);
+        assert_eq!((original.duration, original.ticks_per_second, original.looping), (cached.duration, cached.ticks_per_second, cached.looping));
+        let (o, c) = (&original.bone_transforms["spine"], &cached.bone_transforms["spine"]);
+        assert_eq!((&o.position_timestamps, &o.positions, &o.rotations, &o.scales), (&c.position_timestamps, &c.positions, &c.rotations, &c.scales));
+        let (o, c) = (&original.morph_tracks["blink"], &cached.morph_tracks["blink"]);
+        assert_eq!((&o.timestamps, &o.weights), (&c.timestamps, &c.weights));
+    }
+
+    const BONES_FIXTURE: &str = "WiseModel
+BONECOUNT: 2
+BONE_NAME: hips
+PARENT_INDEX: -1
+OFFSET_MATRIX:
+1 0 0 0
+0 1 0 0
+0 0 1 0
+0 0 0 1
+BONE_NAME: spine
+PARENT_INDEX: 0
+OFFSET_MATRIX:
+1 0 0 0
+0 1 0 0
+0 0 1 0
+0 -1 0 1
+ANIMATION_NAME: Death
+DURATION: 2
+FPS: 24
+TIMESTAMP: 0
+0 0 0
+0 0 0 1
+1 1 1
+
+0 1 0
+0 0 0 1
+1 1 1
+
+TIMESTAMP: 1
+0 0 0
+0 0 0 1
+1 1 1
+
+0 1 0
+0 0 0 1
+1 1 1
+
+TIMESTAMP: 2
+0 -0.5 0
+0.7071068 0 0 0.7071068
+1 1 1
+
+0 1 0
+0 0 0 1
+1 1 1
+
+ANIMATION_NAME: Idle
+DURATION: 1
+FPS: 30
+MORPH_KEY: body_0 0 0
+MORPH_KEY: body_0 1 0.5
+TIMESTAMP: 0
+0 0 0
+0 0 0 1
+1 1 1
+
+0 1 0
+0 0 0 1
+1 1 1
+
+TIMESTAMP: 0
+0 0.1 0
+0 0 0 1
+1 1 1
+
+0 1 0
+0 0.3826834 0 0.9238795
+1 1 1
+
+TIMESTAMP: 1
+0 0 0
+0 0 0 1
+1 1 1
+
+0 1 0
+0 0 0 1
+1 1 1
+
+";
+
+    const MODEL_FIXTURE: &str = "MESH_NAME: fixture
+VERTEX_COUNT: 4
+MATERIAL: body
+TEXTURE_DIFFUSE: body_diffuse.png
+TEXTURE_NORMAL: body_normal.png
+PARALLAX_SCALE: 0.02
+MATERIAL: eyes
+VERT:
+0 0 0
+0 0 1
+0 0
+hips 1.000000
+
+VERT:
+1 0 0
+0 0 1
+1 0
+hips 0.750000 spine 0.250000
+
+VERT:
+1 1 0
+0 0 1
+1 1
+spine 1.000000
+
+VERT:
+0 1 0
+0 0 1
+0 1
+WEIGHTS: None
+
+MORPH_TARGET: body_0
+DELTA_COUNT: 1
+2 0.00000 0.10000 0.00000 0.00000 0.00000 0.00000
+
+SUBMESH: body 0
+INDEX_COUNT: 3
+0 1 2
+
+SUBMESH: eyes 1
+NODE_TRANSFORM:
+1 0 0 0
+0 1 0 0
+0 0 1 0
+0 1.7 0.1 1
+INDEX_COUNT: 3
+0 2 3
+";
+
+    // Animation has no PartialEq, Debug prints floats exactly so this compares them bit for bit
+    fn clip_summary(animation: &Animation) -> String {
+        let mut tracks: Vec<String> = animation.bone_transforms.iter().map(|(name, t)| format!("{} {:?}", name, t)).collect();
+        tracks.sort();
+        let mut morphs: Vec<String> = animation.morph_tracks.iter().map(|(name, t)| format!("{} {:?}", name, t)).collect();
+        morphs.sort();
+        let join: Vec<&str> = animation.model_animation_join.iter().map(|j| j.name.as_str()).collect();
+
+        format!(
+            "{} {} {} {:?} {:?} {:?} {:?}",
+            animation.duration, animation.ticks_per_second, animation.looping, join, animation.current_pose, tracks, morphs,
+        )
+    }
+
+    fn write_fixture(name: &str, contents: &str) -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("wise_cache_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join(format!("{}.txt", name)).to_string_lossy().to_string();
+        fs::write(&file_path, contents).unwrap();
+        (dir, file_path)
+    }
+
+    #[test]
+    fn cached_bones_match_text_loader() {
+        let (dir, file_path) = write_fixture("bones", BONES_FIXTURE);
+        let (root, animator, bind) = parse_bone_data(&file_path).expect("fixture parses");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let data = encode_bones(SOURCE, &root, &animator);
+        let (cached_root, cached, cached_bind) = decode_bones(&data, SOURCE).expect("bones decode");
+
+        let (mut expected, mut actual) = (vec![], vec![]);
+        flatten(&root, &mut expected);
+        flatten(&cached_root, &mut actual);
+        let summary = |bones: &[Bone]| bones.iter().map(|b| format!("{} {:?} {} {:?}", b.id, b.parent_index, b.name, b.offset)).collect::<Vec<_>>();
+        assert_eq!(summary(&expected), summary(&actual));
+
+        assert_eq!(animator.current_animation, cached.current_animation);
+        assert_eq!(animator.animations.len(), cached.animations.len());
+        for (anim_type, animation) in animator.animations.iter() {
+            assert_eq!(clip_summary(animation), clip_summary(&cached.animations[anim_type]), "{}", anim_type);
+        }
+
+        // The throwaway first key is gone from the bind animation on both paths
+        assert_eq!(bind.bone_transforms["spine"].positions.len(), 2);
+        assert_eq!(clip_summary(&bind), clip_summary(&cached_bind));
+    }
+
+    #[test]
+    fn cached_model_matches_text_loader() {
+        let (dir, bones_path) = write_fixture("skinned_bones", BONES_FIXTURE);
+        let (_, _, bind) = parse_bone_data(&bones_path).expect("fixture parses");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (dir, file_path) = write_fixture("model", MODEL_FIXTURE);
+        let parsed = parse_model_data(&file_path, &bind).expect("fixture parses");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(parsed.vertices[1].bone_ids[..2], [0, 1]);
+        assert_eq!(parsed.materials[0].2, vec![(1, "body_diffuse.png".to_string()), (4, "body_normal.png".to_string())]);
+
+        let data = parsed.encode(SOURCE, join_key(&bind));
+        assert_eq!(CachedModel::decode(&data, SOURCE, join_key(&bind)).as_ref(), Some(&parsed));
+    }
+
+    #[test]
+    fn stale_bone_cache_is_dropped() {
+        let dir = std::env::temp_dir().join(format!("wise_cache_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("skeleton.txt").to_string_lossy().to_string();
+        fs::write(&file_path, "source contents").unwrap();
+
+        let (root, animator) = skeleton();
+        let mut data = encode_bones(source_key(&file_path).unwrap(), &root, &animator);
+        bump_version(&mut data);
+        fs::write(cache_path(&file_path), &data).unwrap();
+
+        assert!(load_bones(&file_path).is_none());
+        assert!(!std::path::Path::new(&cache_path(&file_path)).exists());
+
+        save_bones(&file_path, &root, &animator);
+        assert!(load_bones(&file_path).is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
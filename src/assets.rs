@@ -0,0 +1,594 @@
+use std::{cmp::Ordering, collections::{HashMap, HashSet}, ffi::c_void, fmt, hash::{Hash, Hasher}, marker::PhantomData};
+
+use gl::types::GLenum;
+use glam::{Mat4, Quat, Vec3};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+
+use crate::{animation::{animation::{import_bone_data, import_model_data, upload_texture, Animation, Animator, Bone, BoneJoinInfo, BoneTransformTrack, Model}, gltf_import::{import_gltf_bone_data, import_gltf_model_data, is_gltf}}, debug::gizmos::Cuboid, enums_types::{AnimationType, ShaderType, TextureType}, error::{LoadError, LoadResult}, gl_call, shaders::Shader};
+
+// =============================================================
+// Handles
+// =============================================================
+
+/// Typed index into an AssetStore. The generation makes handles to a freed slot stop resolving
+/// instead of silently pointing at whatever got loaded into the slot afterwards.
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+// Manual impls so T doesn't need to be Copy/Eq/etc. itself
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+// Only meaningful for sorting draws so the same mesh ends up back to back
+impl<T> PartialOrd for Handle<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Handle<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.index, self.generation).cmp(&(other.index, other.generation))
+    }
+}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+pub type MeshHandle = Handle<Model>;
+pub type TextureHandle = Handle<u32>;
+pub type SkeletonHandle = Handle<SkeletonAsset>;
+pub type ShaderHandle = Handle<Shader>;
+
+// =============================================================
+// Store
+// =============================================================
+
+struct Slot<T> {
+    value: T,
+    path: Option<String>,
+    refs: u32,
+}
+
+pub struct AssetStore<T> {
+    slots: Vec<Option<Slot<T>>>,
+    generations: Vec<u32>,
+    free: Vec<usize>,
+    by_path: HashMap<String, Handle<T>>,
+}
+
+impl<T> AssetStore<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![],
+            generations: vec![],
+            free: vec![],
+            by_path: HashMap::new(),
+        }
+    }
+
+    /// Inserts with a reference count of 1. Assets without a path (generated meshes etc.) are never
+    /// shared.
+    pub fn insert(&mut self, value: T, path: Option<&str>) -> Handle<T> {
+        let slot = Slot {
+            value,
+            path: path.map(|p| p.to_string()),
+            refs: 1,
+        };
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Some(slot);
+                index
+            }
+            None => {
+                self.slots.push(Some(slot));
+                self.generations.push(0);
+                self.slots.len() - 1
+            }
+        };
+
+        let handle = Handle {
+            index: index as u32,
+            generation: self.generations[index],
+            _marker: PhantomData,
+        };
+
+        if let Some(path) = path {
+            self.by_path.insert(path.to_string(), handle);
+        }
+
+        handle
+    }
+
+    fn slot(&self, handle: Handle<T>) -> Option<&Slot<T>> {
+        if self.generations.get(handle.index as usize) != Some(&handle.generation) {
+            return None;
+        }
+        self.slots[handle.index as usize].as_ref()
+    }
+
+    fn slot_mut(&mut self, handle: Handle<T>) -> Option<&mut Slot<T>> {
+        if self.generations.get(handle.index as usize) != Some(&handle.generation) {
+            return None;
+        }
+        self.slots[handle.index as usize].as_mut()
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.slot(handle).map(|s| &s.value)
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        self.slot_mut(handle).map(|s| &mut s.value)
+    }
+
+    /// Looks up an already loaded asset by path and bumps its reference count.
+    pub fn acquire_path(&mut self, path: &str) -> Option<Handle<T>> {
+        let handle = *self.by_path.get(path)?;
+        self.slot_mut(handle)?.refs += 1;
+        Some(handle)
+    }
+
+    /// Drops one reference, returns the value once nothing references it anymore so the caller can
+    /// free whatever gpu resources it holds.
+    pub fn release(&mut self, handle: Handle<T>) -> Option<T> {
+        let slot = self.slot_mut(handle)?;
+        slot.refs -= 1;
+        if slot.refs > 0 {
+            return None;
+        }
+
+        let index = handle.index as usize;
+        let slot = self.slots[index].take().unwrap();
+        self.generations[index] += 1;
+        self.free.push(index);
+
+        if let Some(path) = &slot.path {
+            self.by_path.remove(path);
+        }
+
+        Some(slot.value)
+    }
+
+    pub fn ref_count(&self, handle: Handle<T>) -> u32 {
+        self.slot(handle).map(|s| s.refs).unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().flatten().map(|s| &mut s.value)
+    }
+
+    /// (handle, path, reference count) for every live asset, mostly for the imgui assets window.
+    pub fn entries(&self) -> Vec<(Handle<T>, Option<&str>, u32)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| {
+                slot.as_ref().map(|s| {
+                    let handle = Handle {
+                        index: i as u32,
+                        generation: self.generations[i],
+                        _marker: PhantomData,
+                    };
+                    (handle, s.path.as_deref(), s.refs)
+                })
+            })
+            .collect()
+    }
+}
+
+// =============================================================
+// Assets
+// =============================================================
+
+/// The shared part of an animated entity: skeleton and clips as loaded. Every entity gets its own
+/// copy of the Bone tree and Animator from instantiate() since those carry playback state.
+pub struct SkeletonAsset {
+    pub bone: Bone,
+    pub animator: Animator,
+    // What import_bone_data hands back for import_model_data to map bone names to ids
+    pub bind_animation: Animation,
+}
+
+impl SkeletonAsset {
+    pub fn instantiate(&self) -> (Bone, Animator) {
+        (self.bone.clone(), self.animator.clone())
+    }
+}
+
+/// Central store for everything loaded off disk. Model textures still belong to their mesh and get
+/// freed with it, the texture store is for standalone textures (ui, particles).
+/// Shaders live in the Renderer's Shaders since it gets built before the EntityManager owning this.
+///
+/// Loads never fail from the caller's point of view: anything that can't be loaded is swapped for
+/// a placeholder (magenta box, single bone skeleton, magenta texture) and the error is kept in
+/// errors for GameState to report.
+pub struct Assets {
+    pub meshes: AssetStore<Model>,
+    pub textures: AssetStore<u32>,
+    pub skeletons: AssetStore<SkeletonAsset>,
+
+    pub errors: Vec<LoadError>,
+    // Paths that already failed once, so every entity using them doesn't report the same error again
+    failed: HashSet<String>,
+}
+
+const PLACEHOLDER_MESH: &str = "<placeholder mesh>";
+const PLACEHOLDER_SKELETON: &str = "<placeholder skeleton>";
+const PLACEHOLDER_TEXTURE: &str = "<placeholder texture>";
+const PLACEHOLDER_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+impl Assets {
+    pub fn new() -> Self {
+        Self {
+            meshes: AssetStore::new(),
+            textures: AssetStore::new(),
+            skeletons: AssetStore::new(),
+
+            errors: vec![],
+            failed: HashSet::new(),
+        }
+    }
+
+    fn record_failure(&mut self, path: &str, e: LoadError) {
+        println!("WARNING: {}, using a placeholder", e);
+        self.failed.insert(path.to_string());
+        self.errors.push(e);
+    }
+
+    // Meshes
+    // ============================================================
+    pub fn load_mesh(&mut self, path: &str, animation: &Animation) -> MeshHandle {
+        if let Some(handle) = self.meshes.acquire_path(path) {
+            return handle;
+        }
+        if self.failed.contains(path) {
+            return self.placeholder_mesh();
+        }
+
+        match import_mesh(path, animation) {
+            Ok(model) => self.meshes.insert(model, Some(path)),
+            Err(e) => {
+                self.record_failure(path, e);
+                self.placeholder_mesh()
+            }
+        }
+    }
+
+    /// Same as load_mesh but maps the vertex bone names against an already loaded skeleton.
+    pub fn load_skinned_mesh(&mut self, path: &str, skeleton: SkeletonHandle) -> MeshHandle {
+        if let Some(handle) = self.meshes.acquire_path(path) {
+            return handle;
+        }
+        if self.failed.contains(path) {
+            return self.placeholder_mesh();
+        }
+
+        let animation = &self.skeletons.get(skeleton).unwrap().bind_animation;
+        match import_mesh(path, animation) {
+            Ok(model) => self.meshes.insert(model, Some(path)),
+            Err(e) => {
+                self.record_failure(path, e);
+                self.placeholder_mesh()
+            }
+        }
+    }
+
+    fn placeholder_mesh(&mut self) -> MeshHandle {
+        if let Some(handle) = self.meshes.acquire_path(PLACEHOLDER_MESH) {
+            return handle;
+        }
+
+        let mut model = Cuboid { w: 1.0, h: 2.0, d: 1.0 }.build_model();
+        model.full_path = PLACEHOLDER_MESH.to_string();
+        // Fully weighted to bone 0 so it also follows the root when drawn as an animated model
+        for vertex in model.vertices.iter_mut() {
+            vertex.bone_ids[0] = 0;
+            vertex.bone_weights[0] = 1.0;
+        }
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba(PLACEHOLDER_COLOR)));
+        upload_texture(&mut model.materials[0], &img, PLACEHOLDER_MESH.to_string(), TextureType::Diffuse);
+        model.setup_opengl();
+
+        self.meshes.insert(model, Some(PLACEHOLDER_MESH))
+    }
+
+    /// For meshes built in code (gizmos and such), these are never shared.
+    pub fn add_mesh(&mut self, model: Model) -> MeshHandle {
+        self.meshes.insert(model, None)
+    }
+
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&Model> {
+        self.meshes.get(handle)
+    }
+
+    pub fn release_mesh(&mut self, handle: MeshHandle) {
+        if let Some(model) = self.meshes.release(handle) {
+            model.release_gpu();
+        }
+    }
+
+    // Skeletons
+    // ============================================================
+    pub fn load_skeleton(&mut self, path: &str) -> SkeletonHandle {
+        if let Some(handle) = self.skeletons.acquire_path(path) {
+            return handle;
+        }
+        if self.failed.contains(path) {
+            return self.placeholder_skeleton();
+        }
+
+        let loaded = if is_gltf(path) {
+            import_gltf_bone_data(path)
+        } else {
+            import_bone_data(path)
+        };
+
+        match loaded {
+            Ok((bone, animator, bind_animation)) => {
+                let asset = SkeletonAsset {
+                    bone,
+                    animator,
+                    bind_animation,
+                };
+
+                self.skeletons.insert(asset, Some(path))
+            }
+            Err(e) => {
+                self.record_failure(path, e);
+                self.placeholder_skeleton()
+            }
+        }
+    }
+
+    /// A single root bone that holds still, with every AnimationType present so state machines can
+    /// switch clips on it like on a real skeleton.
+    fn placeholder_skeleton(&mut self) -> SkeletonHandle {
+        if let Some(handle) = self.skeletons.acquire_path(PLACEHOLDER_SKELETON) {
+            return handle;
+        }
+
+        let bone = Bone {
+            id: 0,
+            parent_index: None,
+            name: "root".to_string(),
+            offset: Mat4::IDENTITY,
+            children: vec![],
+        };
+
+        let mut animation = Animation::default();
+        animation.duration = 1.0;
+        animation.ticks_per_second = 1.0;
+        animation.model_animation_join = vec![BoneJoinInfo { name: bone.name.clone() }];
+        animation.current_pose = vec![Mat4::IDENTITY];
+        animation.current_locals = vec![Mat4::IDENTITY];
+        animation.current_globals = vec![Mat4::IDENTITY];
+
+        let mut track = BoneTransformTrack::default();
+        for time in [0.0, 1.0] {
+            track.position_timestamps.push(time);
+            track.rotation_timestamps.push(time);
+            track.scale_timestamps.push(time);
+            track.positions.push(Vec3::ZERO);
+            track.rotations.push(Quat::IDENTITY);
+            track.scales.push(Vec3::ONE);
+        }
+        animation.bone_transforms.insert(bone.name.clone(), track);
+
+        let mut animator = Animator::new();
+        for anim_type in [AnimationType::Idle, AnimationType::Run, AnimationType::Dance, AnimationType::Death] {
+            let mut clip = animation.clone();
+            clip.looping = anim_type != AnimationType::Death;
+            animator.animations.insert(anim_type, clip);
+        }
+
+        let asset = SkeletonAsset {
+            bone,
+            animator,
+            bind_animation: animation,
+        };
+
+        self.skeletons.insert(asset, Some(PLACEHOLDER_SKELETON))
+    }
+
+    pub fn skeleton(&self, handle: SkeletonHandle) -> Option<&SkeletonAsset> {
+        self.skeletons.get(handle)
+    }
+
+    pub fn release_skeleton(&mut self, handle: SkeletonHandle) {
+        self.skeletons.release(handle);
+    }
+
+    // Textures
+    // ============================================================
+    /// filter is only used the first time a path gets loaded.
+    pub fn load_texture(&mut self, path: &str, filter: GLenum) -> TextureHandle {
+        if let Some(handle) = self.textures.acquire_path(path) {
+            return handle;
+        }
+        if self.failed.contains(path) {
+            return self.placeholder_texture();
+        }
+
+        match create_2d_texture(path, filter) {
+            Ok(id) => self.textures.insert(id, Some(path)),
+            Err(e) => {
+                self.record_failure(path, e);
+                self.placeholder_texture()
+            }
+        }
+    }
+
+    fn placeholder_texture(&mut self) -> TextureHandle {
+        if let Some(handle) = self.textures.acquire_path(PLACEHOLDER_TEXTURE) {
+            return handle;
+        }
+
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba(PLACEHOLDER_COLOR)));
+        let id = upload_2d_texture(&img, gl::NEAREST);
+        self.textures.insert(id, Some(PLACEHOLDER_TEXTURE))
+    }
+
+    pub fn texture_id(&self, handle: TextureHandle) -> u32 {
+        self.textures.get(handle).copied().unwrap_or(0)
+    }
+
+    pub fn release_texture(&mut self, handle: TextureHandle) {
+        if let Some(id) = self.textures.release(handle) {
+            unsafe {
+                gl_call!(gl::DeleteTextures(1, &id));
+            }
+        }
+    }
+}
+
+// =============================================================
+// Shaders
+// =============================================================
+
+/// The Renderer's shaders, stored by path like every other asset so passes drawing with the same
+/// .glsl share one program, and looked up by ShaderType every frame.
+pub struct Shaders {
+    pub store: AssetStore<Shader>,
+    by_type: HashMap<ShaderType, ShaderHandle>,
+}
+
+impl Shaders {
+    pub fn new() -> Self {
+        Self {
+            store: AssetStore::new(),
+            by_type: HashMap::new(),
+        }
+    }
+
+    /// Points shader_type at path, sharing the program if it's loaded already. A shader that fails
+    /// to build gets the magenta placeholder under its real path so hot reloading can swap the fixed
+    /// one in, the compile/link log goes in shader_errors until then.
+    pub fn load(&mut self, shader_type: ShaderType, path: &str, errors: &mut Vec<LoadError>, shader_errors: &mut HashMap<String, String>) -> &mut Shader {
+        let handle = match self.store.acquire_path(path) {
+            Some(handle) => handle,
+            None => {
+                let shader = match Shader::new(path) {
+                    Ok(shader) => shader,
+                    Err(e) => {
+                        if let LoadError::Shader { log, .. } = &e {
+                            shader_errors.insert(path.to_string(), log.clone());
+                        }
+                        errors.push(e);
+                        Shader::placeholder(path)
+                    }
+                };
+                self.store.insert(shader, Some(path))
+            }
+        };
+
+        if let Some(old) = self.by_type.insert(shader_type, handle) {
+            self.release_handle(old);
+        }
+
+        self.store.get_mut(handle).unwrap()
+    }
+
+    pub fn get(&self, shader_type: &ShaderType) -> Option<&Shader> {
+        self.store.get(*self.by_type.get(shader_type)?)
+    }
+
+    pub fn get_mut(&mut self, shader_type: &ShaderType) -> Option<&mut Shader> {
+        self.store.get_mut(*self.by_type.get(shader_type)?)
+    }
+
+    /// Every loaded program once, however many ShaderTypes share it.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Shader> {
+        self.store.values_mut()
+    }
+
+    /// Deletes the program once no ShaderType points at it anymore.
+    fn release_handle(&mut self, handle: ShaderHandle) {
+        if let Some(shader) = self.store.release(handle) {
+            unsafe {
+                gl_call!(gl::DeleteProgram(shader.id));
+            }
+        }
+    }
+}
+
+fn import_mesh(path: &str, animation: &Animation) -> LoadResult<Model> {
+    if is_gltf(path) {
+        import_gltf_model_data(path, animation)
+    } else {
+        import_model_data(path, animation)
+    }
+}
+
+pub fn create_2d_texture(path: &str, filter: GLenum) -> LoadResult<u32> {
+    let img = image::open(path).map_err(|e| LoadError::Image {
+        path: path.to_string(),
+        message: e.to_string(),
+    })?;
+
+    Ok(upload_2d_texture(&img, filter))
+}
+
+fn upload_2d_texture(img: &DynamicImage, filter: GLenum) -> u32 {
+    let mut texture_id = 0;
+
+    let (img_width, img_height) = img.dimensions();
+    let rgba = img.to_rgba8();
+    let raw = rgba.as_raw();
+
+    unsafe {
+        gl_call!(gl::GenTextures(1, &mut texture_id));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture_id));
+        gl_call!(gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as i32,
+            img_width as i32,
+            img_height as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            raw.as_ptr() as *const c_void
+        ));
+
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32));
+        gl_call!(gl::GenerateMipmap(gl::TEXTURE_2D));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    }
+
+    texture_id
+}
@@ -0,0 +1,605 @@
+// Offline converter from anything assimp can read (FBX/OBJ/DAE/...) to the WiseModel text format
+// read by import_model_data/import_bone_data. Produces the same files the blender export scripts in
+// resources/blender/export do (plus MATERIAL/SUBMESH blocks, which the scripts don't write yet), so
+// it has to stay in sync with those and with the importer.
+//
+// Usage: wise-convert <input> <output dir> [--name NAME] [--scale F] [--z-up] [--fps N]
+use std::{collections::{HashMap, HashSet}, fmt::Write, fs, path::Path, process, rc::Rc};
+
+use glam::{Mat3, Mat4, Quat, Vec3};
+use russimp::{animation::{MeshMorphAnim, NodeAnim}, material::TextureType, node::Node, scene::{PostProcess, Scene}, Matrix4x4, Vector3D};
+
+// Keep in sync with some_data.rs
+const MAX_BONE_INFLUENCE: usize = 4;
+
+// Only used when the skeleton has more than one root bone, the importer wants exactly one.
+const SYNTHETIC_ROOT_NAME: &str = "__wise_root";
+
+const KNOWN_ANIMATIONS: [&str; 4] = ["Run", "Idle", "Death", "Dance"];
+
+struct Options {
+    input: String,
+    output_dir: String,
+    name: String,
+    scale: f32,
+    z_up: bool,
+    fps: f32,
+}
+
+struct ConvertedBone {
+    name: String,
+    parent_index: i32,
+    offset: Mat4,
+    // Non-bone nodes between the parent bone (or the scene root) and this bone with their bind
+    // transforms, outermost first. Baked into the keys, FBX imports are full of these ($AssimpFbx$
+    // pivots) and some of them are animated.
+    pivots: Vec<(String, Mat4)>,
+    // Top of the file's own skeleton, picks up the axis and scale conversion
+    is_root: bool,
+    bind_local: Mat4,
+}
+
+fn main() {
+    let options = parse_args();
+
+    let scene = Scene::from_file(
+        &options.input,
+        vec![
+            PostProcess::Triangulate,
+            PostProcess::JoinIdenticalVertices,
+            PostProcess::GenerateSmoothNormals,
+            PostProcess::LimitBoneWeights,
+        ],
+    ).unwrap_or_else(|e| {
+        eprintln!("Failed to load {}: {}", options.input, e);
+        process::exit(1);
+    });
+
+    let Some(root) = scene.root.clone() else {
+        eprintln!("{} has no root node", options.input);
+        process::exit(1);
+    };
+
+    fs::create_dir_all(&options.output_dir).expect("Could not create output directory");
+
+    let conversion = conversion_matrix(&options);
+    let bones = collect_bones(&scene, &root);
+
+    // Morph weights live in the bone file next to the clips, without a skeleton there is no file
+    if bones.is_empty() && scene.animations.iter().any(|a| !a.morph_mesh_channels.is_empty()) {
+        eprintln!("{} has morph target animation but no skeleton, the WiseModel format can only store morph weights in the bone file", options.input);
+        process::exit(1);
+    }
+
+    let model_path = format!("{}/{}_model_FINAL.txt", options.output_dir, options.name);
+    let model_text = write_model(&scene, &root, &options, conversion, &bones);
+    fs::write(&model_path, model_text).expect("Could not write model file");
+    println!("Wrote {}", model_path);
+
+    if bones.is_empty() {
+        println!("No bones found, skipping the bone file");
+        return;
+    }
+
+    let bones_path = format!("{}/{}_bones_FINAL.txt", options.output_dir, options.name);
+    let bones_text = write_bones(&scene, &root, &bones, &options, conversion);
+    fs::write(&bones_path, bones_text).expect("Could not write bones file");
+    println!("Wrote {}", bones_path);
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: wise-convert <input> <output dir> [--name NAME] [--scale F] [--z-up] [--fps N]");
+    eprintln!("  --name   base name for <name>_model_FINAL.txt / <name>_bones_FINAL.txt (default: input file stem)");
+    eprintln!("  --scale  uniform unit conversion, e.g. 0.01 for centimeter FBX files (default: 1)");
+    eprintln!("  --z-up   convert from a Z up source to our Y up");
+    eprintln!("  --fps    rate the animations get resampled at (default: 30)");
+    process::exit(1);
+}
+
+fn parse_args() -> Options {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut positional = vec![];
+    let mut options = Options {
+        input: String::new(),
+        output_dir: String::new(),
+        name: String::new(),
+        scale: 1.0,
+        z_up: false,
+        fps: 30.0,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = |i: usize| args.get(i + 1).cloned().unwrap_or_else(|| usage());
+
+        match args[i].as_str() {
+            "--name" => { options.name = value(i); i += 1; }
+            "--scale" => { options.scale = value(i).parse().unwrap_or_else(|_| usage()); i += 1; }
+            "--fps" => { options.fps = value(i).parse().unwrap_or_else(|_| usage()); i += 1; }
+            "--z-up" => options.z_up = true,
+            "-h" | "--help" => usage(),
+            other => positional.push(other.to_string()),
+        }
+        i += 1;
+    }
+
+    if positional.len() != 2 {
+        usage();
+    }
+
+    options.input = positional[0].clone();
+    options.output_dir = positional[1].clone();
+    if options.name.is_empty() {
+        options.name = Path::new(&options.input).file_stem().unwrap().to_string_lossy().to_string();
+    }
+
+    options
+}
+
+// =============================================================
+// Math helpers
+// =============================================================
+
+// assimp matrices are row major
+fn to_mat4(m: &Matrix4x4) -> Mat4 {
+    Mat4::from_cols_array(&[
+        m.a1, m.b1, m.c1, m.d1,
+        m.a2, m.b2, m.c2, m.d2,
+        m.a3, m.b3, m.c3, m.d3,
+        m.a4, m.b4, m.c4, m.d4,
+    ])
+}
+
+fn to_vec3(v: &Vector3D) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
+
+fn conversion_matrix(options: &Options) -> Mat4 {
+    let rotation = if options.z_up {
+        // (x, y, z) -> (x, z, -y), same as the axis_conversion in the blender scripts
+        Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2)
+    } else {
+        Mat4::IDENTITY
+    };
+
+    Mat4::from_scale(Vec3::splat(options.scale)) * rotation
+}
+
+fn global_transform(node: &Node) -> Mat4 {
+    match node.parent.upgrade() {
+        Some(parent) => global_transform(&parent) * to_mat4(&node.transformation),
+        None => to_mat4(&node.transformation),
+    }
+}
+
+// =============================================================
+// Skeleton
+// =============================================================
+
+fn collect_bones(scene: &Scene, root: &Rc<Node>) -> Vec<ConvertedBone> {
+    let mut offsets: HashMap<String, Mat4> = HashMap::new();
+    for mesh in scene.meshes.iter() {
+        for bone in mesh.bones.iter() {
+            offsets.insert(bone.name.clone(), to_mat4(&bone.offset_matrix));
+        }
+    }
+
+    let mut bones = vec![];
+    if offsets.is_empty() {
+        return bones;
+    }
+
+    // Depth first so parents always get their index before their children, like pose.bones in blender.
+    fn visit(node: &Rc<Node>, parent_bone: i32, mut pivots: Vec<(String, Mat4)>, offsets: &HashMap<String, Mat4>, bones: &mut Vec<ConvertedBone>) {
+        let mut next_parent = parent_bone;
+
+        if let Some(offset) = offsets.get(&node.name) {
+            bones.push(ConvertedBone {
+                name: node.name.clone(),
+                parent_index: parent_bone,
+                offset: *offset,
+                pivots: std::mem::take(&mut pivots),
+                is_root: parent_bone == -1,
+                bind_local: to_mat4(&node.transformation),
+            });
+            next_parent = bones.len() as i32 - 1;
+        } else {
+            pivots.push((node.name.clone(), to_mat4(&node.transformation)));
+        }
+
+        for child in node.children.borrow().iter() {
+            visit(child, next_parent, pivots.clone(), offsets, bones);
+        }
+    }
+
+    visit(root, -1, vec![], &offsets, &mut bones);
+
+    let root_count = bones.iter().filter(|b| b.parent_index == -1).count();
+    if root_count > 1 {
+        println!("Found {} root bones, adding {} as a common parent", root_count, SYNTHETIC_ROOT_NAME);
+
+        // Shift everything by one so the synthetic root can be bone 0.
+        for bone in bones.iter_mut() {
+            bone.parent_index += 1;
+        }
+
+        // Identity, the conversion stays on the real roots below it so it only gets applied once
+        bones.insert(0, ConvertedBone {
+            name: SYNTHETIC_ROOT_NAME.to_string(),
+            parent_index: -1,
+            offset: Mat4::IDENTITY,
+            pivots: vec![],
+            is_root: false,
+            bind_local: Mat4::IDENTITY,
+        });
+    }
+
+    bones
+}
+
+// =============================================================
+// Model file
+// =============================================================
+
+fn write_model(scene: &Scene, root: &Rc<Node>, options: &Options, conversion: Mat4, bones: &[ConvertedBone]) -> String {
+    let mut out = String::new();
+
+    let input_dir = Path::new(&options.input).parent().unwrap_or(Path::new("."));
+
+    // Gather mesh nodes first so every mesh gets its node transform (static meshes only, skinned
+    // meshes are placed by their bones) and the closest bone above it.
+    let bone_names: HashSet<&str> = bones.iter().map(|b| b.name.as_str()).collect();
+    let mut mesh_nodes: Vec<(u32, Mat4, Option<String>)> = vec![];
+    fn gather(node: &Rc<Node>, bone_names: &HashSet<&str>, out: &mut Vec<(u32, Mat4, Option<String>)>) {
+        for mesh_index in node.meshes.iter() {
+            out.push((*mesh_index, global_transform(node), ancestor_bone(node, bone_names)));
+        }
+        for child in node.children.borrow().iter() {
+            gather(child, bone_names, out);
+        }
+    }
+    gather(root, &bone_names, &mut mesh_nodes);
+
+    // Only materials that are actually used get written, numbered in order of first use
+    let mut material_ids: HashMap<u32, usize> = HashMap::new();
+    let mut materials = String::new();
+    for (mesh_index, _, _) in mesh_nodes.iter() {
+        let material_index = scene.meshes[*mesh_index as usize].material_index;
+        if material_ids.contains_key(&material_index) {
+            continue;
+        }
+        material_ids.insert(material_index, material_ids.len());
+
+        writeln!(materials, "MATERIAL: material_{}", material_index).unwrap();
+        let Some(material) = scene.materials.get(material_index as usize) else {
+            continue;
+        };
+
+        for (texture_type, key) in [
+            (TextureType::Diffuse, "TEXTURE_DIFFUSE:"),
+            (TextureType::Specular, "TEXTURE_SPECULAR:"),
+            (TextureType::Emissive, "TEXTURE_EMISSIVE:"),
+            (TextureType::Opacity, "TEXTURE_OPACITY:"),
+            (TextureType::Normals, "TEXTURE_NORMAL:"),
+            (TextureType::Roughness, "TEXTURE_ROUGHNESS:"),
+            (TextureType::Metalness, "TEXTURE_METALNESS:"),
+            (TextureType::Displacement, "TEXTURE_DISPLACEMENT:"),
+        ] {
+            let Some(texture) = material.textures.get(&texture_type) else {
+                continue;
+            };
+
+            let file_name = texture.borrow().filename.replace('\\', "/");
+            let base_name = Path::new(&file_name).file_name().unwrap().to_string_lossy().to_string();
+            writeln!(materials, "{} {}", key, base_name).unwrap();
+
+            // The importer looks for textures next to the model file
+            let source = input_dir.join(&file_name);
+            let dest = Path::new(&options.output_dir).join(&base_name);
+            if source.exists() && !dest.exists() {
+                fs::copy(&source, &dest).expect("Could not copy texture");
+            }
+        }
+    }
+
+    let mut vertices = String::new();
+    let mut submeshes = String::new();
+    let mut morphs = String::new();
+    let mut vertex_count = 0;
+
+    for (mesh_index, node_transform, parent_bone) in mesh_nodes {
+        let mesh = &scene.meshes[mesh_index as usize];
+        // Skinned vertices get the conversion baked in since the bones place them, static ones stay
+        // in mesh space and the importer applies the node transform per submesh. Unweighted meshes
+        // in a skinned file (a sword under a hand bone, a prop next to the character) are drawn
+        // skinned too, so they get their node transform baked in and ride along rigidly on the
+        // closest bone above them, or on the root like the placeholder mesh.
+        let rigid_bone = match (mesh.bones.is_empty(), bones.first()) {
+            (true, Some(root_bone)) => Some(parent_bone.unwrap_or_else(|| root_bone.name.clone())),
+            _ => None,
+        };
+        let (transform, submesh_transform) = if !mesh.bones.is_empty() {
+            (conversion, Mat4::IDENTITY)
+        } else if rigid_bone.is_some() {
+            (conversion * node_transform, Mat4::IDENTITY)
+        } else {
+            (Mat4::IDENTITY, conversion * node_transform)
+        };
+        let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+        let mut weights: Vec<Vec<(String, f32)>> = vec![vec![]; mesh.vertices.len()];
+        for bone in mesh.bones.iter() {
+            for w in bone.weights.iter() {
+                weights[w.vertex_id as usize].push((bone.name.clone(), w.weight));
+            }
+        }
+        if let Some(bone) = &rigid_bone {
+            for vertex_weights in weights.iter_mut() {
+                vertex_weights.push((bone.clone(), 1.0));
+            }
+        }
+
+        let uvs = mesh.texture_coords.first().cloned().flatten();
+
+        for (i, v) in mesh.vertices.iter().enumerate() {
+            let position = transform.transform_point3(to_vec3(v));
+            let normal = mesh.normals.get(i).map(|n| (normal_matrix * to_vec3(n)).normalize_or_zero()).unwrap_or(Vec3::Y);
+            let uv = uvs.as_ref().map(|uvs| (uvs[i].x, uvs[i].y)).unwrap_or((0.0, 0.0));
+
+            writeln!(vertices, "VERT:").unwrap();
+            writeln!(vertices, "{:.5} {:.5} {:.5}", position.x, position.y, position.z).unwrap();
+            writeln!(vertices, "{:.5} {:.5} {:.5}", normal.x, normal.y, normal.z).unwrap();
+            writeln!(vertices, "{:.5} {:.5}", uv.0, uv.1).unwrap();
+
+            let vertex_weights = &mut weights[i];
+            vertex_weights.sort_by(|a, b| b.1.total_cmp(&a.1));
+            vertex_weights.truncate(MAX_BONE_INFLUENCE);
+
+            if vertex_weights.is_empty() {
+                writeln!(vertices, "WEIGHTS: None\n").unwrap();
+            } else {
+                let text: Vec<String> = vertex_weights.iter().map(|(bone, w)| format!("{} {:.6}", bone, w)).collect();
+                writeln!(vertices, "{}\n", text.join(" ")).unwrap();
+            }
+        }
+
+        let indices: Vec<String> = mesh.faces
+            .iter()
+            .filter(|face| face.0.len() == 3)
+            .flat_map(|face| face.0.iter().map(|i| (i + vertex_count).to_string()))
+            .collect();
+
+        writeln!(submeshes, "SUBMESH: {} {}", token(&mesh.name), material_ids[&mesh.material_index]).unwrap();
+        if submesh_transform != Mat4::IDENTITY {
+            writeln!(submeshes, "NODE_TRANSFORM:").unwrap();
+            write_matrix(&mut submeshes, &submesh_transform);
+        }
+        writeln!(submeshes, "INDEX_COUNT: {}", indices.len()).unwrap();
+        writeln!(submeshes, "{}\n", indices.join(" ")).unwrap();
+
+        // assimp only hands us absolute positions for the shape keys, so normals get no deltas.
+        for (target_index, anim_mesh) in mesh.anim_meshes.iter().enumerate() {
+            let deltas: Vec<(usize, Vec3)> = anim_mesh.0
+                .iter()
+                .zip(mesh.vertices.iter())
+                .enumerate()
+                .map(|(i, (target, base))| (i, transform.transform_vector3(to_vec3(target) - to_vec3(base))))
+                .filter(|(_, d)| d.length_squared() > 0.0)
+                .collect();
+
+            writeln!(morphs, "MORPH_TARGET: {}", morph_target_name(&mesh.name, target_index)).unwrap();
+            writeln!(morphs, "DELTA_COUNT: {}", deltas.len()).unwrap();
+            for (i, d) in deltas {
+                writeln!(morphs, "{} {:.5} {:.5} {:.5} 0.00000 0.00000 0.00000", i as u32 + vertex_count, d.x, d.y, d.z).unwrap();
+            }
+            writeln!(morphs).unwrap();
+        }
+
+        vertex_count += mesh.vertices.len() as u32;
+    }
+
+    writeln!(out, "MESH_NAME: {}", options.name).unwrap();
+    writeln!(out, "VERTEX_COUNT: {}", vertex_count).unwrap();
+    out.push_str(&materials);
+    out.push_str(&vertices);
+    out.push_str(&morphs);
+    out.push_str(&submeshes);
+
+    out
+}
+
+// Closest node above (or at) node that is a bone
+fn ancestor_bone(node: &Rc<Node>, bone_names: &HashSet<&str>) -> Option<String> {
+    if bone_names.contains(node.name.as_str()) {
+        return Some(node.name.clone());
+    }
+    ancestor_bone(&node.parent.upgrade()?, bone_names)
+}
+
+// Names go on a line split by whitespace in the importer
+fn token(name: &str) -> String {
+    if name.trim().is_empty() {
+        return "unnamed".to_string();
+    }
+    name.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+// assimp's shape keys only have an index within their mesh, MORPH_TARGET and MORPH_KEY both use this
+fn morph_target_name(mesh_name: &str, target_index: usize) -> String {
+    format!("{}_{}", token(mesh_name), target_index)
+}
+
+// =============================================================
+// Bone file
+// =============================================================
+
+fn sample_vec3(keys: &[(f64, Vec3)], t: f64, default: Vec3) -> Vec3 {
+    sample(keys, t, default, |a, b, f| a.lerp(b, f))
+}
+
+fn sample<T: Copy>(keys: &[(f64, T)], t: f64, default: T, lerp: impl Fn(T, T, f32) -> T) -> T {
+    let Some(first) = keys.first() else {
+        return default;
+    };
+    if t <= first.0 {
+        return first.1;
+    }
+
+    let last = keys[keys.len() - 1];
+    if t >= last.0 {
+        return last.1;
+    }
+
+    let next = keys.iter().position(|k| k.0 >= t).unwrap();
+    let (t0, v0) = keys[next - 1];
+    let (t1, v1) = keys[next];
+
+    lerp(v0, v1, ((t - t0) / (t1 - t0)) as f32)
+}
+
+// A node's local transform at ticks, its bind transform for whatever the clip doesn't animate
+fn sample_local(channels: &HashMap<&str, &NodeAnim>, name: &str, bind_local: Mat4, ticks: f64) -> Mat4 {
+    let Some(channel) = channels.get(name) else {
+        return bind_local;
+    };
+    let (bind_scale, bind_rot, bind_pos) = bind_local.to_scale_rotation_translation();
+
+    let positions: Vec<(f64, Vec3)> = channel.position_keys.iter().map(|k| (k.time, to_vec3(&k.value))).collect();
+    let rotations: Vec<(f64, Quat)> = channel.rotation_keys.iter().map(|k| (k.time, Quat::from_xyzw(k.value.x, k.value.y, k.value.z, k.value.w))).collect();
+    let scales: Vec<(f64, Vec3)> = channel.scaling_keys.iter().map(|k| (k.time, to_vec3(&k.value))).collect();
+
+    Mat4::from_scale_rotation_translation(
+        sample_vec3(&scales, ticks, bind_scale),
+        sample(&rotations, ticks, bind_rot, |a, b, f| a.slerp(b, f)),
+        sample_vec3(&positions, ticks, bind_pos),
+    )
+}
+
+// MORPH_KEY lines for one clip. assimp names a morph channel after the node (or mesh) it animates
+// and each key only lists the targets it sets, the others are at 0 then.
+fn write_morph_keys(out: &mut String, scene: &Scene, root: &Rc<Node>, channels: &[MeshMorphAnim], ticks_per_second: f64) {
+    fn meshes_named(node: &Rc<Node>, name: &str, out: &mut Vec<u32>) {
+        if node.name == name {
+            out.extend(node.meshes.iter().copied());
+        }
+        for child in node.children.borrow().iter() {
+            meshes_named(child, name, out);
+        }
+    }
+
+    for channel in channels.iter() {
+        let mut meshes = vec![];
+        meshes_named(root, &channel.name, &mut meshes);
+        meshes.extend((0..scene.meshes.len() as u32).filter(|i| scene.meshes[*i as usize].name == channel.name));
+        meshes.sort();
+        meshes.dedup();
+        if meshes.is_empty() {
+            println!("WARNING: morph channel '{}' doesn't match any node or mesh, skipping", channel.name);
+            continue;
+        }
+
+        let mut targets: Vec<u32> = channel.keys.iter().flat_map(|k| k.values.iter().copied()).collect();
+        targets.sort();
+        targets.dedup();
+
+        for mesh_index in meshes {
+            let mesh = &scene.meshes[mesh_index as usize];
+            for target in targets.iter().filter(|t| (**t as usize) < mesh.anim_meshes.len()) {
+                let name = morph_target_name(&mesh.name, *target as usize);
+                for key in channel.keys.iter() {
+                    let weight = key.values.iter().position(|v| v == target).map(|i| key.weights[i]).unwrap_or(0.0);
+                    writeln!(out, "MORPH_KEY: {} {:.5} {:.5}", name, key.time / ticks_per_second, weight).unwrap();
+                }
+            }
+        }
+    }
+    writeln!(out).unwrap();
+}
+
+fn write_matrix(out: &mut String, m: &Mat4) {
+    // One column per line, import_bone_data reads them back as x/y/z/w axis
+    for col in m.to_cols_array_2d() {
+        writeln!(out, "{:.5} {:.5} {:.5} {:.5}", col[0], col[1], col[2], col[3]).unwrap();
+    }
+    writeln!(out).unwrap();
+}
+
+fn write_bones(scene: &Scene, root: &Rc<Node>, bones: &[ConvertedBone], options: &Options, conversion: Mat4) -> String {
+    let mut out = String::new();
+    let inverse_conversion = conversion.inverse();
+
+    writeln!(out, "# WiseModel 0.0.1").unwrap();
+    writeln!(out, "BONECOUNT: {}", bones.len()).unwrap();
+    writeln!(out, "FPS: {}", options.fps).unwrap();
+    writeln!(out, "GLOBAL_TRANSFORM:").unwrap();
+    write_matrix(&mut out, &Mat4::IDENTITY);
+
+    for bone in bones.iter() {
+        writeln!(out, "BONE_NAME: {}", bone.name).unwrap();
+        writeln!(out, "PARENT_INDEX: {}", bone.parent_index).unwrap();
+        writeln!(out, "OFFSET_MATRIX:").unwrap();
+        // Vertices got multiplied by the conversion, so undo it before going into bone space.
+        write_matrix(&mut out, &(bone.offset * inverse_conversion));
+    }
+
+    let mut written = HashSet::new();
+
+    for animation in scene.animations.iter() {
+        let name = animation.name.rsplit('|').next().unwrap_or("").to_string();
+        if !KNOWN_ANIMATIONS.contains(&name.as_str()) {
+            println!("WARNING: animation '{}' is not one of {:?}, rename it before loading", name, KNOWN_ANIMATIONS);
+        }
+        if !written.insert(name.clone()) {
+            println!("WARNING: duplicate animation '{}', skipping", name);
+            continue;
+        }
+
+        let ticks_per_second = if animation.ticks_per_second > 0.0 { animation.ticks_per_second } else { 25.0 };
+        let duration = animation.duration / ticks_per_second;
+
+        let channels: HashMap<&str, &NodeAnim> = animation.channels.iter().map(|c| (c.name.as_str(), c)).collect();
+
+        writeln!(out, "ANIMATION_NAME: {}", name).unwrap();
+        writeln!(out, "DURATION: {:.5}\n", duration).unwrap();
+        if !animation.morph_mesh_channels.is_empty() {
+            write_morph_keys(&mut out, scene, root, &animation.morph_mesh_channels, ticks_per_second);
+        }
+
+        let frame_count = (duration * options.fps as f64).ceil() as usize;
+
+        // import_bone_data throws away the first keyframe of every track (the blender scripts start
+        // at frame 1), so write t=0 twice.
+        let mut times = vec![0.0];
+        times.extend((0..=frame_count).map(|f| (f as f64 / options.fps as f64).min(duration)));
+
+        for (frame, time) in times.iter().enumerate() {
+            writeln!(out, "KEYFRAME: {}", frame).unwrap();
+            writeln!(out, "TIMESTAMP: {:.5}", time).unwrap();
+
+            let ticks = time * ticks_per_second;
+
+            for bone in bones.iter() {
+                // The pivots between this bone and its parent, then the bone itself
+                let mut local = Mat4::IDENTITY;
+                for (name, bind_local) in bone.pivots.iter() {
+                    local *= sample_local(&channels, name, *bind_local, ticks);
+                }
+                local *= sample_local(&channels, &bone.name, bone.bind_local, ticks);
+
+                if bone.is_root {
+                    local = conversion * local;
+                }
+                let (scale, rot, pos) = local.to_scale_rotation_translation();
+
+                writeln!(out, "{:.5} {:.5} {:.5}", pos.x, pos.y, pos.z).unwrap();
+                writeln!(out, "{:.5} {:.5} {:.5} {:.5}", rot.x, rot.y, rot.z, rot.w).unwrap();
+                writeln!(out, "{:.5} {:.5} {:.5}\n", scale.x, scale.y, scale.z).unwrap();
+            }
+        }
+    }
+
+    if scene.animations.is_empty() {
+        println!("WARNING: no animations found, the importer needs at least one");
+    }
+
+    out
+}
@@ -26,7 +26,6 @@ pub struct Camera {
 
     pub projection: Mat4,
     pub view: Mat4,
-    pub light_space: Mat4,
 
     pub last_f_state: bool,
 
@@ -65,7 +64,6 @@ impl Camera {
 
             projection: Mat4::IDENTITY,
             view: Mat4::IDENTITY,
-            light_space: Mat4::IDENTITY,
 
             last_f_state: true,
 
@@ -119,6 +117,18 @@ impl Camera {
         self.up = self.right.cross(self.forward).normalize();
     }
 
+    /// Free camera at position facing target, for scripted shots like the golden image scenes.
+    pub fn look_at(&mut self, position: Vec3, target: Vec3) {
+        self.move_state = CameraState::Free;
+        self.position = position;
+        self.direction = (target - position).normalize();
+        self.forward = self.direction;
+        self.pitch = (self.direction.y.asin() as f64).to_degrees();
+        self.yaw = (self.direction.z.atan2(self.direction.x) as f64).to_degrees();
+        self.right = self.forward.cross(vec3(0.0, 1.0, 0.0)).normalize();
+        self.up = self.right.cross(self.forward).normalize();
+    }
+
     pub fn get_view_matrix(&mut self) {
         self.view = Mat4::look_at_rh(self.position, self.target, self.up);
     }
@@ -0,0 +1,130 @@
+use std::{fs::create_dir_all, time::{SystemTime, UNIX_EPOCH}};
+
+use image::DynamicImage;
+
+use crate::offscreen::read_framebuffer;
+
+const CAPTURE_DIR: &str = "debug_out";
+
+/// Screenshots and frame sequences read back from the window into debug_out/. While a sequence is
+/// recording the game steps at exactly 1 / record_fps per frame however long the frame took to
+/// render and save, so the PNGs play back at record_fps without stutter.
+pub struct Capture {
+    // Capture after the imgui windows and game UI are drawn instead of just the scene
+    pub include_ui: bool,
+    pub record_fps: u32,
+    // Last file or folder written, for the UI
+    pub last_output: Option<String>,
+    screenshot_requested: bool,
+    sequence: Option<Sequence>,
+}
+
+struct Sequence {
+    dir: String,
+    frame: u32,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self {
+            include_ui: false,
+            record_fps: 30,
+            last_output: None,
+            screenshot_requested: false,
+            sequence: None,
+        }
+    }
+
+    /// Saves the next rendered frame as debug_out/screenshot_<timestamp>.png.
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.sequence.is_some()
+    }
+
+    /// Starts dumping every frame into a new debug_out/sequence_<timestamp>/ folder, or stops.
+    pub fn toggle_recording(&mut self) {
+        match self.sequence.take() {
+            Some(sequence) => {
+                println!("Recorded {} frames to {}", sequence.frame, sequence.dir);
+                self.last_output = Some(sequence.dir);
+            }
+            None => {
+                let dir = format!("{}/sequence_{}", CAPTURE_DIR, timestamp());
+                if let Err(e) = create_dir_all(&dir) {
+                    println!("Failed to create {}: {}", dir, e);
+                    return;
+                }
+                self.sequence = Some(Sequence { dir, frame: 0 });
+            }
+        }
+    }
+
+    /// Frame time the game should step by instead of the measured one.
+    pub fn fixed_delta(&self) -> Option<f32> {
+        self.sequence.as_ref().map(|_| 1.0 / self.record_fps.max(1) as f32)
+    }
+
+    /// Called once with the scene drawn and again with the UI on top, writes whatever was asked for
+    /// at the stage include_ui picks. Reads the back buffer, so it has to run before swap_buffers.
+    pub fn capture_frame(&mut self, fb_width: u32, fb_height: u32, ui_drawn: bool) {
+        if ui_drawn != self.include_ui || (!self.screenshot_requested && self.sequence.is_none()) {
+            return;
+        }
+
+        // Blending with SRC_ALPHA, ONE_MINUS_SRC_ALPHA lowers the destination alpha wherever UI or
+        // particles went, which would save as see through
+        let image = DynamicImage::ImageRgba8(read_framebuffer(0, fb_width, fb_height)).to_rgb8();
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            let path = format!("{}/screenshot_{}.png", CAPTURE_DIR, timestamp());
+            let saved = create_dir_all(CAPTURE_DIR).map_err(|e| e.to_string())
+                .and_then(|_| image.save(&path).map_err(|e| e.to_string()));
+            match saved {
+                Ok(()) => {
+                    println!("Saved screenshot to {}", path);
+                    self.last_output = Some(path);
+                }
+                Err(e) => println!("Failed to save {}: {}", path, e),
+            }
+        }
+
+        if let Some(sequence) = &mut self.sequence {
+            let path = format!("{}/frame_{:05}.png", sequence.dir, sequence.frame);
+            if let Err(e) = image.save(&path) {
+                println!("Failed to save {}, stopping the recording: {}", path, e);
+                self.sequence = None;
+                return;
+            }
+            sequence.frame += 1;
+        }
+    }
+}
+
+// UTC "YYYY-MM-DD_HH-MM-SS-mmm", sorts by time and is safe in file names everywhere
+fn timestamp() -> String {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let (days, day_secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Days to a civil date, Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}-{:03}",
+        year, month, day,
+        day_secs / 3600, day_secs % 3600 / 60, day_secs % 60,
+        since_epoch.subsec_millis(),
+    )
+}
@@ -0,0 +1,208 @@
+use glam::{vec2, vec3, vec4, Mat4, Vec3};
+
+use crate::{camera::Camera, culling::Aabb, gl_call, lights::GpuLight, shaders::Shader, some_data::{CLUSTER_INDICES_BINDING, CLUSTER_RANGES_BINDING}};
+
+// Froxel grid, tiles across the screen and exponential slices in depth
+pub const CLUSTER_X: usize = 16;
+pub const CLUSTER_Y: usize = 9;
+pub const CLUSTER_Z: usize = 24;
+pub const CLUSTER_COUNT: usize = CLUSTER_X * CLUSTER_Y * CLUSTER_Z;
+
+// Slices are spread between the camera's near plane and this, the last one runs out to z_far
+const CLUSTER_FAR: f32 = 300.0;
+
+/// Clustered forward lighting. Every frame the point and spot lights get binned on the CPU into
+/// view space froxels, model.glsl then only loops over the lights in its fragment's cluster.
+pub struct LightClusters {
+    pub enabled: bool,
+    pub heatmap: bool,
+
+    // View space bounds of each cluster, rebuilt when the projection changes
+    bounds: Vec<Aabb>,
+    bounds_key: (f32, f32, f32, f32),
+
+    // (offset, count) into indices per cluster
+    ranges: Vec<[u32; 2]>,
+    indices: Vec<u32>,
+    pub max_lights_in_cluster: u32,
+
+    ranges_ssbo: u32,
+    indices_ssbo: u32,
+    screen_size: (f32, f32),
+    z_near: f32,
+}
+
+impl LightClusters {
+    pub fn new() -> Self {
+        let mut ranges_ssbo = 0;
+        let mut indices_ssbo = 0;
+        unsafe {
+            gl_call!(gl::GenBuffers(1, &mut ranges_ssbo));
+            gl_call!(gl::GenBuffers(1, &mut indices_ssbo));
+        }
+
+        Self {
+            enabled: true,
+            heatmap: false,
+
+            bounds: Vec::with_capacity(CLUSTER_COUNT),
+            bounds_key: (0.0, 0.0, 0.0, 0.0),
+
+            ranges: vec![[0, 0]; CLUSTER_COUNT],
+            indices: Vec::new(),
+            max_lights_in_cluster: 0,
+
+            ranges_ssbo,
+            indices_ssbo,
+            screen_size: (1.0, 1.0),
+            z_near: 0.1,
+        }
+    }
+
+    /// Bins the lights for this frame's camera and uploads the result. camera.view and projection
+    /// must already be up to date.
+    pub fn update(&mut self, camera: &Camera, lights: &[GpuLight], fb_width: u32, fb_height: u32) {
+        self.screen_size = (fb_width as f32, fb_height as f32);
+        self.z_near = camera.z_near;
+        self.rebuild_bounds(camera, fb_width as f32 / fb_height as f32);
+
+        self.indices.clear();
+        self.max_lights_in_cluster = 0;
+
+        // Light spheres in view space, spot lights are treated as spheres of their range
+        let spheres: Vec<(Vec3, f32)> = lights
+            .iter()
+            .map(|l| (camera.view.transform_point3(l.position.truncate()), l.attenuation.w))
+            .collect();
+
+        let mut cluster_lights: Vec<Vec<u32>> = vec![Vec::new(); CLUSTER_COUNT];
+        for (light_index, (center, radius)) in spheres.iter().enumerate() {
+            // View space looks down -z, skip lights entirely behind the camera
+            let near_depth = -center.z - radius;
+            let far_depth = -center.z + radius;
+            if far_depth < camera.z_near {
+                continue;
+            }
+
+            let first_slice = self.slice_for_depth(near_depth.max(camera.z_near));
+            let last_slice = self.slice_for_depth(far_depth);
+            for z in first_slice..=last_slice {
+                for y in 0..CLUSTER_Y {
+                    for x in 0..CLUSTER_X {
+                        let cluster = cluster_index(x, y, z);
+                        if self.bounds[cluster].intersects_sphere(*center, *radius) {
+                            cluster_lights[cluster].push(light_index as u32);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (range, lights) in self.ranges.iter_mut().zip(cluster_lights.iter()) {
+            *range = [self.indices.len() as u32, lights.len() as u32];
+            self.indices.extend_from_slice(lights);
+            self.max_lights_in_cluster = self.max_lights_in_cluster.max(lights.len() as u32);
+        }
+
+        // Keep the buffer non empty so the SSBO binding stays valid
+        if self.indices.is_empty() {
+            self.indices.push(0);
+        }
+
+        unsafe {
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.ranges_ssbo));
+            gl_call!(gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                std::mem::size_of_val(self.ranges.as_slice()) as isize,
+                self.ranges.as_ptr().cast(),
+                gl::DYNAMIC_DRAW
+            ));
+            gl_call!(gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, CLUSTER_RANGES_BINDING, self.ranges_ssbo));
+
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.indices_ssbo));
+            gl_call!(gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                std::mem::size_of_val(self.indices.as_slice()) as isize,
+                self.indices.as_ptr().cast(),
+                gl::DYNAMIC_DRAW
+            ));
+            gl_call!(gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, CLUSTER_INDICES_BINDING, self.indices_ssbo));
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0));
+        }
+    }
+
+    /// Uniforms model.glsl needs to find a fragment's cluster.
+    pub fn bind(&self, shader: &Shader) {
+        let (scale, bias) = self.slice_scale_bias();
+        shader.set_bool("use_clusters", self.enabled);
+        shader.set_bool("cluster_heatmap", self.heatmap);
+        shader.set_vec3("cluster_dims", vec3(CLUSTER_X as f32, CLUSTER_Y as f32, CLUSTER_Z as f32));
+        shader.set_vec2("cluster_tile_size", vec2(self.screen_size.0 / CLUSTER_X as f32, self.screen_size.1 / CLUSTER_Y as f32));
+        shader.set_float("cluster_slice_scale", scale);
+        shader.set_float("cluster_slice_bias", bias);
+    }
+
+    // slice = floor(log(depth) * scale - bias)
+    fn slice_scale_bias(&self) -> (f32, f32) {
+        let log_ratio = (CLUSTER_FAR / self.z_near).ln();
+        let scale = CLUSTER_Z as f32 / log_ratio;
+        let bias = CLUSTER_Z as f32 * self.z_near.ln() / log_ratio;
+        (scale, bias)
+    }
+
+    fn slice_for_depth(&self, depth: f32) -> usize {
+        let (scale, bias) = self.slice_scale_bias();
+        let slice = (depth.max(self.z_near).ln() * scale - bias).floor();
+        (slice.max(0.0) as usize).min(CLUSTER_Z - 1)
+    }
+
+    fn slice_depth(&self, slice: usize, z_far: f32) -> f32 {
+        if slice >= CLUSTER_Z {
+            return z_far;
+        }
+        self.z_near * (CLUSTER_FAR / self.z_near).powf(slice as f32 / CLUSTER_Z as f32)
+    }
+
+    fn rebuild_bounds(&mut self, camera: &Camera, aspect: f32) {
+        let key = (camera.fovy, aspect, camera.z_near, camera.z_far);
+        if key == self.bounds_key && self.bounds.len() == CLUSTER_COUNT {
+            return;
+        }
+        self.bounds_key = key;
+
+        let inv_projection = Mat4::perspective_rh_gl(camera.fovy, aspect, camera.z_near, camera.z_far).inverse();
+        self.bounds.clear();
+        self.bounds.resize(CLUSTER_COUNT, Aabb::EMPTY);
+
+        for z in 0..CLUSTER_Z {
+            let near = self.slice_depth(z, camera.z_far);
+            let far = self.slice_depth(z + 1, camera.z_far);
+
+            for y in 0..CLUSTER_Y {
+                for x in 0..CLUSTER_X {
+                    // Tile corners on the near plane, pushed out along their view rays to the slice depths
+                    let mut min = Vec3::splat(f32::MAX);
+                    let mut max = Vec3::splat(f32::MIN);
+                    for (tx, ty) in [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)] {
+                        let ndc_x = tx as f32 / CLUSTER_X as f32 * 2.0 - 1.0;
+                        let ndc_y = ty as f32 / CLUSTER_Y as f32 * 2.0 - 1.0;
+                        let point = inv_projection * vec4(ndc_x, ndc_y, -1.0, 1.0);
+                        let ray = point.truncate() / point.w;
+
+                        for depth in [near, far] {
+                            let corner = ray * (depth / -ray.z);
+                            min = min.min(corner);
+                            max = max.max(corner);
+                        }
+                    }
+
+                    self.bounds[cluster_index(x, y, z)] = Aabb { min, max };
+                }
+            }
+        }
+    }
+}
+
+fn cluster_index(x: usize, y: usize, z: usize) -> usize {
+    x + y * CLUSTER_X + z * CLUSTER_X * CLUSTER_Y
+}
@@ -2,7 +2,7 @@ use std::{collections::HashMap, fs::read_to_string};
 
 use serde::Deserialize;
 
-use crate::{debug::gizmos::Cylinder, enums_types::{AnimationType, EntityType, Faction}};
+use crate::{debug::gizmos::Cylinder, error::{LoadError, LoadResult}, enums_types::{AnimationType, EntityType, Faction}};
 
 #[derive(Deserialize, Debug)]
 pub struct EntityConfig {
@@ -10,11 +10,18 @@ pub struct EntityConfig {
 }
 
 impl EntityConfig {
-    pub fn load_from_file(file_name: &str) -> EntityConfig {
+    pub fn load_from_file(file_name: &str) -> LoadResult<EntityConfig> {
         println!("loading entity configuration from {}", &file_name);
-        let config_str = read_to_string(file_name).unwrap();
+        let config_str = read_to_string(file_name).map_err(|e| LoadError::io(file_name, e))?;
 
-        serde_json::from_str(&config_str).expect("The entity config file was missing")
+        serde_json::from_str(&config_str).map_err(|e| LoadError::json(file_name, e))
+    }
+
+    /// Fallback when the file can't be loaded, nothing gets spawned.
+    pub fn empty() -> EntityConfig {
+        EntityConfig {
+            entity_types: HashMap::new(),
+        }
     }
 }
 
@@ -38,4 +45,50 @@ pub struct EntityTypeHelper {
     pub bone_path: String,
     pub hit_cyl: Cylinder,
     pub animation_properties: Vec<AnimationPropHelper>,
+    #[serde(default)]
+    pub material: MaterialParams,
+    #[serde(default)]
+    pub render: RenderFlags,
+}
+
+/// How the renderer treats an archetype's meshes, so new archetypes only need config to show up.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct RenderFlags {
+    // Cut out below 0.1 alpha with depth writes, then blend the soft edges over the result
+    pub alpha_test: bool,
+    // Off enables back face culling
+    pub double_sided: bool,
+}
+
+impl Default for RenderFlags {
+    fn default() -> Self {
+        // Nothing was face culled before these flags existed
+        Self {
+            alpha_test: false,
+            double_sided: true,
+        }
+    }
+}
+
+/// Per archetype multipliers on top of the model's textures, only roughness and metalness are
+/// ignored by the legacy shading path.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct MaterialParams {
+    pub base_color_factor: [f32; 4],
+    pub roughness: f32,
+    pub metalness: f32,
+    pub emissive_strength: f32,
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            roughness: 0.8,
+            metalness: 0.0,
+            emissive_strength: 1.0,
+        }
+    }
 }
@@ -2,6 +2,8 @@ use std::{fs::read_to_string, collections::HashMap};
 
 use serde::Deserialize;
 
+use crate::error::{LoadError, LoadResult};
+
 #[derive(Deserialize, Debug)]
 pub struct GameConfig {
     pub game_title: String,
@@ -14,13 +16,112 @@ pub struct GameConfig {
     pub debug_mode: bool,
     pub fps_counter: bool,
     pub sounds: HashMap<String, String>,
+    #[serde(default)]
+    pub time_of_day: TimeOfDayConfig,
+}
+
+/// Day/night cycle, see time_of_day.rs. Hours run 0..24.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TimeOfDayConfig {
+    pub enabled: bool,
+    // Levels without their own time_of_day start here
+    pub start_hour: f32,
+    // Game hours that pass per real second
+    pub speed: f32,
+    pub sunrise_hour: f32,
+    pub sunset_hour: f32,
+    // Compass angle in degrees the sun rises at, 0 is +X and 90 is +Z
+    pub sun_azimuth: f32,
+    // Degrees above the horizon at the middle of the day
+    pub sun_max_elevation: f32,
+    // Any order, TimeOfDay sorts them by hour. The cycle wraps from the last key back to the first
+    pub keys: Vec<TimeKey>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct TimeKey {
+    pub hour: f32,
+    pub sun_color: [f32; 3],
+    pub sun_intensity: f32,
+    pub ambient: [f32; 3],
+    // Sun brightness the procedural sky scatters
+    pub sky_intensity: f32,
+    // Multiplies the cubemap skybox
+    pub sky_tint: [f32; 3],
+}
+
+impl Default for TimeOfDayConfig {
+    fn default() -> Self {
+        let night = TimeKey {
+            hour: 0.0,
+            sun_color: [0.6, 0.7, 1.0],
+            sun_intensity: 0.0,
+            ambient: [0.06, 0.08, 0.14],
+            sky_intensity: 20.0,
+            sky_tint: [0.08, 0.1, 0.18],
+        };
+        let golden = TimeKey {
+            hour: 6.5,
+            sun_color: [1.0, 0.55, 0.3],
+            sun_intensity: 0.6,
+            ambient: [0.3, 0.25, 0.25],
+            sky_intensity: 20.0,
+            sky_tint: [0.85, 0.55, 0.45],
+        };
+        let day = TimeKey {
+            hour: 9.0,
+            sun_color: [1.0, 0.97, 0.9],
+            sun_intensity: 1.0,
+            ambient: [0.6, 0.6, 0.6],
+            sky_intensity: 20.0,
+            sky_tint: [1.0, 1.0, 1.0],
+        };
+
+        Self {
+            enabled: true,
+            start_hour: 12.0,
+            speed: 0.02,
+            sunrise_hour: 6.0,
+            sunset_hour: 18.0,
+            sun_azimuth: 90.0,
+            sun_max_elevation: 60.0,
+            keys: vec![
+                night,
+                TimeKey { hour: 5.0, ..night },
+                golden,
+                day,
+                TimeKey { hour: 16.0, ..day },
+                TimeKey { hour: 18.0, ..golden },
+                TimeKey { hour: 19.5, ..night },
+            ],
+        }
+    }
 }
 
 impl GameConfig {
-    pub fn load_from_file(file_name: &str) -> GameConfig {
+    pub fn load_from_file(file_name: &str) -> LoadResult<GameConfig> {
         println!("loading game configuration from {}", &file_name);
-        let config_str = read_to_string(file_name).unwrap();
+        let config_str = read_to_string(file_name).map_err(|e| LoadError::io(file_name, e))?;
+
+        serde_json::from_str(&config_str).map_err(|e| LoadError::json(file_name, e))
+    }
 
-        serde_json::from_str(&config_str).expect("The gameconfig file was missing")
+    /// Fallback when the file can't be loaded. Same values as the shipped game_config.json minus
+    /// the sounds.
+    pub fn fallback() -> GameConfig {
+        GameConfig {
+            game_title: "Fallback Config".to_string(),
+            cell_size: 1.0,
+            win_width: 1920.0,
+            win_height: 1080.0,
+            grid_height: 100,
+            grid_width: 100,
+            vsync: true,
+            debug_mode: true,
+            fps_counter: true,
+            sounds: HashMap::new(),
+            time_of_day: TimeOfDayConfig::default(),
+        }
     }
 }
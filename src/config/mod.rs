@@ -1,3 +1,4 @@
 pub mod game_config;
 pub mod entity_config;
 pub mod world_data;
+pub mod watcher;
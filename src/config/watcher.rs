@@ -0,0 +1,75 @@
+use std::{collections::HashMap, fs, time::{Duration, Instant, SystemTime}};
+
+pub const ENTITY_CONFIG_PATH: &str = "config/entity_config.json";
+pub const GAME_CONFIG_PATH: &str = "config/game_config.json";
+pub const WORLD_DATA_PATH: &str = "config/world_data.toml";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigFile {
+    Entity,
+    Game,
+    World,
+}
+
+impl ConfigFile {
+    pub fn path(&self) -> &'static str {
+        match self {
+            ConfigFile::Entity => ENTITY_CONFIG_PATH,
+            ConfigFile::Game => GAME_CONFIG_PATH,
+            ConfigFile::World => WORLD_DATA_PATH,
+        }
+    }
+}
+
+/// Polls the mtimes of the config files, same idea as the shader hot reloading in the Renderer.
+pub struct ConfigWatcher {
+    pub enabled: bool,
+    // Rebuilding the level throws away everything that happened since startup so it's opt in
+    pub reload_level: bool,
+    // Why each file's last reload failed, cleared once that file loads again
+    pub errors: HashMap<ConfigFile, String>,
+    files: Vec<(ConfigFile, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new() -> Self {
+        let files = [ConfigFile::Entity, ConfigFile::Game, ConfigFile::World]
+            .into_iter()
+            .map(|f| (f, modified_time(f.path())))
+            .collect();
+
+        Self {
+            enabled: true,
+            reload_level: false,
+            errors: HashMap::new(),
+            files,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Returns the files that changed on disk since the last poll.
+    pub fn poll(&mut self) -> Vec<ConfigFile> {
+        if !self.enabled || self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = vec![];
+        for (file, modified) in self.files.iter_mut() {
+            let current = modified_time(file.path());
+            if current.is_some() && current != *modified {
+                *modified = current;
+                changed.push(*file);
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified_time(file_path: &str) -> Option<SystemTime> {
+    fs::metadata(file_path).and_then(|m| m.modified()).ok()
+}
@@ -3,19 +3,23 @@ use toml::value::{Table, Value, Array};
 
 use serde::{ser::SerializeSeq, Deserialize, Serialize, Serializer};
 
-use crate::enums_types::{EntityType, Faction};
+use crate::{enums_types::{EntityType, Faction}, error::{LoadError, LoadResult}};
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct WorldData {
+    // Hour the level starts at, the game config's start_hour otherwise. Ahead of entities so it
+    // serializes above the [[entities]] tables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_of_day: Option<f32>,
     pub entities: Vec<EntityInstance>,
 }
 
 impl WorldData {
-    pub fn load_from_file(file_name: &str) -> Self {
+    pub fn load_from_file(file_name: &str) -> LoadResult<Self> {
         println!("loading world data from {}", &file_name);
-        let config_str = read_to_string(file_name).unwrap();
+        let config_str = read_to_string(file_name).map_err(|e| LoadError::io(file_name, e))?;
 
-        toml::from_str(&config_str).expect("The world_data file was missing or malformed")
+        toml::from_str(&config_str).map_err(|e| LoadError::toml(file_name, &config_str, e))
     }
 
     pub fn write_to_file(&self, file_name: &str) {
@@ -49,6 +53,9 @@ impl WorldData {
             entities_array.push(Value::Table(ent));
         }
 
+        if let Some(hour) = self.time_of_day {
+            root.insert("time_of_day".into(), Value::Float(snap(hour as f64, 4)));
+        }
         root.insert("entities".into(), Value::Array(entities_array));
         let toml_str = toml::to_string(&Value::Table(root)).unwrap();
         write(path, toml_str).unwrap();
@@ -0,0 +1,130 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::entity_manager::EntityManager;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb { min: Vec3::splat(f32::MAX), max: Vec3::splat(f32::MIN) };
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn grow(&mut self, point: Vec3) {
+        self.min = self.min.min(point);
+        self.max = self.max.max(point);
+    }
+
+    pub fn expanded(&self, amount: Vec3) -> Aabb {
+        Aabb { min: self.min - amount, max: self.max + amount }
+    }
+
+    /// Box around the transformed box, stays conservative under rotation.
+    pub fn transformed(&self, mat: &Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let center = mat.transform_point3(self.center());
+        let extents = self.extents();
+        // Each world axis gets the absolute projection of every local extent
+        let world_extents = mat.x_axis.truncate().abs() * extents.x
+            + mat.y_axis.truncate().abs() * extents.y
+            + mat.z_axis.truncate().abs() * extents.z;
+
+        Aabb { min: center - world_extents, max: center + world_extents }
+    }
+
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        let closest = center.clamp(self.min, self.max);
+        closest.distance_squared(center) <= radius * radius
+    }
+}
+
+/// Six inward facing planes pulled out of a view projection matrix. Works for the camera's
+/// perspective and the cascades' orthographic projections alike.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    // xyz normal, w distance
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(view_projection: Mat4) -> Self {
+        let rows = [
+            view_projection.row(0),
+            view_projection.row(1),
+            view_projection.row(2),
+            view_projection.row(3),
+        ];
+
+        let mut planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[3] + rows[2],
+            rows[3] - rows[2],
+        ];
+        for plane in planes.iter_mut() {
+            *plane /= plane.truncate().length();
+        }
+
+        Self { planes }
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let extents = aabb.extents();
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            // Box is outside once even its corner furthest along the normal is behind the plane
+            let radius = extents.dot(normal.abs());
+            normal.dot(center) + plane.w >= -radius
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CullCounter {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+impl CullCounter {
+    /// Entities without bounds always count as visible.
+    pub fn test(&mut self, bounds: Option<&Aabb>, visible: impl Fn(&Aabb) -> bool) -> bool {
+        let keep = bounds.is_none_or(visible);
+        if keep {
+            self.drawn += 1;
+        } else {
+            self.culled += 1;
+        }
+        keep
+    }
+}
+
+/// Per frame counters for the debug UI. Shadow counts add up over every cascade and point light.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CullStats {
+    pub camera: CullCounter,
+    pub shadow: CullCounter,
+}
+
+/// Keeps the ids whose world bounds pass visible.
+pub fn cull(em: &EntityManager, ids: impl Iterator<Item = usize>, visible: impl Fn(&Aabb) -> bool, counter: &mut CullCounter) -> Vec<usize> {
+    ids.filter(|id| counter.test(em.world_bounds.get(*id), &visible)).collect()
+}
@@ -11,6 +11,14 @@ pub struct Cuboid {
 
 impl Cuboid {
     pub fn create_model(&self) -> Model {
+        let mut cuboid = self.build_model();
+        cuboid.setup_opengl();
+
+        cuboid
+    }
+
+    /// Geometry only, for callers that want to tweak the vertices before they go to the gpu.
+    pub fn build_model(&self) -> Model {
         let mut cuboid = Model::new();
 
         let max_x = self.w / 2.0;
@@ -78,7 +86,6 @@ impl Cuboid {
 
         cuboid.vertices = vertices;
         cuboid.indices = indices;
-        cuboid.setup_opengl();
 
         cuboid
     }
@@ -1,2 +1,3 @@
 pub mod write;
 pub mod gizmos;
+pub mod skeleton;
@@ -0,0 +1,270 @@
+use std::mem;
+
+use glam::{vec3, Mat4, Vec3, Vec4Swizzles};
+
+use crate::{animation::animation::{Animation, Bone}, camera::Camera, entity_manager::EntityManager, gl_call, shaders::Shader};
+
+const ROOT_COLOR: Vec3 = vec3(1.0, 0.85, 0.0);
+const BONE_COLOR: Vec3 = vec3(0.0, 0.8, 1.0);
+const SELECTED_COLOR: Vec3 = vec3(1.0, 0.0, 1.0);
+
+// Size of the cross drawn on joints that have no parent line (roots and the picked bone).
+const JOINT_MARKER_SIZE: f32 = 0.08;
+
+pub struct BoneLabel {
+    pub screen_pos: [f32; 2],
+    pub name: String,
+    pub color: [f32; 4],
+}
+
+/// Draws the current pose of every skinned entity as lines (or wire octahedra)
+/// so broken imports can actually be looked at.
+pub struct SkeletonDebug {
+    pub enabled: bool,
+    pub show_labels: bool,
+    pub octahedra: bool,
+    pub selected_bone: Option<u32>,
+    // Entity selected_bone belongs to
+    inspected: Option<usize>,
+
+    vao: u32,
+    vbo: u32,
+    // interleaved pos/color, rebuilt every frame
+    vertices: Vec<f32>,
+}
+
+impl SkeletonDebug {
+    pub fn new() -> Self {
+        let mut vao = 0;
+        let mut vbo = 0;
+
+        unsafe {
+            gl_call!(gl::GenVertexArrays(1, &mut vao));
+            gl_call!(gl::GenBuffers(1, &mut vbo));
+
+            gl_call!(gl::BindVertexArray(vao));
+            gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, vbo));
+
+            let stride = (6 * mem::size_of::<f32>()) as i32;
+
+            gl_call!(gl::EnableVertexAttribArray(0));
+            gl_call!(gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null()));
+
+            gl_call!(gl::EnableVertexAttribArray(1));
+            gl_call!(gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, stride, (3 * mem::size_of::<f32>()) as *const _));
+
+            gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, 0));
+            gl_call!(gl::BindVertexArray(0));
+        }
+
+        Self {
+            enabled: false,
+            show_labels: false,
+            octahedra: true,
+            selected_bone: None,
+            inspected: None,
+
+            vao,
+            vbo,
+            vertices: vec![],
+        }
+    }
+
+    /// The selected animated entity whose bones the Skeleton window lists. Drops the picked bone
+    /// whenever that changes, a bone id from one skeleton means nothing on another.
+    pub fn inspected_entity(&mut self, em: &EntityManager) -> Option<usize> {
+        let id = em.selected.iter().copied().find(|id| em.skellingtons.contains(*id));
+        if id != self.inspected {
+            self.inspected = id;
+            self.selected_bone = None;
+        }
+        id
+    }
+
+    pub fn draw(&mut self, em: &EntityManager, camera: &Camera, shader: &Shader) {
+        let inspected = self.inspected_entity(em);
+        if !self.enabled {
+            return;
+        }
+
+        self.vertices.clear();
+
+        for entry in em.skellingtons.iter() {
+            let id = entry.key();
+            let (Some(animator), Some(trans)) = (em.animators.get(id), em.transforms.get(id)) else {
+                continue;
+            };
+            let Some(animation) = animator.animations.get(&animator.current_animation) else {
+                continue;
+            };
+
+            let model_mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
+            // Only highlight the picked bone on the entity that is being inspected
+            let selected = if inspected == Some(id) { self.selected_bone } else { None };
+
+            self.push_bone(entry.value(), animation, model_mat, None, selected);
+        }
+
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        shader.activate();
+        shader.set_mat4("projection", camera.projection);
+        shader.set_mat4("view", camera.view);
+
+        unsafe {
+            gl_call!(gl::Disable(gl::DEPTH_TEST));
+
+            gl_call!(gl::BindVertexArray(self.vao));
+            gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo));
+            gl_call!(gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertices.len() * mem::size_of::<f32>()) as isize,
+                self.vertices.as_ptr().cast(),
+                gl::STREAM_DRAW,
+            ));
+
+            gl_call!(gl::DrawArrays(gl::LINES, 0, (self.vertices.len() / 6) as i32));
+
+            gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, 0));
+            gl_call!(gl::BindVertexArray(0));
+            gl_call!(gl::Enable(gl::DEPTH_TEST));
+        }
+    }
+
+    fn push_bone(&mut self, bone: &Bone, animation: &Animation, model_mat: Mat4, parent_pos: Option<Vec3>, selected: Option<u32>) {
+        let pos = joint_position(bone.id, animation, model_mat);
+        let is_selected = selected == Some(bone.id);
+
+        let color = if is_selected {
+            SELECTED_COLOR
+        } else if bone.parent_index.is_none() {
+            ROOT_COLOR
+        } else {
+            BONE_COLOR
+        };
+
+        if let Some(parent_pos) = parent_pos {
+            if self.octahedra {
+                self.push_octahedron(parent_pos, pos, color);
+            } else {
+                self.push_line(parent_pos, pos, color);
+            }
+        }
+
+        if parent_pos.is_none() || is_selected {
+            self.push_cross(pos, JOINT_MARKER_SIZE, color);
+        }
+
+        for child in bone.children.iter() {
+            self.push_bone(child, animation, model_mat, Some(pos), selected);
+        }
+    }
+
+    fn push_line(&mut self, a: Vec3, b: Vec3, color: Vec3) {
+        self.vertices.extend_from_slice(&[a.x, a.y, a.z, color.x, color.y, color.z]);
+        self.vertices.extend_from_slice(&[b.x, b.y, b.z, color.x, color.y, color.z]);
+    }
+
+    fn push_cross(&mut self, center: Vec3, size: f32, color: Vec3) {
+        let half = size / 2.0;
+        self.push_line(center - Vec3::X * half, center + Vec3::X * half, color);
+        self.push_line(center - Vec3::Y * half, center + Vec3::Y * half, color);
+        self.push_line(center - Vec3::Z * half, center + Vec3::Z * half, color);
+    }
+
+    // Classic blender style bone: a thin diamond that is widest 10% of the way from head to tail.
+    fn push_octahedron(&mut self, head: Vec3, tail: Vec3, color: Vec3) {
+        let dir = tail - head;
+        let length = dir.length();
+        if length < 0.0001 {
+            return;
+        }
+
+        let axis = dir / length;
+        let up = if axis.y.abs() > 0.99 { Vec3::X } else { Vec3::Y };
+        let a = axis.cross(up).normalize() * length * 0.1;
+        let b = axis.cross(a);
+        let mid = head + dir * 0.1;
+
+        let ring = [mid + a, mid + b, mid - a, mid - b];
+
+        for i in 0..ring.len() {
+            let next = ring[(i + 1) % ring.len()];
+            self.push_line(head, ring[i], color);
+            self.push_line(ring[i], tail, color);
+            self.push_line(ring[i], next, color);
+        }
+    }
+}
+
+pub fn joint_position(bone_id: u32, animation: &Animation, model_mat: Mat4) -> Vec3 {
+    let global = animation.current_globals.get(bone_id as usize).copied().unwrap_or(Mat4::IDENTITY);
+    (model_mat * global).w_axis.xyz()
+}
+
+/// Returns (depth, bone id, bone name) for every bone, parents before children.
+pub fn flatten_bones(bone: &Bone, depth: usize, out: &mut Vec<(usize, u32, String)>) {
+    out.push((depth, bone.id, bone.name.clone()));
+    for child in bone.children.iter() {
+        flatten_bones(child, depth + 1, out);
+    }
+}
+
+/// Projects every joint of every skeleton to screen space so imgui can put the bone names on top
+/// of the scene.
+pub fn bone_labels(em: &EntityManager, camera: &Camera, width: f32, height: f32, inspected: Option<usize>, selected_bone: Option<u32>) -> Vec<BoneLabel> {
+    let mut labels = vec![];
+    let view_proj = camera.projection * camera.view;
+
+    for entry in em.skellingtons.iter() {
+        let id = entry.key();
+        let (Some(animator), Some(trans)) = (em.animators.get(id), em.transforms.get(id)) else {
+            continue;
+        };
+        let Some(animation) = animator.animations.get(&animator.current_animation) else {
+            continue;
+        };
+
+        let model_mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
+        let is_inspected = inspected == Some(id);
+
+        let mut bones = vec![];
+        flatten_bones(entry.value(), 0, &mut bones);
+
+        for (depth, bone_id, name) in bones {
+            let world = joint_position(bone_id, animation, model_mat);
+            let clip = view_proj * world.extend(1.0);
+
+            // behind the camera
+            if clip.w <= 0.0 {
+                continue;
+            }
+
+            let ndc = clip.xyz() / clip.w;
+            if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 {
+                continue;
+            }
+
+            let color = if is_inspected && selected_bone == Some(bone_id) {
+                SELECTED_COLOR
+            } else if depth == 0 {
+                ROOT_COLOR
+            } else {
+                BONE_COLOR
+            };
+
+            labels.push(BoneLabel {
+                screen_pos: [
+                    (ndc.x * 0.5 + 0.5) * width,
+                    (1.0 - (ndc.y * 0.5 + 0.5)) * height,
+                ],
+                name,
+                color: [color.x, color.y, color.z, 1.0],
+            });
+        }
+    }
+
+    labels
+}
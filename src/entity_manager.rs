@@ -7,19 +7,24 @@ use libc::EILSEQ;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 
-use crate::{animation::{animation::{import_bone_data, import_model_data, Animation, Animator, Bone, Model}, animation_system}, camera::Camera, collision_system, config::{entity_config::{AnimationPropHelper, EntityConfig}, world_data::WorldData}, debug::gizmos::{Cuboid, Cylinder}, enums_types::{CellType, EntityType, Faction, Parent, Rotator, SimState, Transform, VisualEffect}, grid::Grid, movement_system, some_data::{GRASSES, TREES}, sound::sound_manager::{ContinuousSound, OneShot, SoundManager}, sparse_set::SparseSet, state_machines, terrain::Terrain};
+use crate::{animation::{animation::{Animation, Animator, Bone, Model}, animation_system, gltf_import::is_gltf}, assets::{Assets, MeshHandle, SkeletonHandle}, camera::Camera, collision_system, culling::Aabb, config::{entity_config::{AnimationPropHelper, EntityConfig, MaterialParams, RenderFlags}, watcher::{ENTITY_CONFIG_PATH, WORLD_DATA_PATH}, world_data::WorldData}, debug::gizmos::{Cuboid, Cylinder}, enums_types::{CellType, EntityType, Faction, Parent, Rotator, SimState, Transform, VisualEffect}, error::LoadError, grid::Grid, movement_system, some_data::{GRASSES, TREES}, sound::sound_manager::{ContinuousSound, OneShot, SoundManager}, sparse_set::SparseSet, state_machines, terrain::Terrain};
 
 pub struct EntityManager {
     pub next_entity_id: usize,
     pub transforms: SparseSet<Transform>,
     pub factions: SparseSet<Faction>,
     pub entity_types: SparseSet<EntityType>,
-    pub models: SparseSet<Model>,
-    pub ani_models: SparseSet<Model>,
+    pub models: SparseSet<MeshHandle>,
+    pub ani_models: SparseSet<MeshHandle>,
     pub animators: SparseSet<Animator>,
     pub skellingtons: SparseSet<Bone>,
+    pub skeleton_handles: SparseSet<SkeletonHandle>,
     pub rotators: SparseSet<Rotator>,
     pub sim_states: SparseSet<SimState>,
+    pub material_params: SparseSet<MaterialParams>,
+    pub render_flags: SparseSet<RenderFlags>,
+    // Model bounds moved by the transform, see update_world_bounds
+    pub world_bounds: SparseSet<Aabb>,
 
     // Simulation/Behavior Components
     pub destinations: SparseSet<Vec3>,
@@ -34,6 +39,8 @@ pub struct EntityManager {
     pub selected: Vec<usize>,
     pub v_effects: SparseSet<VisualEffect>,
     pub entity_trashcan: Vec<usize>,
+
+    pub assets: Assets,
 }
 
 impl EntityManager {
@@ -47,8 +54,12 @@ impl EntityManager {
             ani_models: SparseSet::with_capacity(max_entities),
             animators: SparseSet::with_capacity(max_entities),
             skellingtons: SparseSet::with_capacity(max_entities),
+            skeleton_handles: SparseSet::with_capacity(max_entities),
             rotators: SparseSet::with_capacity(max_entities),
             sim_states: SparseSet::with_capacity(max_entities),
+            material_params: SparseSet::with_capacity(max_entities),
+            render_flags: SparseSet::with_capacity(max_entities),
+            world_bounds: SparseSet::with_capacity(max_entities),
 
             destinations: SparseSet::with_capacity(max_entities),
 
@@ -61,12 +72,24 @@ impl EntityManager {
             selected: Vec::new(),
             v_effects: SparseSet::with_capacity(max_entities),
             entity_trashcan: Vec::new(),
+
+            assets: Assets::new(),
         }
     }
 
-    pub fn populate_initial_entity_data(&mut self, ec: &mut EntityConfig, wd: &mut WorldData) {
+    /// World entries whose entity type has no archetype in the entity config are skipped and
+    /// reported through errors.
+    pub fn populate_initial_entity_data(&mut self, ec: &mut EntityConfig, wd: &mut WorldData, errors: &mut Vec<LoadError>) {
         for instance in wd.entities.iter() {
-            let archetype = ec.entity_types.get(&instance.entity_type).unwrap();
+            let Some(archetype) = ec.entity_types.get(&instance.entity_type) else {
+                errors.push(LoadError::parse(
+                    WORLD_DATA_PATH,
+                    None,
+                    Some("entity_type"),
+                    format!("no archetype for {} in {}", instance.entity_type, ENTITY_CONFIG_PATH),
+                ));
+                continue;
+            };
             let position = instance.position;
             let rotation = instance.rotation;
             let scale_correction = archetype.scale_correction;
@@ -75,6 +98,10 @@ impl EntityManager {
                 "-FRAC_PI_2" => Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
                 _ => Quat::IDENTITY,
             };
+
+            // Both create functions spawn the entity at next_entity_id before its hit cylinder
+            self.material_params.insert(self.next_entity_id, archetype.material);
+            self.render_flags.insert(self.next_entity_id, archetype.render);
             match instance.faction {
                 Faction::Player | Faction::Enemy => {
                     self.create_animated_entity(
@@ -119,18 +146,7 @@ impl EntityManager {
         };
         self.transforms.insert(self.next_entity_id, transform);
 
-        let mut model = Model::new();
-        let mut found = false;
-        for m in self.models.iter_mut() {
-            if m.value().full_path == *model_path.to_string() {
-                model = m.value().clone();
-                found = true;
-            }
-        }
-
-        if !found {
-            model = import_model_data(model_path, &Animation::default());
-        }
+        let model = self.assets.load_mesh(model_path, &Animation::default());
         self.models.insert(self.next_entity_id, model);
         
         self.next_entity_id += 1;
@@ -140,7 +156,7 @@ impl EntityManager {
         // CYLINDER PASS
         let cyl = cylinder;
 
-        let cyl_mod = cyl.create_model(12);
+        let cyl_mod = self.assets.add_mesh(cyl.create_model(12));
         self.cylinders.insert(self.next_entity_id, cyl);
         
         self.models.insert(self.next_entity_id, cyl_mod);
@@ -169,41 +185,15 @@ impl EntityManager {
             original_rotation: rot_correction,
         };
 
-        let (skellington, mut animator, animation) = import_bone_data(animation_path);
-
-        for prop in animation_props.iter() {
-            let anim = animator.animations.get_mut(&prop.name).unwrap();
-            for (k, v) in prop.one_shots.iter() {
-                for frame in v.iter() {
-                    anim.one_shots.push(OneShot {
-                        sound_type: k.clone(),
-                        segment: *frame,
-                        triggered: false.into(),
-                    });
-                }
-            }
-
-            for cs in prop.continuous_sounds.iter() {
-                anim.continuous_sounds.push(ContinuousSound {
-                    sound_type: cs.clone(),
-                    playing: false.into(),
-                });
-            }
-        }
+        // glTF files carry the skeleton and clips alongside the mesh, so bone_path can either point
+        // at the same file or be left empty.
+        let animation_path = if animation_path.is_empty() && is_gltf(model_path) { model_path } else { animation_path };
+        let skeleton = self.assets.load_skeleton(animation_path);
+        let (skellington, mut animator) = self.assets.skeleton(skeleton).unwrap().instantiate();
 
-        let mut model = Model::new();
-        let mut found = false;
-        for m in self.ani_models.iter_mut() {
-            if m.value().full_path == *model_path.to_string() {
-                println!("FOUND DUPLICATE MODEL, CLONING");
-                model = m.value().clone();
-                found = true;
-            }
-        }
+        apply_animation_props(&mut animator, animation_props);
 
-        if !found {
-            model = import_model_data(model_path, &animation);
-        }         
+        let model = self.assets.load_skinned_mesh(model_path, skeleton);
 
         let starting_rot = rotation * rot_correction;
 
@@ -219,7 +209,8 @@ impl EntityManager {
             self.destinations.insert(self.next_entity_id, position);
         }
         self.animators.insert(self.next_entity_id, animator);
-        self.skellingtons.insert(self.next_entity_id, skellington.clone());
+        self.skellingtons.insert(self.next_entity_id, skellington);
+        self.skeleton_handles.insert(self.next_entity_id, skeleton);
         self.transforms.insert(self.next_entity_id, transform);
         self.factions.insert(self.next_entity_id, faction.clone());
         self.ani_models.insert(self.next_entity_id, model);
@@ -239,7 +230,7 @@ impl EntityManager {
 
         let cyl = cylinder;
 
-        let cyl_mod = cyl.create_model(12);
+        let cyl_mod = self.assets.add_mesh(cyl.create_model(12));
         self.cylinders.insert(self.next_entity_id, cyl);
         
         self.models.insert(self.next_entity_id, cyl_mod);
@@ -259,6 +250,70 @@ impl EntityManager {
         self.next_entity_id += 1;
     }
 
+    /// Pushes archetype changes from a reloaded entity_config.json onto the live entities: scale,
+    /// hit cylinders, material params, render flags and animation sound notifies. Mesh and bone paths are only read at spawn time.
+    pub fn apply_entity_config(&mut self, ec: &EntityConfig, sm: &mut SoundManager) {
+        let ids: Vec<(usize, EntityType)> = self.entity_types
+            .iter()
+            .map(|e| (e.key(), e.value().clone()))
+            .collect();
+
+        for (id, entity_type) in ids {
+            let Some(archetype) = ec.entity_types.get(&entity_type) else {
+                continue;
+            };
+
+            if let Some(trans) = self.transforms.get_mut(id) {
+                trans.scale = archetype.scale_correction.into();
+            }
+
+            if self.material_params.contains(id) {
+                self.material_params.insert(id, archetype.material);
+            }
+            if self.render_flags.contains(id) {
+                self.render_flags.insert(id, archetype.render);
+            }
+
+            if let Some(animator) = self.animators.get_mut(id) {
+                // Continuous sounds get restarted by the renderer once their playing flag is reset
+                sm.cleanup_entity_sounds(id);
+                apply_animation_props(animator, &archetype.animation_properties);
+            }
+
+            let cylinder_ids: Vec<usize> = self.parents
+                .iter()
+                .filter(|p| p.value().parent_id == id && self.cylinders.contains(p.key()))
+                .map(|p| p.key())
+                .collect();
+
+            for cyl_id in cylinder_ids {
+                if let Some(handle) = self.models.get(cyl_id) {
+                    self.assets.release_mesh(*handle);
+                }
+                let cyl = archetype.hit_cyl.clone();
+                let cyl_mod = self.assets.add_mesh(cyl.create_model(12));
+                self.models.insert(cyl_id, cyl_mod);
+                self.cylinders.insert(cyl_id, cyl);
+            }
+        }
+    }
+
+    /// Deletes everything that came from world_data and spawns it again. Entity ids aren't reused
+    /// so every reload eats into max_entities.
+    pub fn reload_level(&mut self, ec: &mut EntityConfig, wd: &mut WorldData, sm: &mut SoundManager, errors: &mut Vec<LoadError>) {
+        let ids: Vec<usize> = self.entity_types
+            .iter()
+            .filter(|e| *e.value() != EntityType::Terrain)
+            .map(|e| e.key())
+            .collect();
+
+        self.entity_trashcan.extend(ids);
+        self.delete_entities(sm);
+        self.selected.clear();
+
+        self.populate_initial_entity_data(ec, wd, errors);
+    }
+
     pub fn update(&mut self, sm: &mut SoundManager) {
         self.delete_entities(sm);
     }
@@ -269,12 +324,25 @@ impl EntityManager {
             self.transforms.remove(*id);
             self.factions.remove(*id);
             self.entity_types.remove(*id);
+            if let Some(handle) = self.models.get(*id) {
+                self.assets.release_mesh(*handle);
+            }
+            if let Some(handle) = self.ani_models.get(*id) {
+                self.assets.release_mesh(*handle);
+            }
+            if let Some(handle) = self.skeleton_handles.get(*id) {
+                self.assets.release_skeleton(*handle);
+            }
             self.models.remove(*id);
             self.ani_models.remove(*id);
             self.animators.remove(*id);
             self.skellingtons.remove(*id);
+            self.skeleton_handles.remove(*id);
             self.rotators.remove(*id);
             self.sim_states.remove(*id);
+            self.material_params.remove(*id);
+            self.render_flags.remove(*id);
+            self.world_bounds.remove(*id);
             self.destinations.remove(*id);
             self.cylinders.remove(*id);
             self.parents.remove(*id);
@@ -284,6 +352,45 @@ impl EntityManager {
         self.entity_trashcan.clear();
     }
 
+    /// Refits every model's world space box to its current transform for frustum culling.
+    pub fn update_world_bounds(&mut self) {
+        for entry in self.models.iter().chain(self.ani_models.iter()) {
+            let id = entry.key();
+            let (Some(model), Some(trans)) = (self.assets.mesh(entry.value), self.transforms.get(id)) else {
+                continue;
+            };
+            if model.bounds.is_empty() {
+                continue;
+            }
+
+            let mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
+            self.world_bounds.insert(id, model.bounds.transformed(&mat));
+        }
+    }
+
+    /// Every static mesh the renderer should draw, gizmos like hit cylinders are left out.
+    pub fn static_renderables(&self) -> impl Iterator<Item = usize> + '_ {
+        self.models
+            .iter()
+            .map(|entry| entry.key())
+            .filter(|id| self.factions.get(*id) != Some(&Faction::Gizmo))
+    }
+
+    /// Every skinned mesh, sorted by mesh so entities sharing a model draw back to back.
+    pub fn skinned_renderables(&self) -> Vec<usize> {
+        let mut ids: Vec<(MeshHandle, usize)> = self.ani_models.iter().map(|entry| (entry.value, entry.key())).collect();
+        ids.sort();
+        ids.into_iter().map(|(_, id)| id).collect()
+    }
+
+    pub fn model(&self, id: usize) -> Option<&Model> {
+        self.models.get(id).and_then(|h| self.assets.mesh(*h))
+    }
+
+    pub fn ani_model(&self, id: usize) -> Option<&Model> {
+        self.ani_models.get(id).and_then(|h| self.assets.mesh(*h))
+    }
+
     pub fn get_ids_for_faction(&self, faction: Faction) -> Vec<usize> {
         let result: Vec<usize> = self.factions
             .iter()
@@ -298,19 +405,36 @@ impl EntityManager {
 
             result
     }
+}
 
-    pub fn get_ids_for_type(&self, entity_type: EntityType) -> Vec<usize> {
-        let result: Vec<usize> = self.entity_types
-            .iter()
-            .filter_map(|f|
-                if *f.value() == entity_type {
-                    Some(f.key())
-                } else {
-                    None
-                }
-            )
-            .collect();
+/// Rebuilds the sound notifies on every clip from an archetype's animation properties.
+fn apply_animation_props(animator: &mut Animator, animation_props: &[AnimationPropHelper]) {
+    for anim in animator.animations.values_mut() {
+        anim.one_shots.clear();
+        anim.continuous_sounds.clear();
+    }
 
-            result
+    for prop in animation_props.iter() {
+        let Some(anim) = animator.animations.get_mut(&prop.name) else {
+            println!("WARNING: no {} clip to attach sounds to", prop.name);
+            continue;
+        };
+
+        for (k, v) in prop.one_shots.iter() {
+            for frame in v.iter() {
+                anim.one_shots.push(OneShot {
+                    sound_type: k.clone(),
+                    segment: *frame,
+                    triggered: false.into(),
+                });
+            }
+        }
+
+        for cs in prop.continuous_sounds.iter() {
+            anim.continuous_sounds.push(ContinuousSound {
+                sound_type: cs.clone(),
+                playing: false.into(),
+            });
+        }
     }
 }
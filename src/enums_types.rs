@@ -9,7 +9,8 @@ pub enum VaoType {
     Cube,
     Skybox,
     DebugLight,
-    GroundPlane
+    GroundPlane,
+    Quad,
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -30,6 +31,7 @@ pub enum EboType {
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub enum FboType {
     DepthMap,
+    PointShadow,
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -37,6 +39,7 @@ pub enum ShaderType {
     Skybox,
     DebugLight,
     Depth,
+    PointShadow,
     GroundPlane,
     DebugShadowMap,
     Model,
@@ -44,6 +47,13 @@ pub enum ShaderType {
     Gizmo,
     Particles,
     GameUi,
+    Skeleton,
+    BloomDownsample,
+    BloomUpsample,
+    Tonemap,
+    SsaoPrepass,
+    Ssao,
+    SsaoBlur,
 }
 
 /// A struct to carry some rotation state for blending between rotations smoothly
@@ -141,7 +151,7 @@ impl Display for TextureType {
             TextureType::Diffuse => write!(f, "Diffuse"),
             TextureType::Specular => write!(f, "Specular"),
             TextureType::Emissive => write!(f, "Emissive"),
-            TextureType::NormalMap => write!(f, "Normal Map"),
+            TextureType::NormalMap => write!(f, "NormalMap"),
             TextureType::Roughness => write!(f, "Roughness"),
             TextureType::Metalness => write!(f, "Metalness"),
             TextureType::Displacement => write!(f, "Displacement"),
@@ -202,7 +212,7 @@ impl AnimationType {
             "Idle" => Some(AnimationType::Idle),
             "Death" => Some(AnimationType::Death),
             "Dance" => Some(AnimationType::Dance),
-            _ => panic!("Invalid AnimationType passed in."),
+            _ => None,
         }
     }
 
@@ -0,0 +1,71 @@
+use std::fmt;
+
+/// Everything that can go wrong while loading configs and assets. Loaders hand these back instead
+/// of panicking so GameState can report all of them at once and fall back to placeholders.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file couldn't be opened or read at all
+    Io { path: String, message: String },
+    /// The file was read but something in it is wrong. Lines are 1 based.
+    Parse { path: String, line: Option<usize>, field: Option<String>, message: String },
+    Image { path: String, message: String },
+    Shader { path: String, log: String },
+    Gltf { path: String, message: String },
+    Sound { name: String, event: String, code: i32 },
+}
+
+pub type LoadResult<T> = Result<T, LoadError>;
+
+impl LoadError {
+    pub fn io(path: &str, e: std::io::Error) -> Self {
+        LoadError::Io {
+            path: path.to_string(),
+            message: e.to_string(),
+        }
+    }
+
+    pub fn parse(path: &str, line: Option<usize>, field: Option<&str>, message: impl Into<String>) -> Self {
+        LoadError::Parse {
+            path: path.to_string(),
+            line,
+            field: field.map(|f| f.to_string()),
+            message: message.into(),
+        }
+    }
+
+    pub fn json(path: &str, e: serde_json::Error) -> Self {
+        // serde_json tacks " at line x column y" onto its message, we keep the line separately
+        let message = e.to_string();
+        let message = message.split(" at line ").next().unwrap_or_default();
+        Self::parse(path, Some(e.line()), None, message)
+    }
+
+    pub fn toml(path: &str, source: &str, e: toml::de::Error) -> Self {
+        let line = e.span().map(|span| source[..span.start].matches('\n').count() + 1);
+        Self::parse(path, line, None, e.message())
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, message } => write!(f, "{}: {}", path, message),
+            LoadError::Parse { path, line, field, message } => {
+                write!(f, "{}", path)?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                if let Some(field) = field {
+                    write!(f, " ({})", field)?;
+                }
+                write!(f, ": {}", message)
+            }
+            LoadError::Image { path, message } => write!(f, "{}: bad image: {}", path, message),
+            LoadError::Shader { path, log } => write!(f, "{}: {}", path, log.trim_end()),
+            LoadError::Gltf { path, message } => write!(f, "{}: {}", path, message),
+            LoadError::Sound { name, event, code } => write!(f, "sound {} ({}): FMOD error {}", name, event, code),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
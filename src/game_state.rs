@@ -7,7 +7,7 @@ use glfw::{Context, Glfw, GlfwReceiver, Key, PWindow, WindowEvent};
 use image::GrayImage;
 use rusttype::{point, Font, Scale};
 
-use crate::{animation::animation_system, camera::Camera, collision_system, config::{entity_config::{self, EntityConfig}, game_config::GameConfig, world_data::WorldData}, debug::{gizmos::Cylinder, write::write_data}, entity_manager::{self, EntityManager}, enums_types::{AnimationType, CameraState, EntityType, Faction, ShaderType, SimState, Transform}, gl_call, grid::Grid, input::{handle_keyboard_input, handle_mouse_input}, lights::{DirLight, Lights}, movement_system, particles::{Emitter, ParticleSystem}, renderer::Renderer, sound::{fmod::FMOD_Studio_System_Update, sound_manager::SoundManager}, state_machines, terrain::Terrain, ui::{font::{self, FontManager}, game_ui::{self, GameUiContext}, imgui::ImguiManager, message_queue::{MessageQueue, UiMessage}}};
+use crate::{animation::animation_system, camera::Camera, capture::Capture, collision_system, config::{entity_config::{self, EntityConfig}, game_config::GameConfig, watcher::{ConfigFile, ConfigWatcher, ENTITY_CONFIG_PATH, GAME_CONFIG_PATH, WORLD_DATA_PATH}, world_data::WorldData}, debug::{gizmos::Cylinder, write::write_data}, entity_manager::{self, EntityManager}, enums_types::{AnimationType, CameraState, EntityType, Faction, ShaderType, SimState, Transform}, error::LoadError, gl_call, grid::Grid, input::{handle_keyboard_input, handle_mouse_input}, lights::{DirLight, Lights}, movement_system, offscreen::OffscreenTarget, particles::{Emitter, ParticleSystem}, renderer::Renderer, sound::{fmod::FMOD_Studio_System_Update, sound_manager::SoundManager}, state_machines, terrain::Terrain, time_of_day::TimeOfDay, ui::{font::{self, FontManager}, game_ui::{self, GameUiContext}, imgui::ImguiManager, message_queue::{MessageQueue, UiMessage}}};
 // use rand::prelude::*;
 // use rand_chacha::ChaCha8Rng;
 
@@ -28,6 +28,8 @@ pub struct GameState {
 
     pub entity_manager: EntityManager,
     pub light_manager: Lights,
+    pub time_of_day: TimeOfDay,
+    pub capture: Capture,
     pub imgui_manager: ImguiManager,
 
 
@@ -50,19 +52,37 @@ pub struct GameState {
 
     pub message_queue: MessageQueue,
     pub game_ui_context: GameUiContext,
+
+    pub game_config: GameConfig,
+    pub entity_config: EntityConfig,
+    pub config_watcher: ConfigWatcher,
+
+    // Everything that failed to load and got replaced by a fallback, shown in the Load Errors window
+    pub load_errors: Vec<LoadError>,
 }
 
 impl GameState {
     pub fn new() -> Self {
+        Self::create(None)
+    }
+
+    /// Same game behind a hidden window, for rendering into an OffscreenTarget. Any GL 4.6
+    /// context will do, Mesa's llvmpipe included (see golden.rs for the environment it needs).
+    pub fn new_headless(width: u32, height: u32) -> Self {
+        Self::create(Some((width, height)))
+    }
+
+    fn create(headless: Option<(u32, u32)>) -> Self {
         let mut glfw = glfw::init(glfw::fail_on_errors).expect("Failed to init glfw");
 
         glfw.window_hint(glfw::WindowHint::ContextVersion(4, 6)); // OpenGL 3.3
         glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
         glfw.window_hint(glfw::WindowHint::Resizable(true));
+        glfw.window_hint(glfw::WindowHint::Visible(headless.is_none()));
         #[cfg(target_os = "macos")]
         glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
 
-        let (mut width, mut height):(i32, i32) = (1920, 1080);
+        let (mut width, mut height):(i32, i32) = headless.map(|(w, h)| (w as i32, h as i32)).unwrap_or((1920, 1080));
 
         let (mut window, events) = glfw
             .create_window(width as u32, height as u32, "Hello this is window", glfw::WindowMode::Windowed)
@@ -75,6 +95,9 @@ impl GameState {
         window.make_current();
 
         glfw.with_primary_monitor(|_glfw, maybe_monitor| {
+            if headless.is_some() {
+                return;
+            }
             if let Some(monitor) = maybe_monitor {
                 if let Some(video_mode) = monitor.get_video_mode() {
                     // Extract the current resolution & refresh rate from the monitor
@@ -96,7 +119,9 @@ impl GameState {
             }
         });
 
-        glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
+        if headless.is_none() {
+            glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
+        }
 
 
         let (fb_width, fb_height) = window.get_framebuffer_size();
@@ -122,16 +147,30 @@ impl GameState {
         let mut light_manager = Lights::new(50);
         light_manager.dir_light = DirLight::default_white();
 
-        let renderer = Renderer::new();
+        // Nothing in here bails on a bad file. Each failure gets recorded, replaced with a fallback
+        // and the whole list gets reported once everything is up.
+        let mut load_errors = vec![];
+
+        let renderer = Renderer::new(&mut load_errors);
 
-        let game_config = GameConfig::load_from_file("config/game_config.json");
+        let game_config = GameConfig::load_from_file(GAME_CONFIG_PATH).unwrap_or_else(|e| {
+            load_errors.push(e);
+            GameConfig::fallback()
+        });
 
-        let sound_manager = SoundManager::new(&game_config);
+        let sound_manager = SoundManager::new(&game_config, &mut load_errors);
 
-        let mut entity_config = EntityConfig::load_from_file("config/entity_config.json");
-        let mut world_data = WorldData::load_from_file("config/world_data.toml");
+        let mut entity_config = EntityConfig::load_from_file(ENTITY_CONFIG_PATH).unwrap_or_else(|e| {
+            load_errors.push(e);
+            EntityConfig::empty()
+        });
+        let mut world_data = WorldData::load_from_file(WORLD_DATA_PATH).unwrap_or_else(|e| {
+            load_errors.push(e);
+            WorldData { time_of_day: None, entities: vec![] }
+        });
         let mut entity_manager = EntityManager::new(10_000);
-        entity_manager.populate_initial_entity_data(&mut entity_config, &mut world_data);
+        entity_manager.populate_initial_entity_data(&mut entity_config, &mut world_data, &mut load_errors);
+        let time_of_day = TimeOfDay::new(&game_config.time_of_day, world_data.time_of_day);
 
         let mut grid = Grid::new(game_config.grid_width, game_config.grid_height, game_config.cell_size);
         grid.generate();
@@ -140,7 +179,10 @@ impl GameState {
 
         //TEMP
 
-        let mut terrain = Terrain::from_height_map("resources/textures/grid_height.png");
+        let mut terrain = Terrain::from_height_map("resources/textures/grid_height.png").unwrap_or_else(|e| {
+            load_errors.push(e);
+            Terrain::flat(100, 100)
+        });
         let model = terrain.into_opengl_model();
 
         entity_manager.transforms.insert(entity_manager.next_entity_id, Transform {
@@ -162,11 +204,19 @@ impl GameState {
         font_manager.setup_buffers();
 
         let mut particles = ParticleSystem::new();
-        // particles.spawn_continuous_emitter(100, vec3(10.0, 20.0, 10.0), "Smoke", Some("resources/textures/smoke.png"));
-         // particles.spawn_continuous_emitter(50, Vec3::splat(0.0), "Smoke", None);
+        // particles.spawn_continuous_emitter(100, vec3(10.0, 20.0, 10.0), "Smoke", Some("resources/textures/smoke.png"), &mut entity_manager.assets);
+         // particles.spawn_continuous_emitter(50, Vec3::splat(0.0), "Smoke", None, &mut entity_manager.assets);
 
         let ui_ctx = GameUiContext::new();
 
+        load_errors.append(&mut entity_manager.assets.errors);
+        if !load_errors.is_empty() {
+            println!("=== {} problems while loading ===", load_errors.len());
+            for e in load_errors.iter() {
+                println!("{}", e);
+            }
+        }
+
         Self {
             delta_time: 0.0,
             last_frame: 0.0,
@@ -183,6 +233,8 @@ impl GameState {
 
             entity_manager,
             light_manager,
+            time_of_day,
+            capture: Capture::new(),
             imgui_manager,
 
             paused: false,
@@ -202,6 +254,49 @@ impl GameState {
             particles,
             message_queue: MessageQueue::new(), 
             game_ui_context: ui_ctx,
+
+            game_config,
+            entity_config,
+            config_watcher: ConfigWatcher::new(),
+
+            load_errors,
+        }
+    }
+
+    /// Applies edits to the files in config/ without a restart. A file that fails to parse leaves
+    /// the current config in place.
+    fn hot_reload_configs(&mut self) {
+        for file in self.config_watcher.poll() {
+            let result = match file {
+                ConfigFile::Entity => EntityConfig::load_from_file(file.path()).map(|ec| {
+                    self.entity_manager.apply_entity_config(&ec, &mut self.sound_manager);
+                    self.entity_config = ec;
+                }),
+                // Only the sound mappings are live, grid and window settings are read once at startup
+                ConfigFile::Game => GameConfig::load_from_file(file.path()).map(|gc| {
+                    self.sound_manager.reload_sounds(&gc);
+                    self.time_of_day.apply_config(&gc.time_of_day);
+                    self.game_config = gc;
+                }),
+                ConfigFile::World => {
+                    if !self.config_watcher.reload_level {
+                        continue;
+                    }
+                    WorldData::load_from_file(file.path()).map(|mut wd| {
+                        self.entity_manager.reload_level(&mut self.entity_config, &mut wd, &mut self.sound_manager, &mut self.load_errors);
+                        self.time_of_day.start_level(wd.time_of_day);
+                    })
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    self.config_watcher.errors.remove(&file);
+                }
+                Err(e) => {
+                    self.config_watcher.errors.insert(file, e.to_string());
+                }
+            }
         }
     }
 
@@ -243,6 +338,16 @@ impl GameState {
                                 self.message_queue.send(UiMessage::PauseToggle);
                             }
                         },
+                        glfw::Key::F12 => {
+                            if action == glfw::Action::Press {
+                                self.capture.request_screenshot();
+                            }
+                        },
+                        glfw::Key::F10 => {
+                            if action == glfw::Action::Press {
+                                self.capture.toggle_recording();
+                            }
+                        },
                         _ => {}
                     }
                     handle_keyboard_input(key, action, &mut self.pressed_keys);
@@ -261,13 +366,19 @@ impl GameState {
     pub fn update(&mut self) {
         // CALC DELTA TIME
         let current_frame = self.glfw.get_time() as f32;
-        self.delta_time = current_frame - self.last_frame;
+        // Recording a sequence steps at its fixed rate, however slow saving the frames makes us
+        self.delta_time = self.capture.fixed_delta().unwrap_or(current_frame - self.last_frame);
         self.last_frame = current_frame;
         self.elapsed += self.delta_time;
 
         if self.delta_time <= 0.0 {
             return;
         }
+
+        self.renderer.hot_reload_shaders();
+        self.hot_reload_configs();
+        // Assets that failed to load since the last frame (spawns, particles, ui textures)
+        self.load_errors.append(&mut self.entity_manager.assets.errors);
         
         // Fps calc
         let fps_now = (1.0 / self.delta_time.max(0.0001)) as u32;
@@ -276,7 +387,7 @@ impl GameState {
             self.last_fps_update = self.elapsed;
         }
 
-        self.particles.update(self.delta_time);
+        self.particles.update(self.delta_time, &mut self.entity_manager.assets);
 
         if let Some(player_entry) = self.entity_manager.factions.iter().find(|f| f.value() == &Faction::Player) {
             let player_key = player_entry.key();
@@ -327,6 +438,7 @@ impl GameState {
         self.camera.update(&self.entity_manager, self.delta_time);
         self.sound_manager.update(&self.camera);
         self.light_manager.update(&self.delta_time);
+        self.time_of_day.update(self.delta_time);
 
         // UPDATE SYSTEMS
         movement_system::update(
@@ -385,14 +497,10 @@ impl GameState {
         // ======================================
         // Actually draw stuff
         // ======================================
-        self.renderer.draw(&self.entity_manager, &mut self.camera, &self.light_manager, &mut self.grid, &mut self.sound_manager, self.fb_width, self.fb_height, self.elapsed);
-
-        self.particles.render(
-            self.renderer.shaders.get_mut(&ShaderType::Particles).unwrap(),
-            &self.camera,
-        );
+        self.draw_scene();
+        self.capture.capture_frame(self.fb_width, self.fb_height, false);
         
-        self.imgui_manager.draw(&mut self.window, self.fb_width as f32, self.fb_height as f32, self.delta_time, &mut self.light_manager, &mut self.renderer, &mut self.sound_manager, &self.camera, &mut self.entity_manager);
+        self.imgui_manager.draw(&mut self.window, self.fb_width as f32, self.fb_height as f32, self.delta_time, &mut self.light_manager, &mut self.time_of_day, &mut self.capture, &mut self.renderer, &mut self.sound_manager, &self.camera, &mut self.entity_manager, &mut self.config_watcher, &mut self.load_errors);
 
 
         // let phrase = format!("FPS: {}", self.fps);
@@ -420,6 +528,7 @@ impl GameState {
             &self.camera.move_state,
             &self.pressed_keys,
             &mut self.game_ui_context,
+            &mut self.entity_manager.assets,
         );
 
         if self.message_queue.queue.contains(&UiMessage::WindowShouldClose) {
@@ -430,8 +539,44 @@ impl GameState {
             self.paused = !self.paused;
         }
 
+        self.capture.capture_frame(self.fb_width, self.fb_height, true);
         self.window.swap_buffers();
         self.glfw.poll_events();
         self.message_queue.drain();
     }
+
+    /// Everything render draws except the UI, into whatever Renderer.output_fbo points at.
+    fn draw_scene(&mut self) {
+        // Outside update so entities moved while paused still cull correctly
+        self.entity_manager.update_world_bounds();
+        // Outside update too, so scrubbing the clock while paused shows up
+        self.time_of_day.apply(&mut self.light_manager.dir_light, &mut self.renderer.sky);
+        self.renderer.draw(&self.entity_manager, &mut self.camera, &self.light_manager, &mut self.grid, &mut self.sound_manager, self.fb_width, self.fb_height, self.elapsed);
+
+        self.particles.render(
+            self.renderer.shaders.get_mut(&ShaderType::Particles).unwrap(),
+            &self.camera,
+        );
+        self.renderer.post_process_pass();
+    }
+
+    /// Draws the current state into target instead of the window, without the UI.
+    pub fn render_to_target(&mut self, target: &OffscreenTarget) {
+        let window_size = (self.fb_width, self.fb_height);
+        (self.fb_width, self.fb_height) = (target.width, target.height);
+        self.renderer.output_fbo = target.fbo;
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, target.fbo));
+            gl_call!(gl::Viewport(0, 0, target.width as i32, target.height as i32));
+        }
+
+        self.draw_scene();
+
+        self.renderer.output_fbo = 0;
+        (self.fb_width, self.fb_height) = window_size;
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+            gl_call!(gl::Viewport(0, 0, self.fb_width as i32, self.fb_height as i32));
+        }
+    }
 }
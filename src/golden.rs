@@ -0,0 +1,259 @@
+//! Golden image tests. Renders the scenes in resources/golden/scenes.toml offscreen and compares
+//! them against the PNGs stored next to it, so changes to model.glsl, shadows and the post chain
+//! show up as failures instead of being caught by eye.
+//!
+//!     cargo run -- --golden            compare, exits with 1 if any scene differs
+//!     cargo run -- --golden --bless    write the current renders as the new goldens
+//!     cargo test -- --ignored golden   same comparison as a test, ignored by default since it
+//!                                      needs a GL context
+//!
+//! Failed scenes leave the actual render and a diff (differing pixels in red) in debug_out/golden/.
+//! Scenes load fixture worlds under resources/golden/ rather than the live level in config/, which
+//! the editor saves over.
+//!
+//! Goldens are only comparable between renders from the same GL implementation. Bless and check
+//! them on Mesa's llvmpipe so they mean the same thing on every machine, run with
+//!
+//!     LIBGL_ALWAYS_SOFTWARE=1 MESA_GL_VERSION_OVERRIDE=4.6 MESA_GLSL_VERSION_OVERRIDE=460
+//!
+//! and under xvfb-run on machines without a display, glfw still wants one for the hidden window.
+
+use std::{fs::{create_dir_all, read_to_string}, path::Path};
+
+use glam::Vec3;
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+
+use crate::{animation::animation_system, config::world_data::WorldData, error::{LoadError, LoadResult}, game_state::GameState, offscreen::OffscreenTarget};
+
+const SCENES_PATH: &str = "resources/golden/scenes.toml";
+const GOLDEN_DIR: &str = "resources/golden";
+const OUTPUT_DIR: &str = "debug_out/golden";
+
+#[derive(Deserialize, Debug)]
+struct GoldenScenes {
+    scenes: Vec<GoldenScene>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GoldenScene {
+    name: String,
+    world: String,
+    camera_position: [f32; 3],
+    camera_target: [f32; 3],
+    hour: f32,
+    #[serde(default = "default_width")]
+    width: u32,
+    #[serde(default = "default_height")]
+    height: u32,
+    #[serde(default)]
+    tolerance: Tolerance,
+}
+
+/// Rasterization differs a little between drivers and even Mesa versions, so a pixel only counts
+/// as different past channel_threshold and a scene only fails past max_differing of its pixels.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+struct Tolerance {
+    channel_threshold: u8,
+    max_differing: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self { channel_threshold: 8, max_differing: 0.005 }
+    }
+}
+
+fn default_width() -> u32 { 640 }
+fn default_height() -> u32 { 360 }
+
+impl GoldenScenes {
+    fn load_from_file(file_name: &str) -> LoadResult<Self> {
+        let config_str = read_to_string(file_name).map_err(|e| LoadError::io(file_name, e))?;
+
+        toml::from_str(&config_str).map_err(|e| LoadError::toml(file_name, &config_str, e))
+    }
+}
+
+/// Renders every scene, returns whether all of them matched (or got blessed).
+pub fn run(bless: bool) -> bool {
+    let scenes = match GoldenScenes::load_from_file(SCENES_PATH) {
+        Ok(scenes) => scenes.scenes,
+        Err(e) => {
+            println!("golden: {}", e);
+            return false;
+        }
+    };
+
+    let mut state = GameState::new_headless(default_width(), default_height());
+    // Not fatal, a missing asset shows up in the renders anyway
+    for e in &state.load_errors {
+        println!("golden: {}", e);
+    }
+    create_dir_all(OUTPUT_DIR).expect("Failed to create golden output dir");
+
+    let mut passed = 0;
+    for scene in &scenes {
+        let actual = match render_scene(&mut state, scene) {
+            Ok(image) => image,
+            Err(e) => {
+                println!("FAIL {}: {}", scene.name, e);
+                continue;
+            }
+        };
+
+        let golden_path = format!("{}/{}.png", GOLDEN_DIR, scene.name);
+        if bless {
+            actual.save(&golden_path).expect("Failed to write golden image");
+            println!("BLESS {} -> {}", scene.name, golden_path);
+            passed += 1;
+            continue;
+        }
+
+        if !Path::new(&golden_path).exists() {
+            println!("FAIL {}: no golden at {}, run with --bless to create it", scene.name, golden_path);
+            actual.save(format!("{}/{}_actual.png", OUTPUT_DIR, scene.name)).expect("Failed to write golden output");
+            continue;
+        }
+        let expected = match image::open(&golden_path) {
+            Ok(image) => image.to_rgba8(),
+            Err(e) => {
+                println!("FAIL {}: {}: {}", scene.name, golden_path, e);
+                continue;
+            }
+        };
+
+        match compare(&expected, &actual, scene.tolerance) {
+            Ok(fraction) => {
+                println!("ok   {} ({:.3}% differing)", scene.name, fraction * 100.0);
+                passed += 1;
+            }
+            Err((message, diff)) => {
+                println!("FAIL {}: {}", scene.name, message);
+                actual.save(format!("{}/{}_actual.png", OUTPUT_DIR, scene.name)).expect("Failed to write golden output");
+                if let Some(diff) = diff {
+                    diff.save(format!("{}/{}_diff.png", OUTPUT_DIR, scene.name)).expect("Failed to write golden output");
+                }
+            }
+        }
+    }
+
+    println!("golden: {}/{} scenes passed", passed, scenes.len());
+    passed == scenes.len()
+}
+
+/// Loads the scene's level, freezes the clock at its hour and renders one frame of it.
+fn render_scene(state: &mut GameState, scene: &GoldenScene) -> LoadResult<RgbaImage> {
+    let mut wd = WorldData::load_from_file(&scene.world)?;
+    let mut errors = vec![];
+    state.entity_manager.reload_level(&mut state.entity_config, &mut wd, &mut state.sound_manager, &mut errors);
+    if let Some(e) = errors.pop() {
+        return Err(e);
+    }
+
+    state.time_of_day.start_level(Some(scene.hour));
+    state.time_of_day.paused = true;
+    state.elapsed = 0.0;
+    state.camera.look_at(Vec3::from(scene.camera_position), Vec3::from(scene.camera_target));
+    // Bind poses, models are skinned with whatever the last level left otherwise
+    animation_system::update(&mut state.entity_manager, 0.0);
+
+    let target = OffscreenTarget::new(scene.width, scene.height);
+    state.render_to_target(&target);
+    let image = target.read_pixels();
+    target.release();
+
+    Ok(image)
+}
+
+/// Fraction of differing pixels when within tolerance, otherwise why not and a diff image if the
+/// sizes even match.
+fn compare(expected: &RgbaImage, actual: &RgbaImage, tolerance: Tolerance) -> Result<f32, (String, Option<RgbaImage>)> {
+    if expected.dimensions() != actual.dimensions() {
+        let message = format!("size {:?} doesn't match the golden's {:?}", actual.dimensions(), expected.dimensions());
+        return Err((message, None));
+    }
+
+    let mut differing = 0;
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    for ((e, a), d) in expected.pixels().zip(actual.pixels()).zip(diff.pixels_mut()) {
+        let over = e.0.iter().zip(a.0.iter()).any(|(e, a)| e.abs_diff(*a) > tolerance.channel_threshold);
+        *d = if over {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // Dimmed so the red stands out against the scene
+            Rgba([a[0] / 4, a[1] / 4, a[2] / 4, 255])
+        };
+    }
+
+    let fraction = differing as f32 / (actual.width() * actual.height()) as f32;
+    if fraction > tolerance.max_differing {
+        let message = format!("{:.3}% of pixels differ, {:.3}% allowed", fraction * 100.0, tolerance.max_differing * 100.0);
+        return Err((message, Some(diff)));
+    }
+    Ok(fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::{compare, Tolerance};
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([(x * 7) as u8, (y * 11) as u8, 128, 255]))
+    }
+
+    #[test]
+    #[ignore = "needs a GL 4.6 context, see the module docs"]
+    fn golden_scenes() {
+        assert!(super::run(false), "golden scenes differ, see debug_out/golden/");
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = gradient(32, 16);
+        assert_eq!(compare(&image, &image, Tolerance::default()), Ok(0.0));
+    }
+
+    #[test]
+    fn noise_within_threshold_matches() {
+        let expected = gradient(32, 16);
+        let mut actual = expected.clone();
+        for pixel in actual.pixels_mut() {
+            pixel[0] = pixel[0].saturating_add(3);
+            pixel[2] -= 5;
+        }
+
+        assert_eq!(compare(&expected, &actual, Tolerance::default()), Ok(0.0));
+    }
+
+    #[test]
+    fn differences_past_threshold_fail() {
+        let expected = gradient(32, 16);
+        let mut actual = expected.clone();
+        // 8 of 512 pixels, past the default 0.5%
+        for x in 0..8 {
+            actual.put_pixel(x, 0, Rgba([255, 255, 255, 255]));
+        }
+
+        let Err((message, Some(diff))) = compare(&expected, &actual, Tolerance::default()) else {
+            panic!("expected a failure with a diff image");
+        };
+        assert!(message.contains("of pixels differ"), "{}", message);
+        assert_eq!(diff.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_ne!(diff.get_pixel(8, 0), &Rgba([255, 0, 0, 255]));
+
+        // Same pixels are fine once enough of them are allowed to differ
+        let loose = Tolerance { max_differing: 0.05, ..Tolerance::default() };
+        assert_eq!(compare(&expected, &actual, loose), Ok(8.0 / 512.0));
+    }
+
+    #[test]
+    fn size_mismatch_fails_without_diff() {
+        let result = compare(&gradient(32, 16), &gradient(16, 16), Tolerance::default());
+        assert!(matches!(result, Err((_, None))));
+    }
+}
@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 use std::fs::read_to_string;
 
-use glam::{vec2, Vec3};
+use glam::{vec2, Vec3, Vec4};
 use glam::vec3;
 use image::{ImageBuffer, Rgba};
 
@@ -83,7 +83,9 @@ impl Grid {
         let mut model = self.generate_grid_mesh();
         model.directory = "resources/textures".to_string();
 
-        texture_from_file(&mut model, "half_dark_half_light.png".to_string(), TextureType::Diffuse);
+        if let Err(e) = texture_from_file(&mut model, 0, "half_dark_half_light.png".to_string(), TextureType::Diffuse) {
+            println!("WARNING: {}", e);
+        }
         self.model = model;
     }
 
@@ -124,10 +126,10 @@ impl Grid {
 
 
                 // Add vertices for the cell
-                vertices.push(Vertex { position: vec3(x, 0.0, z), normal: vec3(0.0, 1.0, 0.0), uv: bl, bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
-                vertices.push(Vertex { position: vec3(x + self.cell_size, 0.0, z), normal: vec3(0.0, 1.0, 0.0), uv: br,bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
-                vertices.push(Vertex { position: vec3(x + self.cell_size, 0.0, z + self.cell_size), normal: vec3(0.0, 1.0, 0.0), uv: tr, bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
-                vertices.push(Vertex { position: vec3(x, 0.0, z + self.cell_size), normal: vec3(0.0, 1.0, 0.0), uv: tl, bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
+                vertices.push(Vertex { position: vec3(x, 0.0, z), normal: vec3(0.0, 1.0, 0.0), uv: bl, tangent: Vec4::X, bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
+                vertices.push(Vertex { position: vec3(x + self.cell_size, 0.0, z), normal: vec3(0.0, 1.0, 0.0), uv: br, tangent: Vec4::X, bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
+                vertices.push(Vertex { position: vec3(x + self.cell_size, 0.0, z + self.cell_size), normal: vec3(0.0, 1.0, 0.0), uv: tr, tangent: Vec4::X, bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
+                vertices.push(Vertex { position: vec3(x, 0.0, z + self.cell_size), normal: vec3(0.0, 1.0, 0.0), uv: tl, tangent: Vec4::X, bone_ids: [-1; MAX_BONE_INFLUENCE], bone_weights: [0.0; MAX_BONE_INFLUENCE] });
 
                 // Add indices for two triangles
                 // flipped winding
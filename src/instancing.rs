@@ -0,0 +1,122 @@
+use glam::Mat4;
+
+use crate::{assets::MeshHandle, config::entity_config::{MaterialParams, RenderFlags}, entity_manager::EntityManager, gl_call, some_data::INSTANCE_BUFFER_BINDING};
+
+// Bits in GpuInstance.flags, must match model.glsl
+pub const INSTANCE_SELECTED: u32 = 1;
+pub const INSTANCE_FLASHING: u32 = 2;
+
+/// std430 layout of one entry in the InstanceBuffer SSBO read by model.glsl and the depth shaders.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GpuInstance {
+    pub model: Mat4,
+    // x INSTANCE_* bits, the rest is padding
+    pub flags: [u32; 4],
+}
+
+/// Entities sharing a mesh, material params and render flags, drawn with one instanced call per
+/// submesh.
+pub struct InstanceBatch {
+    pub mesh: MeshHandle,
+    pub material: MaterialParams,
+    pub flags: RenderFlags,
+    // Range of this batch in the uploaded instance array
+    pub offset: u32,
+    pub count: u32,
+}
+
+/// Groups static models into batches and keeps their instance data in one SSBO. Shaders find their
+/// instance at instance_offset + gl_InstanceID.
+pub struct InstanceBuffer {
+    pub batches: Vec<InstanceBatch>,
+    instances: Vec<GpuInstance>,
+    ssbo: u32,
+}
+
+impl InstanceBuffer {
+    pub fn new() -> Self {
+        let mut ssbo = 0;
+        unsafe {
+            gl_call!(gl::GenBuffers(1, &mut ssbo));
+        }
+
+        Self {
+            batches: Vec::new(),
+            instances: Vec::new(),
+            ssbo,
+        }
+    }
+
+    /// Rebuilds the batches for these entities and uploads their transforms and flags. Batches are
+    /// sorted by render flags and then mesh so state changes between draws stay rare.
+    pub fn fill(&mut self, em: &EntityManager, ids: impl Iterator<Item = usize>) {
+        let mut grouped: Vec<(MeshHandle, MaterialParams, RenderFlags, Vec<GpuInstance>)> = Vec::new();
+
+        for id in ids {
+            let (Some(mesh), Some(trans)) = (em.models.get(id), em.transforms.get(id)) else {
+                continue;
+            };
+            let material = em.material_params.get(id).copied().unwrap_or_default();
+            let render_flags = em.render_flags.get(id).copied().unwrap_or_default();
+
+            let mut flags = 0;
+            if em.selected.contains(&id) {
+                flags |= INSTANCE_SELECTED;
+            }
+            if em.v_effects.get(id).is_some() {
+                flags |= INSTANCE_FLASHING;
+            }
+
+            let instance = GpuInstance {
+                model: Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position),
+                flags: [flags, 0, 0, 0],
+            };
+
+            match grouped.iter_mut().find(|(m, p, f, _)| *m == *mesh && *p == material && *f == render_flags) {
+                Some((_, _, _, instances)) => instances.push(instance),
+                None => grouped.push((*mesh, material, render_flags, vec![instance])),
+            }
+        }
+        grouped.sort_by_key(|(mesh, _, flags, _)| (flags.double_sided, flags.alpha_test, *mesh));
+
+        self.batches.clear();
+        self.instances.clear();
+        for (mesh, material, flags, instances) in grouped {
+            self.batches.push(InstanceBatch {
+                mesh,
+                material,
+                flags,
+                offset: self.instances.len() as u32,
+                count: instances.len() as u32,
+            });
+            self.instances.extend(instances);
+        }
+
+        if self.instances.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.ssbo));
+            gl_call!(gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                std::mem::size_of_val(self.instances.as_slice()) as isize,
+                self.instances.as_ptr().cast(),
+                gl::DYNAMIC_DRAW
+            ));
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0));
+        }
+    }
+
+    /// Several InstanceBuffers share the binding point, so bind right before drawing.
+    pub fn bind(&self) {
+        if self.instances.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl_call!(gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, INSTANCE_BUFFER_BINDING, self.ssbo));
+        }
+    }
+}
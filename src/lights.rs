@@ -1,9 +1,9 @@
 #![allow(dead_code)]
 use std::collections::HashMap;
 
-use glam::{vec3, Vec3};
+use glam::{vec3, vec4, Vec3, Vec4};
 
-use crate::{some_data::WHITE, sparse_set::SparseSet};
+use crate::{some_data::{MAX_SHADOW_POINT_LIGHTS, WHITE}, sparse_set::SparseSet};
 
 pub struct PointStrength {
     pub constant: f32,
@@ -37,6 +37,9 @@ pub struct PointLight {
     pub constant: f32,
     pub linear: f32, 
     pub quadratic: f32,
+
+    // Only the first MAX_SHADOW_POINT_LIGHTS casters get a cube map
+    pub casts_shadow: bool,
 }
 
 impl PointLight {
@@ -54,10 +57,73 @@ impl PointLight {
             constant: 1.0,
             linear: 0.09,
             quadratic: 0.032,
+            casts_shadow: false,
+        }
+    }
+}
+
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+
+    pub diffuse: Vec3,
+
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+
+    // Half angles in degrees, full strength inside inner fading to nothing at outer
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl SpotLight {
+    pub fn with_default_strength(position: Vec3, direction: Vec3, diffuse: Vec3) -> Self {
+        Self {
+            position,
+            direction,
+            diffuse,
+            constant: 1.0,
+            linear: 0.09,
+            quadratic: 0.032,
+            inner_angle: 12.5,
+            outer_angle: 17.5,
         }
     }
 }
 
+/// Distance where the attenuation drops below 1/256, anything further is skipped in model.glsl and
+/// it doubles as the far plane for point shadows.
+pub fn attenuation_range(constant: f32, linear: f32, quadratic: f32) -> f32 {
+    let cutoff = 256.0;
+    if quadratic > 0.0 {
+        (-linear + (linear * linear - 4.0 * quadratic * (constant - cutoff)).sqrt()) / (2.0 * quadratic)
+    } else if linear > 0.0 {
+        (cutoff - constant) / linear
+    } else {
+        1000.0
+    }
+}
+
+pub const LIGHT_POINT: f32 = 0.0;
+pub const LIGHT_SPOT: f32 = 1.0;
+
+/// std430 layout of one entry in the LightBuffer SSBO in model.glsl.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct GpuLight {
+    // xyz position, w LIGHT_POINT or LIGHT_SPOT
+    pub position: Vec4,
+    // xyz spot direction, w cos of the outer angle
+    pub direction: Vec4,
+    // rgb color, w cos of the inner angle
+    pub color: Vec4,
+    // constant, linear, quadratic, range
+    pub attenuation: Vec4,
+    // x shadow cube index or -1
+    pub params: Vec4,
+}
+
 pub struct DirLight {
     pub direction: Vec3,
     pub view_pos: Vec3,
@@ -111,14 +177,18 @@ impl DirLight {
 pub struct Lights {
     next_light_id: usize,
     pub point_lights: SparseSet<PointLight>,
+    pub spot_lights: SparseSet<SpotLight>,
     pub velocities: SparseSet<Vec3>,
     pub point_strengths: HashMap<u32, PointStrength>,
 
     pub dir_light: DirLight,
 
-    pub near: f32,
-    pub far: f32,
-    pub bounds: f32,
+    // Cascaded shadow maps cover the camera frustum out to shadow_distance
+    pub shadow_distance: f32,
+    // 0 splits the cascades evenly, 1 logarithmically
+    pub cascade_lambda: f32,
+    // How far behind each cascade casters still get rendered
+    pub cascade_z_extension: f32,
     
     pub bias_scalar: f32
 }
@@ -129,14 +199,15 @@ impl Lights {
         Self {
             next_light_id: 0,
             point_lights: SparseSet::with_capacity(max_lights),
+            spot_lights: SparseSet::with_capacity(max_lights),
             velocities: SparseSet::with_capacity(max_lights),
             point_strengths,
 
             dir_light: DirLight::default_white(),
 
-            near: 0.6,
-            far: 115.756,
-            bounds: 17.5,
+            shadow_distance: 120.0,
+            cascade_lambda: 0.75,
+            cascade_z_extension: 50.0,
 
             bias_scalar: 0.002,
         }
@@ -152,6 +223,74 @@ impl Lights {
         self.next_light_id += 1;
     }
 
+    pub fn add_spot_light(&mut self, mut light: SpotLight, distance: u32) {
+        if let Some(strength) = self.point_strengths.get(&distance) {
+            light.constant = strength.constant;
+            light.linear = strength.linear;
+            light.quadratic = strength.quadratic;
+        }
+        self.spot_lights.insert(self.next_light_id, light);
+        self.next_light_id += 1;
+    }
+
+    /// Light ids index the sparse sets directly, so they run out at max_lights.
+    pub fn has_room(&self) -> bool {
+        self.next_light_id < self.point_lights.capacity()
+    }
+
+    pub fn clear_local_lights(&mut self) {
+        self.point_lights.clear();
+        self.spot_lights.clear();
+        self.velocities.clear();
+        self.next_light_id = 0;
+    }
+
+    /// Point lights that get a shadow cube map, in cube map array order.
+    pub fn shadow_casters(&self) -> Vec<&PointLight> {
+        self.point_lights
+            .iter()
+            .map(|p| p.value())
+            .filter(|p| p.casts_shadow)
+            .take(MAX_SHADOW_POINT_LIGHTS)
+            .collect()
+    }
+
+    /// Flattens point and spot lights into the layout the light SSBO expects. Shadow indices line
+    /// up with shadow_casters.
+    pub fn gpu_lights(&self) -> Vec<GpuLight> {
+        let mut lights = Vec::with_capacity(self.point_lights.len() + self.spot_lights.len());
+        let mut shadow_index = 0;
+
+        for point in self.point_lights.iter().map(|p| p.value()) {
+            let shadow = if point.casts_shadow && shadow_index < MAX_SHADOW_POINT_LIGHTS {
+                shadow_index += 1;
+                (shadow_index - 1) as f32
+            } else {
+                -1.0
+            };
+
+            lights.push(GpuLight {
+                position: point.position.extend(LIGHT_POINT),
+                direction: Vec4::ZERO,
+                color: point.diffuse.extend(0.0),
+                attenuation: vec4(point.constant, point.linear, point.quadratic, attenuation_range(point.constant, point.linear, point.quadratic)),
+                params: vec4(shadow, 0.0, 0.0, 0.0),
+            });
+        }
+
+        for spot in self.spot_lights.iter().map(|s| s.value()) {
+            lights.push(GpuLight {
+                position: spot.position.extend(LIGHT_SPOT),
+                direction: spot.direction.normalize_or_zero().extend(spot.outer_angle.to_radians().cos()),
+                color: spot.diffuse.extend(spot.inner_angle.to_radians().cos()),
+                attenuation: vec4(spot.constant, spot.linear, spot.quadratic, attenuation_range(spot.constant, spot.linear, spot.quadratic)),
+                params: vec4(-1.0, 0.0, 0.0, 0.0),
+            });
+        }
+
+        lights
+    }
+
     pub fn update(&mut self, delta: &f32) {
         for i in self.point_lights.iter_mut() {
             if let Some(velocity) = self.velocities.get(i.key()) {
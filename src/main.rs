@@ -23,6 +23,19 @@ mod terrain;
 mod collision_system;
 mod state_machines;
 mod particles;
+mod assets;
+mod error;
+mod shadows;
+mod clusters;
+mod instancing;
+mod culling;
+mod post_process;
+mod ssao;
+mod sky;
+mod time_of_day;
+mod offscreen;
+mod golden;
+mod capture;
 
 use std::{fs::{self, OpenOptions}, path::Path};
 
@@ -30,6 +43,12 @@ use game_state::GameState;
 use std::io::Write;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--golden") {
+        let passed = golden::run(args.iter().any(|a| a == "--bless"));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     let mut state = GameState::new();
     while !state.window.should_close() {
         state.process_events();
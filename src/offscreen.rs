@@ -0,0 +1,71 @@
+use image::{imageops, RgbaImage};
+
+use crate::gl_call;
+
+/// Framebuffer with its own color and depth storage, so a frame can be rendered without it ever
+/// reaching the window. Set Renderer.output_fbo to fbo to draw into it.
+pub struct OffscreenTarget {
+    pub fbo: u32,
+    pub width: u32,
+    pub height: u32,
+    color_rbo: u32,
+    depth_rbo: u32,
+}
+
+impl OffscreenTarget {
+    pub fn new(width: u32, height: u32) -> Self {
+        let (mut fbo, mut color_rbo, mut depth_rbo) = (0, 0, 0);
+        unsafe {
+            gl_call!(gl::GenFramebuffers(1, &mut fbo));
+            gl_call!(gl::GenRenderbuffers(1, &mut color_rbo));
+            gl_call!(gl::GenRenderbuffers(1, &mut depth_rbo));
+
+            gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, color_rbo));
+            gl_call!(gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as i32, height as i32));
+            gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, depth_rbo));
+            gl_call!(gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32));
+            gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, 0));
+
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, fbo));
+            gl_call!(gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, color_rbo));
+            gl_call!(gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, depth_rbo));
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                println!("Offscreen framebuffer incomplete at {}x{}", width, height);
+            }
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+        }
+
+        Self { fbo, width, height, color_rbo, depth_rbo }
+    }
+
+    pub fn read_pixels(&self) -> RgbaImage {
+        read_framebuffer(self.fbo, self.width, self.height)
+    }
+
+    pub fn release(&self) {
+        unsafe {
+            gl_call!(gl::DeleteFramebuffers(1, &self.fbo));
+            gl_call!(gl::DeleteRenderbuffers(1, &self.color_rbo));
+            gl_call!(gl::DeleteRenderbuffers(1, &self.depth_rbo));
+        }
+    }
+}
+
+/// Reads back the color of any framebuffer, 0 being the window's back buffer. Rows come out of GL
+/// bottom up, so the image gets flipped to the usual top down order.
+pub fn read_framebuffer(fbo: u32, width: u32, height: u32) -> RgbaImage {
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    unsafe {
+        gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo));
+        if fbo == 0 {
+            gl_call!(gl::ReadBuffer(gl::BACK));
+        }
+        gl_call!(gl::PixelStorei(gl::PACK_ALIGNMENT, 1));
+        gl_call!(gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr().cast()));
+        gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0));
+    }
+
+    let mut image = RgbaImage::from_raw(width, height, pixels).expect("pixel buffer matches the image size");
+    imageops::flip_vertical_in_place(&mut image);
+    image
+}
@@ -1,8 +1,7 @@
 use glam::{vec3, Mat3, Mat4, Quat, Vec3};
-use image::GenericImageView;
 use rand::{rng, Rng};
 
-use crate::{camera::Camera, gl_call, lights::Lights, shaders::Shader};
+use crate::{assets::{Assets, TextureHandle}, camera::Camera, gl_call, lights::Lights, shaders::Shader};
 
 pub struct Emitter {
     pub positions: Vec<Vec3>,
@@ -21,6 +20,7 @@ pub struct Emitter {
     pub emit_accumulator: f32,
     pub origin: Vec3,
     pub texture: Option<u32>,
+    pub texture_handle: Option<TextureHandle>,
     pub instance_vbo: u32,
     pub alphas: Vec<f32>,
     pub alpha_vbo: u32,
@@ -51,6 +51,7 @@ impl Emitter {
             emit_accumulator: 0.0,
             origin: Vec3::splat(1.0),
             texture: None,
+            texture_handle: None,
             instance_vbo,
             alphas: vec![],
             alpha_vbo,
@@ -312,43 +313,13 @@ impl ParticleSystem {
         self.emitters.push(emitter);
     }
 
-    pub fn spawn_continuous_emitter(&mut self, pps: usize, origin: Vec3, emit_type: &str, texture_path: Option<&str>) {
+    pub fn spawn_continuous_emitter(&mut self, pps: usize, origin: Vec3, emit_type: &str, texture_path: Option<&str>, assets: &mut Assets) {
         let mut emitter = Emitter::new();
 
-        unsafe {
-            if let Some(texture_path) = texture_path {
-                let mut tex = 0;
-
-                gl_call!(gl::GenTextures(1, &mut tex));
-                gl_call!(gl::BindTexture(gl::TEXTURE_2D, tex));
-                gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32));
-                gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32));
-                gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
-                gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
-
-                let img = match image::open(texture_path) {
-                    Ok(img) => img,
-                    _ => panic!("error opening smoke texture"),
-                };
-
-                let (img_width, img_height) = img.dimensions();
-                let rgba = img.to_rgba8();
-                let raw = rgba.as_raw();
-
-                gl_call!(gl::TexImage2D(
-                    gl::TEXTURE_2D,
-                    0,
-                    gl::RGBA8 as i32,
-                    img_width as i32,
-                    img_height as i32,
-                    0,
-                    gl::RGBA,
-                    gl::UNSIGNED_BYTE,
-                    raw.as_ptr().cast(),
-                ));
-
-                emitter.texture = Some(tex);
-            }
+        if let Some(texture_path) = texture_path {
+            let handle = assets.load_texture(texture_path, gl::LINEAR);
+            emitter.texture = Some(assets.texture_id(handle));
+            emitter.texture_handle = Some(handle);
         }
 
         emitter.pps = pps;
@@ -358,7 +329,7 @@ impl ParticleSystem {
 
     }
 
-    pub fn update(&mut self, dt: f32) {
+    pub fn update(&mut self, dt: f32, assets: &mut Assets) {
         let mut gravity = vec3(0.0, 0.0, 0.0);
 
         for emitter in self.emitters.iter_mut() {
@@ -435,6 +406,11 @@ impl ParticleSystem {
             }
         }
 
+        for emitter in self.emitters.iter().filter(|e| !e.alive) {
+            if let Some(handle) = emitter.texture_handle {
+                assets.release_texture(handle);
+            }
+        }
         self.emitters.retain(|e| e.alive);
     }
 
@@ -0,0 +1,295 @@
+use std::ptr::null;
+
+use glam::vec2;
+
+use crate::{assets::Shaders, enums_types::ShaderType, gl_call};
+
+// Each bloom mip is half the size of the one before, starting at half the screen
+const BLOOM_MIPS: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemapper {
+    // Clamps, the scene looks like it did before the HDR target
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 3] = [Tonemapper::None, Tonemapper::Reinhard, Tonemapper::Aces];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tonemapper::None => "None",
+            Tonemapper::Reinhard => "Reinhard",
+            Tonemapper::Aces => "ACES",
+        }
+    }
+}
+
+/// Effects that run on the HDR scene before tonemapping, in the order they are listed in
+/// PostProcess.effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostEffect {
+    Bloom,
+}
+
+pub struct BloomSettings {
+    pub enabled: bool,
+    // Linear brightness where bloom starts, knee softens the cut off below it
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    // In uv units of each upsampled mip
+    pub filter_radius: f32,
+}
+
+struct BloomMip {
+    texture: u32,
+    width: i32,
+    height: i32,
+}
+
+/// Owns the floating point scene target everything gets rendered into, then runs the effect stack
+/// and tonemaps onto the default framebuffer. The scene holds the same gamma encoded colors the
+/// default framebuffer used to get, just without clamping, so emissive surfaces and the flash white
+/// effect can go past 1.0. Effects and tonemapping work on the linearized values.
+pub struct PostProcess {
+    pub enabled: bool,
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    pub bloom: BloomSettings,
+    pub effects: Vec<PostEffect>,
+
+    width: i32,
+    height: i32,
+    scene_fbo: u32,
+    scene_color: u32,
+    // Depth texture instead of a renderbuffer so effects can read it
+    pub scene_depth: u32,
+
+    bloom_fbo: u32,
+    bloom_mips: Vec<BloomMip>,
+}
+
+impl PostProcess {
+    pub fn new() -> Self {
+        let mut scene_fbo = 0;
+        let mut bloom_fbo = 0;
+        unsafe {
+            gl_call!(gl::GenFramebuffers(1, &mut scene_fbo));
+            gl_call!(gl::GenFramebuffers(1, &mut bloom_fbo));
+        }
+
+        Self {
+            enabled: true,
+            exposure: 1.0,
+            tonemapper: Tonemapper::Aces,
+            bloom: BloomSettings {
+                enabled: true,
+                threshold: 1.0,
+                knee: 0.5,
+                intensity: 0.8,
+                filter_radius: 0.005,
+            },
+            effects: vec![PostEffect::Bloom],
+
+            width: 0,
+            height: 0,
+            scene_fbo,
+            scene_color: 0,
+            scene_depth: 0,
+
+            bloom_fbo,
+            bloom_mips: Vec::new(),
+        }
+    }
+
+    /// Binds the scene target, resizing it first if the framebuffer changed size. With post
+    /// processing off the scene goes straight to the default framebuffer.
+    pub fn begin_scene(&mut self, fb_width: u32, fb_height: u32) {
+        if !self.enabled {
+            return;
+        }
+
+        // Clamped the same way resize stores it, or a minimised 0x0 window reallocates every frame
+        let (width, height) = ((fb_width as i32).max(1), (fb_height as i32).max(1));
+        if width != self.width || height != self.height {
+            self.resize(width, height);
+        }
+
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.scene_fbo));
+            gl_call!(gl::Viewport(0, 0, self.width, self.height));
+        }
+    }
+
+    /// Runs the effect stack and tonemaps the scene onto output_fbo, 0 being the window.
+    pub fn apply(&mut self, shaders: &Shaders, quad_vao: u32, output_fbo: u32) {
+        if !self.enabled {
+            return;
+        }
+
+        unsafe {
+            gl_call!(gl::Disable(gl::DEPTH_TEST));
+            gl_call!(gl::Disable(gl::BLEND));
+            gl_call!(gl::Disable(gl::CULL_FACE));
+        }
+
+        let mut bloom_texture = None;
+        for effect in self.effects.clone() {
+            match effect {
+                PostEffect::Bloom if self.bloom.enabled => {
+                    bloom_texture = Some(self.render_bloom(shaders, quad_vao));
+                }
+                PostEffect::Bloom => {}
+            }
+        }
+
+        let shader = shaders.get(&ShaderType::Tonemap).unwrap();
+        shader.activate();
+        shader.set_float("exposure", self.exposure);
+        shader.set_int("tonemapper", self.tonemapper as u32);
+        shader.set_bool("use_bloom", bloom_texture.is_some());
+        shader.set_float("bloom_intensity", self.bloom.intensity);
+        shader.set_int("scene", 0);
+        shader.set_int("bloom", 1);
+
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, output_fbo));
+            gl_call!(gl::Viewport(0, 0, self.width, self.height));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.scene_color));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE1));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, bloom_texture.unwrap_or(0)));
+        }
+        draw_quad(quad_vao);
+
+        unsafe {
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+            gl_call!(gl::Enable(gl::DEPTH_TEST));
+        }
+    }
+
+    /// Downsamples the scene through the mip chain, thresholding on the first step, then adds each
+    /// mip back onto the next larger one. Returns the half resolution result.
+    fn render_bloom(&self, shaders: &Shaders, quad_vao: u32) -> u32 {
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.bloom_fbo));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+        }
+
+        let downsample = shaders.get(&ShaderType::BloomDownsample).unwrap();
+        downsample.activate();
+        downsample.set_int("src_texture", 0);
+        downsample.set_float("threshold", self.bloom.threshold);
+        downsample.set_float("knee", self.bloom.knee);
+
+        let mut src_texture = self.scene_color;
+        let mut src_size = vec2(self.width as f32, self.height as f32);
+        for (i, mip) in self.bloom_mips.iter().enumerate() {
+            downsample.set_bool("prefilter", i == 0);
+            downsample.set_vec2("src_resolution", src_size);
+            self.target_mip(mip);
+            unsafe {
+                gl_call!(gl::BindTexture(gl::TEXTURE_2D, src_texture));
+            }
+            draw_quad(quad_vao);
+
+            src_texture = mip.texture;
+            src_size = vec2(mip.width as f32, mip.height as f32);
+        }
+
+        let upsample = shaders.get(&ShaderType::BloomUpsample).unwrap();
+        upsample.activate();
+        upsample.set_int("src_texture", 0);
+        upsample.set_float("filter_radius", self.bloom.filter_radius);
+
+        unsafe {
+            gl_call!(gl::Enable(gl::BLEND));
+            gl_call!(gl::BlendFunc(gl::ONE, gl::ONE));
+        }
+        for pair in self.bloom_mips.windows(2).rev() {
+            let (target, source) = (&pair[0], &pair[1]);
+            self.target_mip(target);
+            unsafe {
+                gl_call!(gl::BindTexture(gl::TEXTURE_2D, source.texture));
+            }
+            draw_quad(quad_vao);
+        }
+        unsafe {
+            gl_call!(gl::Disable(gl::BLEND));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        }
+
+        self.bloom_mips[0].texture
+    }
+
+    fn target_mip(&self, mip: &BloomMip) {
+        unsafe {
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, mip.texture, 0));
+            gl_call!(gl::Viewport(0, 0, mip.width, mip.height));
+        }
+    }
+
+    fn resize(&mut self, width: i32, height: i32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+
+        unsafe {
+            if self.scene_color != 0 {
+                gl_call!(gl::DeleteTextures(1, &self.scene_color));
+                gl_call!(gl::DeleteTextures(1, &self.scene_depth));
+            }
+            for mip in self.bloom_mips.drain(..) {
+                gl_call!(gl::DeleteTextures(1, &mip.texture));
+            }
+
+            self.scene_color = create_target(gl::RGBA16F, gl::RGBA, gl::FLOAT, self.width, self.height);
+            self.scene_depth = create_target(gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, gl::FLOAT, self.width, self.height);
+
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.scene_fbo));
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.scene_color, 0));
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, self.scene_depth, 0));
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                println!("HDR scene framebuffer incomplete at {}x{}", self.width, self.height);
+            }
+
+            let (mut width, mut height) = (self.width, self.height);
+            for _ in 0..BLOOM_MIPS {
+                width = (width / 2).max(1);
+                height = (height / 2).max(1);
+                let texture = create_target(gl::R11F_G11F_B10F, gl::RGB, gl::FLOAT, width, height);
+                self.bloom_mips.push(BloomMip { texture, width, height });
+            }
+
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+        }
+    }
+}
+
+pub fn create_target(internal_format: gl::types::GLenum, format: gl::types::GLenum, data_type: gl::types::GLenum, width: i32, height: i32) -> u32 {
+    let mut texture = 0;
+    unsafe {
+        gl_call!(gl::GenTextures(1, &mut texture));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture));
+        gl_call!(gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, width, height, 0, format, data_type, null()));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
+        gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    }
+    texture
+}
+
+/// Fullscreen quad with the position and uv layout of Renderer::render_quad.
+pub fn draw_quad(quad_vao: u32) {
+    unsafe {
+        gl_call!(gl::BindVertexArray(quad_vao));
+        gl_call!(gl::DrawArrays(gl::TRIANGLES, 0, 6));
+        gl_call!(gl::BindVertexArray(0));
+    }
+}
@@ -1,44 +1,82 @@
 #![allow(dead_code, clippy::too_many_arguments)]
-use std::{collections::HashMap, ffi::c_void, mem, ptr::null_mut};
+use std::{collections::HashMap, ffi::c_void, mem, ptr::null_mut, time::{Duration, Instant}};
 
 use gl::CULL_FACE;
-use glam::{vec3, vec4, Mat4, Vec3, Vec4};
+use glam::{vec3, vec4, Mat4, Quat, Vec3, Vec4};
 use image::GenericImageView;
 
-use crate::{camera::Camera, entity_manager::EntityManager, enums_types::{EntityType, Faction, FboType, ShaderType, VaoType}, gl_call, grid::Grid, lights::Lights, shaders::Shader, some_data::{FACES_CUBEMAP, POINT_LIGHT_POSITIONS, SHADOW_HEIGHT, SHADOW_WIDTH, SKYBOX_INDICES, SKYBOX_VERTICES, UNIT_CUBE_VERTICES}, sound::sound_manager::SoundManager};
+use crate::{assets::Shaders, camera::Camera, clusters::LightClusters, config::entity_config::{MaterialParams, RenderFlags}, culling::{self, Aabb, CullCounter, CullStats, Frustum}, error::LoadError, debug::skeleton::SkeletonDebug, entity_manager::EntityManager, enums_types::{Faction, FboType, ShaderType, VaoType}, gl_call, grid::Grid, instancing::InstanceBuffer, lights::{attenuation_range, GpuLight, Lights}, post_process::{draw_quad, PostProcess}, shaders::Shader, shadows::ShadowCascades, sky::Sky, ssao::Ssao, some_data::{FACES_CUBEMAP, LIGHT_BUFFER_BINDING, MAX_SHADOW_POINT_LIGHTS, POINT_SHADOW_SIZE, SHADOW_CASCADES, SHADOW_HEIGHT, SHADOW_WIDTH, SKYBOX_INDICES, SKYBOX_VERTICES, UNIT_CUBE_VERTICES}, sound::sound_manager::SoundManager};
 
 pub struct Renderer {
-    pub shaders: HashMap<ShaderType, Shader>,
+    pub shaders: Shaders,
     pub vaos: HashMap<VaoType, u32>,
     pub fbos: HashMap<FboType, u32>,
+    // Depth texture array, one layer per shadow cascade
     pub depth_map: u32,
+    pub cascades: ShadowCascades,
     pub cubemap_texture: u32,
 
+    // Point and spot lights live in an SSBO, shadow casting point lights get a layer of the cube array
+    pub light_ssbo: u32,
+    pub light_count: usize,
+    pub point_shadow_map: u32,
+    pub show_light_gizmos: bool,
+    // Point and spot lights binned into view space froxels so model.glsl only walks nearby ones
+    pub clusters: LightClusters,
+
+    // Static models drawn instanced, one buffer for the model pass and one for every shadow pass
+    pub static_instances: InstanceBuffer,
+    pub shadow_instances: InstanceBuffer,
+
+    // Skip entities whose world bounds are outside the camera or light frustum
+    pub frustum_culling: bool,
+    pub cull_stats: CullStats,
+
+    // HDR scene target, bloom and tonemapping
+    pub post: PostProcess,
+    pub ssao: Ssao,
+    pub sky: Sky,
+
+    // Where the finished frame goes, 0 for the window or an OffscreenTarget's fbo
+    pub output_fbo: u32,
+
     pub shadow_debug: bool,
+    pub shadow_debug_cascade: u32,
+    pub cascade_debug: bool,
+    pub skeleton_debug: SkeletonDebug,
+    // Cook-Torrance in model.glsl, off falls back to the old Blinn-Phong for comparison
+    pub use_pbr: bool,
+
+    // Shader hot reloading, errors are keyed by file path
+    pub shader_hot_reload: bool,
+    pub shader_errors: HashMap<String, String>,
+    last_shader_poll: Instant,
 }
 
+const SHADER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 impl Renderer {
-    pub fn new() -> Self {
+    /// Anything that fails to load gets pushed onto errors. Broken shaders are swapped for the
+    /// magenta placeholder and also show up in shader_errors until hot reloading fixes them.
+    pub fn new(errors: &mut Vec<LoadError>) -> Self {
         // =============================================================
         // Setup Shaders
         // =============================================================
-        let mut shaders = HashMap::new();
+        let mut shaders = Shaders::new();
         let mut vaos = HashMap::new();
         let mut fbos = HashMap::new();
-
-        let skybox_shader = Shader::new("resources/shaders/skybox.glsl");
-        let debug_light_shader = Shader::new("resources/shaders/point_light.glsl");
-        let depth_shader = Shader::new("resources/shaders/depth_shader.glsl");
-        let text_shader = Shader::new("resources/shaders/text.glsl");
-        text_shader.activate();
-        let loc = unsafe { gl::GetUniformLocation(text_shader.id, b"textTexture\0".as_ptr() as *const _) };
-        unsafe {
-            gl::Uniform1i(loc, 1); 
-        }
-        let model_shader = Shader::new("resources/shaders/model.glsl");
-        let gizmo_shader = Shader::new("resources/shaders/gizmo.glsl");
-        let particle_shader = Shader::new("resources/shaders/particles.glsl");
-        let game_ui_shader = Shader::new("resources/shaders/game_ui.glsl");
+        let mut shader_errors = HashMap::new();
+
+        shaders.load(ShaderType::Skybox, "resources/shaders/skybox.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::DebugLight, "resources/shaders/point_light.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Depth, "resources/shaders/depth_shader.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::PointShadow, "resources/shaders/point_shadow.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Text, "resources/shaders/text.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Model, "resources/shaders/model.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Gizmo, "resources/shaders/gizmo.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Particles, "resources/shaders/particles.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::GameUi, "resources/shaders/game_ui.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Skeleton, "resources/shaders/skeleton.glsl", errors, &mut shader_errors);
 
         let mut vao = 0;
         let mut vbo = 0;
@@ -49,7 +87,7 @@ impl Renderer {
         // Skybox memes
         // =============================================================
         unsafe {
-            skybox_shader.activate();
+            shaders.get(&ShaderType::Skybox).unwrap().activate();
             gl_call!(gl::GenVertexArrays(1, &mut vao));
             gl_call!(gl::GenBuffers(1, &mut vbo));
             gl_call!(gl::GenBuffers(1, &mut ebo));
@@ -101,9 +139,16 @@ impl Renderer {
             gl_call!(gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32));
 
             for i in 0..FACES_CUBEMAP.len() {
+                // A missing face leaves the cubemap incomplete, which samples as black
                 let img = match image::open(FACES_CUBEMAP[i]) {
                     Ok(img) => img,
-                    _=> panic!("Error opening {}", FACES_CUBEMAP[i]),
+                    Err(e) => {
+                        errors.push(LoadError::Image {
+                            path: FACES_CUBEMAP[i].to_string(),
+                            message: e.to_string(),
+                        });
+                        continue;
+                    }
                 };
                 let (img_width, img_height) = img.dimensions();
                 let rgba = img.to_rgb8();
@@ -127,7 +172,7 @@ impl Renderer {
         // Debug point light setup
         // =============================================================
         unsafe {
-            debug_light_shader.activate();
+            shaders.get(&ShaderType::DebugLight).unwrap().activate();
 
             gl_call!(gl::GenVertexArrays(1, &mut vao));
             gl_call!(gl::GenBuffers(1, &mut vbo));
@@ -176,6 +221,8 @@ impl Renderer {
         // directional light.
         // We can do this using a "framebuffer". We have been using a 
         // framebuffer all along, just the "default" one given to us.
+        // The camera frustum is split into SHADOW_CASCADES slices and each
+        // one gets its own layer of a depth texture array.
         let mut fbo = 0;
         let mut depth_map = 0;
         unsafe {
@@ -184,49 +231,160 @@ impl Renderer {
             fbos.insert(FboType::DepthMap, fbo);
 
             gl_call!(gl::GenTextures(1, &mut depth_map));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, depth_map));
-            gl_call!(gl::TexImage2D(gl::TEXTURE_2D, 0, gl::DEPTH_COMPONENT as i32, SHADOW_WIDTH, SHADOW_HEIGHT, 0, gl::DEPTH_COMPONENT, gl::FLOAT, null_mut()));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, depth_map));
+            gl_call!(gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                gl::DEPTH_COMPONENT32F as i32,
+                SHADOW_WIDTH,
+                SHADOW_HEIGHT,
+                SHADOW_CASCADES as i32,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                null_mut()
+            ));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32));
             gl_call!(gl::TexParameterfv(
-                gl::TEXTURE_2D, 
+                gl::TEXTURE_2D_ARRAY, 
                 gl::TEXTURE_BORDER_COLOR, 
                 [1.0, 1.0, 1.0, 1.0].as_ptr().cast() 
             ));
 
             gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, fbo));
-            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, depth_map, 0));
+            gl_call!(gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, depth_map, 0, 0));
             gl_call!(gl::DrawBuffer(gl::NONE));
             gl_call!(gl::ReadBuffer(gl::NONE));
             gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
         }
 
-        let mut debug_depth_quad = Shader::new("resources/shaders/debug_depth_quad.glsl");
+        // =============================================================
+        // Point and spot lights
+        // =============================================================
+        // Every local light goes into one SSBO that model.glsl loops over.
+        // Shadow casting point lights render linear distance into the 6
+        // faces of their layer in a cube map array.
+        let mut light_ssbo = 0;
+        let mut point_shadow_map = 0;
+        unsafe {
+            gl_call!(gl::GenBuffers(1, &mut light_ssbo));
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, light_ssbo));
+            gl_call!(gl::BufferData(gl::SHADER_STORAGE_BUFFER, 0, std::ptr::null(), gl::DYNAMIC_DRAW));
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0));
 
-        debug_depth_quad.activate();
-        debug_depth_quad.store_uniform_location("depth_map");
-        debug_depth_quad.set_int("depth_map", 0);
+            gl_call!(gl::GenFramebuffers(1, &mut fbo));
+            fbos.insert(FboType::PointShadow, fbo);
+
+            gl_call!(gl::GenTextures(1, &mut point_shadow_map));
+            gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, point_shadow_map));
+            gl_call!(gl::TexImage3D(
+                gl::TEXTURE_CUBE_MAP_ARRAY,
+                0,
+                gl::DEPTH_COMPONENT32F as i32,
+                POINT_SHADOW_SIZE,
+                POINT_SHADOW_SIZE,
+                (MAX_SHADOW_POINT_LIGHTS * 6) as i32,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                null_mut()
+            ));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_CUBE_MAP_ARRAY, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_CUBE_MAP_ARRAY, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_CUBE_MAP_ARRAY, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_CUBE_MAP_ARRAY, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_CUBE_MAP_ARRAY, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32));
+
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, fbo));
+            gl_call!(gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, point_shadow_map, 0, 0));
+            gl_call!(gl::DrawBuffer(gl::NONE));
+            gl_call!(gl::ReadBuffer(gl::NONE));
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+        }
+
+        shaders.load(ShaderType::DebugShadowMap, "resources/shaders/debug_depth_quad.glsl", errors, &mut shader_errors);
+
+        // =============================================================
+        // Post processing
+        // =============================================================
+        shaders.load(ShaderType::BloomDownsample, "resources/shaders/bloom_downsample.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::BloomUpsample, "resources/shaders/bloom_upsample.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Tonemap, "resources/shaders/tonemap.glsl", errors, &mut shader_errors);
+        vaos.insert(VaoType::Quad, create_quad_vao());
+
+        shaders.load(ShaderType::SsaoPrepass, "resources/shaders/ssao_prepass.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::Ssao, "resources/shaders/ssao.glsl", errors, &mut shader_errors);
+        shaders.load(ShaderType::SsaoBlur, "resources/shaders/ssao_blur.glsl", errors, &mut shader_errors);
 
-        shaders.insert(ShaderType::Model, model_shader);
-        shaders.insert(ShaderType::Skybox, skybox_shader);
-        shaders.insert(ShaderType::DebugLight, debug_light_shader);
-        shaders.insert(ShaderType::Depth, depth_shader);
-        shaders.insert(ShaderType::DebugShadowMap, debug_depth_quad);
-        shaders.insert(ShaderType::Text, text_shader);
-        shaders.insert(ShaderType::Gizmo, gizmo_shader);
-        shaders.insert(ShaderType::Particles, particle_shader);
-        shaders.insert(ShaderType::GameUi, game_ui_shader);
 
         Self {
             shaders,
             vaos,
             fbos,
             depth_map,
+            cascades: ShadowCascades::new(),
 
             cubemap_texture,
+
+            light_ssbo,
+            light_count: 0,
+            point_shadow_map,
+            show_light_gizmos: false,
+            clusters: LightClusters::new(),
+            static_instances: InstanceBuffer::new(),
+            shadow_instances: InstanceBuffer::new(),
+            frustum_culling: true,
+            cull_stats: CullStats::default(),
+            post: PostProcess::new(),
+            ssao: Ssao::new(),
+            sky: Sky::new(),
+            output_fbo: 0,
             shadow_debug: false,
+            shadow_debug_cascade: 0,
+            cascade_debug: false,
+            skeleton_debug: SkeletonDebug::new(),
+            use_pbr: true,
+
+            shader_hot_reload: true,
+            shader_errors,
+            last_shader_poll: Instant::now(),
+        }
+    }
+
+    /// Recompiles every shader whose .glsl changed on disk since it was loaded. A shader that fails
+    /// to compile keeps running its old program and the error shows up in the imgui Shaders panel.
+    pub fn hot_reload_shaders(&mut self) {
+        if !self.shader_hot_reload || self.last_shader_poll.elapsed() < SHADER_POLL_INTERVAL {
+            return;
+        }
+        self.last_shader_poll = Instant::now();
+
+        for shader in self.shaders.values_mut() {
+            if shader.is_stale() {
+                Self::reload_shader(shader, &mut self.shader_errors);
+            }
+        }
+    }
+
+    pub fn reload_all_shaders(&mut self) {
+        for shader in self.shaders.values_mut() {
+            Self::reload_shader(shader, &mut self.shader_errors);
+        }
+    }
+
+    fn reload_shader(shader: &mut Shader, errors: &mut HashMap<String, String>) {
+        match shader.reload() {
+            Ok(()) => {
+                println!("Reloaded shader {}", shader.file_path);
+                errors.remove(&shader.file_path);
+            }
+            Err(e) => {
+                println!("Failed to reload shader {}: {}", shader.file_path, e);
+                errors.insert(shader.file_path.clone(), e);
+            }
         }
     }
 
@@ -241,17 +399,28 @@ impl Renderer {
         fb_height: u32,
         elapsed: f32,
     ) {
+        self.cull_stats = CullStats::default();
+        self.point_shadow_pass(em, light_manager);
         self.shadow_pass(em, camera, light_manager, fb_width, fb_height);
+        // After shadow_pass so the clusters are binned against this frame's view
+        self.upload_lights(light_manager, camera, fb_width, fb_height);
 
         if self.shadow_debug {
             return;
         }
 
+        // The SSAO prepass and static_model_pass draw the same visible statics
+        self.cull_static_models(camera, em);
+        self.ssao_pass(camera, em, grid, fb_width, fb_height);
+
+        // Everything up to post_process_pass lands in the HDR target
+        self.post.begin_scene(fb_width, fb_height);
+
         // =============================================================
         // Render OOP-esque things
         // =============================================================
         // shadow pass must come first or you're gonna have a bad time
-        self.skybox_pass(camera, fb_width, fb_height);
+        self.skybox_pass(camera, light_manager, fb_width, fb_height);
         self.grid_pass(grid, camera, light_manager);
         
         // =============================================================
@@ -261,21 +430,16 @@ impl Renderer {
         let gizmo_ids = em.get_ids_for_faction(Faction::Gizmo);
         // self.gizmo_pass(camera, em, gizmo_ids);
 
-        // Non-animated models
-        let foliage_ids = em.get_ids_for_type(EntityType::TreeFoliage);
-        let trunk_ids = em.get_ids_for_type(EntityType::TreeTrunk);
-        let stump_ids = em.get_ids_for_type(EntityType::Stump);
+        // Anything with a mesh gets drawn, how is up to its render flags
+        self.static_model_pass(camera, em, light_manager);
+        self.ani_model_pass(camera, em, light_manager, sound_manager, elapsed);
 
-        self.static_model_pass(camera, em, light_manager, foliage_ids);
-        self.static_model_pass(camera, em, light_manager, trunk_ids);
-        self.static_model_pass(camera, em, light_manager, stump_ids);
-
-        // Animated models
-        let y_robot_ids = em.get_ids_for_type(EntityType::YRobot);
-        let moose_ids = em.get_ids_for_type(EntityType::MooseMan);
-
-        self.ani_model_pass(camera, em, light_manager, sound_manager, y_robot_ids, elapsed);
-        self.ani_model_pass(camera, em, light_manager, sound_manager, moose_ids, elapsed);
+        // Debug overlays go last so they draw on top of everything
+        if self.show_light_gizmos {
+            self.debug_light_pass(camera, light_manager);
+        }
+        let skeleton_shader = self.shaders.get(&ShaderType::Skeleton).unwrap();
+        self.skeleton_debug.draw(em, camera, skeleton_shader);
     }
 
 
@@ -287,7 +451,7 @@ impl Renderer {
         let shader = self.shaders.get_mut(&ShaderType::Gizmo).unwrap();
         shader.activate();
         for id in ids {
-            let model = em.models.get(id).unwrap();
+            let model = em.model(id).unwrap();
             let trans = em.transforms.get(id).unwrap();
             let m_mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
 
@@ -302,15 +466,17 @@ impl Renderer {
         }
     }
 
-    fn ani_model_pass(&mut self, camera: &mut Camera, em: &EntityManager, light_manager: &Lights, sound_manager: &mut SoundManager, ids: Vec<usize>, elapsed: f32) {
+    fn ani_model_pass(&mut self, camera: &mut Camera, em: &EntityManager, light_manager: &Lights, sound_manager: &mut SoundManager, elapsed: f32) {
         let shader = self.shaders.get_mut(&ShaderType::Model).unwrap();
         shader.activate();
 
         shader.set_bool("is_animated", true);
-        shader.set_bool("alpha_test_pass", false);
+        shader.set_bool("is_instanced", false);
+        shader.set_bool("use_pbr", self.use_pbr);
         shader.set_float("elapsed", elapsed);
         shader.set_bool("do_reg_fresnel", true);
-        for id in ids {
+        let frustum = Frustum::from_matrix(camera.projection * camera.view);
+        for id in em.skinned_renderables() {
             if em.v_effects.get(id).is_some() {
                 shader.set_bool("flash_white", true);
             }
@@ -318,7 +484,7 @@ impl Renderer {
             let is_selected = em.selected.contains(&id);
             shader.set_bool("selection_fresnel", is_selected);
 
-            let model = em.ani_models.get(id).unwrap();
+            let model = em.ani_model(id).unwrap();
             let trans = em.transforms.get(id).unwrap();
 
             let animator = em.animators.get(id).unwrap();
@@ -344,104 +510,136 @@ impl Renderer {
             }
 
 
+            // Sounds above still have to tick for entities off screen
+            if !self.cull_stats.camera.test(em.world_bounds.get(id), |b| !self.frustum_culling || frustum.intersects_aabb(b)) {
+                shader.set_bool("selection_fresnel", false);
+                shader.set_bool("flash_white", false);
+                continue;
+            }
+
+            let flags = em.render_flags.get(id).copied().unwrap_or_default();
+            set_face_culling(flags);
+
             let m_mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
 
             shader.set_mat4("model", m_mat);
             shader.set_mat4("projection", camera.projection);
             shader.set_mat4("view", camera.view);
-            shader.set_mat4("light_space_mat", camera.light_space);
             shader.set_dir_light("dir_light", &light_manager.dir_light);
             shader.set_float("bias_scalar", light_manager.bias_scalar);
             shader.set_mat4_array("bone_transforms", &animation.current_pose);
             shader.set_vec3("view_position", camera.position);
-            model.draw(shader);
-            unsafe {
-                gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-                gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_map));
-                shader.set_int("shadow_map", 0);
+            shader.set_material_params(em.material_params.get(id).unwrap_or(&MaterialParams::default()));
+            bind_shadow_cascades(shader, &self.cascades, self.depth_map, self.cascade_debug);
+            bind_local_lights(shader, self.light_count, self.point_shadow_map, &self.clusters);
+            self.ssao.bind(shader);
+            self.sky.bind_fog(shader, light_manager.dir_light.direction);
+
+            // Same as draw_instance_batches, the cut out writing depth then the soft edges over it
+            shader.set_bool("alpha_test_pass", flags.alpha_test);
+            model.draw_with_morphs(shader, &animation.current_morph_weights);
+            if flags.alpha_test {
+                unsafe {
+                    gl_call!(gl::Enable(gl::BLEND));
+                    gl_call!(gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
+                    gl_call!(gl::DepthMask(gl::FALSE));
+                }
+                shader.set_bool("alpha_test_pass", false);
+                model.draw_with_morphs(shader, &animation.current_morph_weights);
+                unsafe {
+                    gl_call!(gl::Disable(gl::BLEND));
+                    gl_call!(gl::DepthMask(gl::TRUE));
+                }
             }
-
-            model.draw(shader);
             shader.set_bool("selection_fresnel", false);
             shader.set_bool("flash_white", false);
         }
+        set_face_culling(RenderFlags::default());
+        shader.set_bool("alpha_test_pass", false);
         shader.set_bool("do_reg_fresnel", false);
     }
 
 
-    fn static_model_pass(&mut self, camera: &mut Camera, em: &EntityManager, light_manager: &Lights, ids: Vec<usize>) {
+    fn cull_static_models(&mut self, camera: &Camera, em: &EntityManager) {
+        let frustum = Frustum::from_matrix(camera.projection * camera.view);
+        let culling = self.frustum_culling;
+        let ids = culling::cull(em, em.static_renderables(), |b| !culling || frustum.intersects_aabb(b), &mut self.cull_stats.camera);
+        self.static_instances.fill(em, ids.into_iter());
+    }
+
+    /// Normals and depth of everything visible, then the occlusion the model passes sample.
+    fn ssao_pass(&mut self, camera: &Camera, em: &EntityManager, grid: &Grid, fb_width: u32, fb_height: u32) {
+        if !self.ssao.enabled {
+            return;
+        }
+        self.ssao.begin_prepass(fb_width, fb_height);
+
+        // Separate counter, ani_model_pass does the bookkeeping for the camera stats
+        let frustum = Frustum::from_matrix(camera.projection * camera.view);
+        let culling = self.frustum_culling;
+        let ani_ids = culling::cull(em, em.skinned_renderables(), |b| !culling || frustum.intersects_aabb(b), &mut CullCounter::default());
+
+        let shader = self.shaders.get(&ShaderType::SsaoPrepass).unwrap();
+        shader.activate();
+        shader.set_mat4("projection", camera.projection);
+        shader.set_mat4("view", camera.view);
+        draw_depth_only(shader, em, &self.static_instances, &ani_ids);
+
+        // The ground catches most of the occlusion
+        shader.set_bool("is_animated", false);
+        shader.set_int("morph_target_count", 0);
+        shader.set_mat4("model", Mat4::IDENTITY);
+        grid.model.draw_depth(shader);
+
+        let quad_vao = *self.vaos.get(&VaoType::Quad).unwrap();
+        self.ssao.compute(&self.shaders, quad_vao, camera.projection, self.output_fbo);
+    }
+
+    /// Entities sharing a mesh, material and render flags get batched into one instanced draw,
+    /// selection and flashing come from per instance flags instead of uniforms.
+    fn static_model_pass(&mut self, camera: &mut Camera, em: &EntityManager, light_manager: &Lights) {
         unsafe {
             gl_call!(gl::Enable(gl::DEPTH_TEST));
             gl_call!(gl::DepthMask(gl::TRUE)); // Allow writing to depth buffer
             gl_call!(gl::Disable(gl::BLEND));
         }
-        // Alpha pass
         let shader = self.shaders.get_mut(&ShaderType::Model).unwrap();
         shader.activate();
         shader.set_bool("is_animated", false);
-        shader.set_bool("alpha_test_pass", true);
+        shader.set_bool("is_instanced", true);
+        shader.set_bool("use_pbr", self.use_pbr);
         shader.set_bool("do_fresnel", false);
-        for id in ids.iter() {
-            let is_selected = em.selected.contains(&id);
-            shader.set_bool("selection_fresnel", is_selected);
+        shader.set_bool("selection_fresnel", false);
 
-            let model = em.models.get(*id).unwrap();
-            let trans = em.transforms.get(*id).unwrap();
-            let m_mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
+        shader.set_mat4("projection", camera.projection);
+        shader.set_mat4("view", camera.view);
+        shader.set_dir_light("dir_light", &light_manager.dir_light);
+        shader.set_float("bias_scalar", light_manager.bias_scalar);
+        shader.set_vec3("view_position", camera.position);
+        bind_shadow_cascades(shader, &self.cascades, self.depth_map, self.cascade_debug);
+        bind_local_lights(shader, self.light_count, self.point_shadow_map, &self.clusters);
+        self.ssao.bind(shader);
+        self.sky.bind_fog(shader, light_manager.dir_light.direction);
+        self.static_instances.bind();
 
-            shader.set_mat4("model", m_mat);
-            shader.set_mat4("projection", camera.projection);
-            shader.set_mat4("view", camera.view);
-            shader.set_mat4("light_space_mat", camera.light_space);
-            shader.set_dir_light("dir_light", &light_manager.dir_light);
-            shader.set_float("bias_scalar", light_manager.bias_scalar);
-            shader.set_vec3("view_position", camera.position);
-            unsafe {
-                gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-                gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_map));
-                shader.set_int("shadow_map", 0);
-                model.draw(shader);
-                gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            }
-            shader.set_bool("selection_fresnel", false);
-        }
+        // Opaque and cut out geometry, writing depth
+        draw_instance_batches(shader, em, &self.static_instances, false);
 
+        // Soft edges of alpha tested batches blend over the cut out result
         unsafe {
             gl_call!(gl::Enable(gl::BLEND));
             gl_call!(gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
             gl_call!(gl::DepthMask(gl::FALSE));
         }
-        shader.set_bool("alpha_test_pass", false);
-        for id in ids {
-            let is_selected = em.selected.contains(&id);
-            shader.set_bool("selection_fresnel", is_selected);
-
-            let model = em.models.get(id).unwrap();
-            let trans = em.transforms.get(id).unwrap();
-            let m_mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
-
-            shader.set_mat4("model", m_mat);
-            shader.set_mat4("projection", camera.projection);
-            shader.set_mat4("view", camera.view);
-            shader.set_mat4("light_space_mat", camera.light_space);
-            shader.set_dir_light("dir_light", &light_manager.dir_light);
-            shader.set_float("bias_scalar", light_manager.bias_scalar);
-            shader.set_vec3("view_position", camera.position);
-            unsafe {
-                gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-                gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_map));
-                shader.set_int("shadow_map", 0);
-                model.draw(shader);
-                gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-            }
-            shader.set_bool("selection_fresnel", false);
-        }
+        draw_instance_batches(shader, em, &self.static_instances, true);
 
         unsafe {
             gl_call!(gl::Disable(gl::BLEND));
             gl_call!(gl::DepthMask(gl::TRUE));
         }
+        set_face_culling(RenderFlags::default());
 
+        shader.set_bool("is_instanced", false);
         shader.set_bool("do_reg_fresnel", false);
         shader.set_bool("selection_fresnel", false);
     }
@@ -460,23 +658,25 @@ impl Renderer {
         shader.set_mat4("model", Mat4::IDENTITY);
         shader.set_mat4("view", camera.view);
         shader.set_mat4("projection", camera.projection);
-        shader.set_mat4("light_space_mat", camera.light_space);
         shader.set_dir_light("dir_light", &light_manager.dir_light);
         shader.set_float("bias_scalar", light_manager.bias_scalar);
         shader.set_vec3("view_position", camera.position);
         shader.set_bool("is_animated", false);
+        shader.set_bool("is_instanced", false);
         shader.set_bool("alpha_test_pass", false);
+        shader.set_bool("use_pbr", self.use_pbr);
+        shader.set_material_params(&MaterialParams::default());
+        bind_shadow_cascades(shader, &self.cascades, self.depth_map, self.cascade_debug);
+        bind_local_lights(shader, self.light_count, self.point_shadow_map, &self.clusters);
+        self.ssao.bind(shader);
+        self.sky.bind_fog(shader, light_manager.dir_light.direction);
+        grid.draw(shader);
         unsafe {
-            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_map));
-            shader.set_int("shadow_map", 0);
-
-            grid.draw(shader);
             gl::Disable(gl::BLEND)
         }
     }
 
-    fn skybox_pass(&mut self, camera: &mut Camera, fb_width: u32, fb_height: u32) {
+    fn skybox_pass(&mut self, camera: &mut Camera, light_manager: &Lights, fb_width: u32, fb_height: u32) {
         camera.reset_matrices(fb_width as f32 / fb_height as f32);
 
         unsafe {
@@ -500,6 +700,8 @@ impl Renderer {
             skybox_shader_prog.activate();
             skybox_shader_prog.set_mat4("view", view_no_translation);
             skybox_shader_prog.set_mat4("projection", camera.projection);
+            self.sky.bind_sky(skybox_shader_prog, light_manager.dir_light.direction);
+            skybox_shader_prog.set_int("skybox", 1);
 
             gl_call!(gl::BindVertexArray(*self.vaos.get(&VaoType::Skybox).unwrap()));
             gl_call!(gl::ActiveTexture(gl::TEXTURE1));
@@ -513,45 +715,39 @@ impl Renderer {
     }
 
     fn shadow_pass(&mut self, em: &EntityManager, camera: &mut Camera, light_manager: &Lights, fb_width: u32, fb_height: u32) {
-        let shader = self.shaders.get_mut(&ShaderType::Depth).unwrap();
-        let near_plane = light_manager.near;
-        let far_plane = light_manager.far;
-        let half_bound = light_manager.bounds;
-
-        let bound_l = -half_bound;
-        let bound_r = half_bound;
-        let bound_b = -half_bound;
-        let bound_t = half_bound;
-
-        // Calculate dir_light pos
-        let light_dir = light_manager.dir_light.direction.normalize();
-        let light_distance = light_manager.dir_light.distance;
-        let camera_forward = camera.forward.normalize();
-        let shadow_push = half_bound * 1.2;
-        let shadow_center = camera.position + camera_forward * shadow_push;
-
-        let light_pos = shadow_center + light_dir * light_distance;
-
-        let light_projection = Mat4::orthographic_rh_gl(bound_l, bound_r, bound_b, bound_t, near_plane, far_plane);
-        let light_view = Mat4::look_at_rh(light_pos, shadow_center, vec3(0.0, 1.0, 0.0));
-
-        camera.light_space = light_projection * light_view;
-        shader.activate();
-        shader.set_mat4("light_space_mat", camera.light_space);
+        // The cascades are fitted to this frame's view, skybox_pass would only update it afterwards
+        let aspect = fb_width as f32 / fb_height as f32;
+        camera.reset_matrices(aspect);
+        self.cascades.fit(camera, light_manager, aspect);
 
         unsafe {
             gl_call!(gl::Viewport(0, 0, SHADOW_WIDTH, SHADOW_HEIGHT));
             gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, *self.fbos.get(&FboType::DepthMap).unwrap()));
-            gl_call!(gl::Clear(gl::DEPTH_BUFFER_BIT));
             // Render scene
             gl_call!(gl::Enable(CULL_FACE));
             //gl_call!(gl::CullFace(gl::BACK));
             gl::CullFace(gl::FRONT);
-            self.render_sample_depth(em);
+        }
+
+        for cascade in 0..SHADOW_CASCADES {
+            let shader = self.shaders.get(&ShaderType::Depth).unwrap();
+            shader.activate();
+            shader.set_mat4("light_space_mat", self.cascades.light_spaces[cascade]);
+
+            unsafe {
+                gl_call!(gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, self.depth_map, 0, cascade as i32));
+                gl_call!(gl::Clear(gl::DEPTH_BUFFER_BIT));
+            }
+            let frustum = Frustum::from_matrix(self.cascades.light_spaces[cascade]);
+            let ani_ids = self.cull_shadow_casters(em, |b| frustum.intersects_aabb(b));
+            self.render_sample_depth(em, ShaderType::Depth, &ani_ids);
+        }
+
+        unsafe {
             // gl_call!(gl::CullFace(gl::BACK)); 
             gl_call!(gl::Disable(CULL_FACE));
             // End render
-            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER,0));
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.output_fbo));
             gl_call!(gl::Viewport(0, 0, fb_width as i32, fb_height as i32));
 
             gl_call!(gl::ClearColor(0.0, 0.0, 0.0, 1.0));
@@ -563,142 +759,293 @@ impl Renderer {
             unsafe {
                 let depth_debug_quad = self.shaders.get(&ShaderType::DebugShadowMap).unwrap();
                 depth_debug_quad.activate();
+                depth_debug_quad.set_int("layer", self.shadow_debug_cascade.min(SHADOW_CASCADES as u32 - 1));
+                depth_debug_quad.set_int("depth_map", 0);
                 gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-                gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_map));
+                gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.depth_map));
             }
             self.render_quad();
-            return;
         }
     }
 
-    fn render_sample_depth(&mut self, em: &EntityManager) {
-        let depth_shader = self.shaders.get(&ShaderType::Depth).unwrap();
-        depth_shader.activate();
-
-        depth_shader.set_bool("is_animated", false);
-        for model in em.models.iter() {
-            let check = em.factions.get(model.key()).unwrap();
-            // TODO:: Get rid of this.
-            if check == &Faction::Gizmo {
-                continue;
-            }
-            let trans = em.transforms.get(model.key()).unwrap();
+    fn upload_lights(&mut self, light_manager: &Lights, camera: &Camera, fb_width: u32, fb_height: u32) {
+        let mut lights = light_manager.gpu_lights();
+        self.light_count = lights.len();
+        if self.clusters.enabled || self.clusters.heatmap {
+            self.clusters.update(camera, &lights, fb_width, fb_height);
+        }
+        // Some drivers complain about an empty buffer bound to an SSBO slot
+        if lights.is_empty() {
+            lights.push(GpuLight::default());
+        }
 
-            let model_model = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
-            unsafe {
-                gl::BindVertexArray(model.value.vao);
-            }
-            depth_shader.set_mat4("model", model_model);
-            unsafe {
-                gl_call!(gl::DrawElements(
-                    gl::TRIANGLES, 
-                    model.value.indices.len() as i32, 
-                    gl::UNSIGNED_INT, 
-                    std::ptr::null(),
-                ));
+        unsafe {
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.light_ssbo));
+            gl_call!(gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                mem::size_of_val(lights.as_slice()) as isize,
+                lights.as_ptr().cast(),
+                gl::DYNAMIC_DRAW
+            ));
+            gl_call!(gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, LIGHT_BUFFER_BINDING, self.light_ssbo));
+            gl_call!(gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0));
+        }
+    }
 
-                gl_call!(gl::BindVertexArray(0));
-            }
+    /// Renders every shadow casting point light into its cube, one face at a time.
+    fn point_shadow_pass(&mut self, em: &EntityManager, light_manager: &Lights) {
+        let casters = light_manager.shadow_casters();
+        if casters.is_empty() {
+            return;
         }
-        depth_shader.set_bool("is_animated", true);
 
-        for ani_model in em.ani_models.iter() {
-            if let Some(animator) = em.animators.get(ani_model.key()) {
-                let animation = animator.animations.get(&animator.current_animation).unwrap();
-                let trans = em.transforms.get(ani_model.key()).unwrap();
+        // Standard cube map face order, +X -X +Y -Y +Z -Z
+        let faces = [
+            (vec3(1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+            (vec3(-1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+            (vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)),
+            (vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, -1.0)),
+            (vec3(0.0, 0.0, 1.0), vec3(0.0, -1.0, 0.0)),
+            (vec3(0.0, 0.0, -1.0), vec3(0.0, -1.0, 0.0)),
+        ];
 
-                depth_shader.set_mat4_array("bone_transforms", &animation.current_pose);
+        unsafe {
+            gl_call!(gl::Viewport(0, 0, POINT_SHADOW_SIZE, POINT_SHADOW_SIZE));
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, *self.fbos.get(&FboType::PointShadow).unwrap()));
+        }
 
-                let mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
-                unsafe {
-                    gl::BindVertexArray(ani_model.value.vao);
-                }
-                depth_shader.set_mat4("model", mat);
+        for (index, light) in casters.iter().enumerate() {
+            let far_plane = attenuation_range(light.constant, light.linear, light.quadratic);
+            let projection = Mat4::perspective_rh_gl(90.0_f32.to_radians(), 1.0, 0.1, far_plane);
+            // Every face of the cube shares the same casters, anything inside the light's range
+            let ani_ids = self.cull_shadow_casters(em, |b| b.intersects_sphere(light.position, far_plane));
+
+            for (face, (dir, up)) in faces.iter().enumerate() {
+                let shader = self.shaders.get(&ShaderType::PointShadow).unwrap();
+                shader.activate();
+                shader.set_mat4("light_space_mat", projection * Mat4::look_at_rh(light.position, light.position + *dir, *up));
+                shader.set_vec3("light_position", light.position);
+                shader.set_float("far_plane", far_plane);
 
                 unsafe {
-                    gl_call!(gl::DrawElements(
-                        gl::TRIANGLES, 
-                        ani_model.value.indices.len() as i32, 
-                        gl::UNSIGNED_INT, 
-                        std::ptr::null(),
-                    ));
-
-                    gl_call!(gl::BindVertexArray(0));
+                    gl_call!(gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, self.point_shadow_map, 0, (index * 6 + face) as i32));
+                    gl_call!(gl::Clear(gl::DEPTH_BUFFER_BIT));
                 }
+                self.render_sample_depth(em, ShaderType::PointShadow, &ani_ids);
             }
         }
 
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.output_fbo));
+        }
+    }
+
+    /// Fills shadow_instances with the static casters that pass visible and returns the animated
+    /// ones that do. Gizmos never cast shadows.
+    fn cull_shadow_casters(&mut self, em: &EntityManager, visible: impl Fn(&Aabb) -> bool) -> Vec<usize> {
+        let culling = self.frustum_culling;
+        let visible = |b: &Aabb| !culling || visible(b);
+
+        let static_ids = culling::cull(em, em.static_renderables(), visible, &mut self.cull_stats.shadow);
+        self.shadow_instances.fill(em, static_ids.into_iter());
+
+        culling::cull(em, em.ani_models.iter().map(|entry| entry.key()), visible, &mut self.cull_stats.shadow)
     }
 
-    fn debug_light_pass(&mut self, camera: &mut Camera) {
+    /// Draws the batched static casters in shadow_instances and the given animated models with a
+    /// depth only shader, shared by the cascade and point light shadow passes.
+    fn render_sample_depth(&mut self, em: &EntityManager, shader_type: ShaderType, ani_ids: &[usize]) {
+        let depth_shader = self.shaders.get(&shader_type).unwrap();
+        draw_depth_only(depth_shader, em, &self.shadow_instances, ani_ids);
+    }
+
+    fn debug_light_pass(&mut self, camera: &mut Camera, light_manager: &Lights) {
         let debug_light_shader = self.shaders.get(&ShaderType::DebugLight).unwrap();
         debug_light_shader.activate();
         debug_light_shader.set_mat4("view", camera.view);
         debug_light_shader.set_mat4("projection", camera.projection);
 
+        let points = light_manager.point_lights.iter().map(|p| (p.value().position, Quat::IDENTITY, p.value().diffuse));
+        // Spot lights get stretched along their direction so the cone's aim is visible
+        let spots = light_manager.spot_lights.iter().map(|s| {
+            let dir = s.value().direction.normalize_or_zero();
+            (s.value().position, Quat::from_rotation_arc(Vec3::Z, dir), s.value().diffuse)
+        });
+
         unsafe {
             gl_call!(gl::BindVertexArray(*self.vaos.get(&VaoType::DebugLight).unwrap()));
-            for light_pos in &POINT_LIGHT_POSITIONS {
-
-                let mut m_mat = Mat4::IDENTITY;
-                m_mat *= Mat4::from_translation(*light_pos);
-                m_mat *= Mat4::from_scale(vec3(0.2, 0.2, 0.2)); 
+            for (is_spot, (position, rotation, color)) in points.map(|p| (false, p)).chain(spots.map(|s| (true, s))) {
+                let scale = if is_spot { vec3(0.15, 0.15, 0.5) } else { vec3(0.2, 0.2, 0.2) };
+                let m_mat = Mat4::from_scale_rotation_translation(scale, rotation, position);
 
                 debug_light_shader.set_mat4("model", m_mat);
-                debug_light_shader.set_vec3("LightColor", vec3(1.0, 1.0, 1.0));
+                debug_light_shader.set_vec3("LightColor", color);
 
                 gl_call!(gl::DrawArrays(gl::TRIANGLES, 0, 36));
             }
+            gl_call!(gl::BindVertexArray(0));
         }
     }
 
     pub fn render_quad(&self) {
-        let mut vao = 0;
-        let mut vbo = 0;
+        draw_quad(*self.vaos.get(&VaoType::Quad).unwrap());
+    }
 
-        let quad_vertices: [f32; 30] = [
-            // Positions      // Texture Coords
-            -1.0,  1.0, 0.0,  0.0, 1.0,
-            -1.0, -1.0, 0.0,  0.0, 0.0,
-             1.0, -1.0, 0.0,  1.0, 0.0,
+    /// Bloom and tonemapping onto the default framebuffer. Call after everything that belongs in
+    /// the scene (particles included) and before the UI.
+    pub fn post_process_pass(&mut self) {
+        if self.shadow_debug {
+            return;
+        }
+        let quad_vao = *self.vaos.get(&VaoType::Quad).unwrap();
+        self.post.apply(&self.shaders, quad_vao, self.output_fbo);
+        self.ssao.draw_debug(&self.shaders, quad_vao, self.output_fbo);
+    }
+}
 
-            -1.0,  1.0, 0.0,  0.0, 1.0,
-             1.0, -1.0, 0.0,  1.0, 0.0,
-             1.0,  1.0, 0.0,  1.0, 1.0
-        ];
+/// Shadow sampling uniforms for model.glsl, the cascade array always lives on texture unit 0.
+fn bind_shadow_cascades(shader: &Shader, cascades: &ShadowCascades, depth_map: u32, cascade_debug: bool) {
+    shader.set_mat4_array("light_space_mats", &cascades.light_spaces);
+    shader.set_float_array("cascade_splits", &cascades.splits);
+    shader.set_bool("cascade_debug", cascade_debug);
+    unsafe {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, depth_map));
+    }
+    shader.set_int("shadow_map", 0);
+}
 
-        unsafe {
-            gl_call!(gl::GenVertexArrays(1, &mut vao));
-            gl_call!(gl::GenBuffers(1, &mut vbo));
-            gl_call!(gl::BindVertexArray(vao));
+/// Point and spot light uniforms for model.glsl, the SSBO itself is bound once per frame in
+/// upload_lights. Point shadow cubes sit on unit 9, after the material textures.
+fn bind_local_lights(shader: &Shader, light_count: usize, point_shadow_map: u32, clusters: &LightClusters) {
+    shader.set_int("light_count", light_count as u32);
+    clusters.bind(shader);
+    unsafe {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE9));
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, point_shadow_map));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+    shader.set_int("point_shadow_maps", 9);
+}
 
-            gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, vbo));
-            gl_call!(gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (quad_vertices.len() * std::mem::size_of::<f32>()) as isize,
-                quad_vertices.as_ptr() as *const _,
-                gl::STATIC_DRAW
-            ));
+/// Face culling and alpha testing follow each batch's flags, the batches are sorted so these rarely
+/// change. soft_edges only draws the alpha tested batches again, without the cut out.
+fn draw_instance_batches(shader: &mut Shader, em: &EntityManager, instances: &InstanceBuffer, soft_edges: bool) {
+    for batch in instances.batches.iter().filter(|b| !soft_edges || b.flags.alpha_test) {
+        let Some(model) = em.assets.mesh(batch.mesh) else {
+            continue;
+        };
+        set_face_culling(batch.flags);
+        shader.set_bool("alpha_test_pass", batch.flags.alpha_test && !soft_edges);
+        shader.set_material_params(&batch.material);
+        shader.set_int("instance_offset", batch.offset);
+        model.draw_instanced(shader, batch.count);
+    }
+}
 
-            let stride = (5 * std::mem::size_of::<f32>()) as i32;
+fn set_face_culling(flags: RenderFlags) {
+    unsafe {
+        if flags.double_sided {
+            gl_call!(gl::Disable(CULL_FACE));
+        } else {
+            gl_call!(gl::Enable(CULL_FACE));
+            gl_call!(gl::CullFace(gl::BACK));
+        }
+    }
+}
 
-            // Position Attribute
-            gl_call!(gl::EnableVertexAttribArray(0));
-            gl_call!(gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null()));
+/// Geometry only for the shadow and SSAO prepass shaders, which share model.glsl's instancing,
+/// skinning and morph uniforms. Alpha tested batches also get their diffuse map, for shaders that
+/// discard the cut out parts like the SSAO prepass does.
+fn draw_depth_only(depth_shader: &Shader, em: &EntityManager, instances: &InstanceBuffer, ani_ids: &[usize]) {
+    depth_shader.activate();
+
+    depth_shader.set_bool("is_animated", false);
+    depth_shader.set_bool("is_instanced", true);
+    depth_shader.set_int("morph_target_count", 0);
+    instances.bind();
+    for batch in instances.batches.iter() {
+        let Some(model) = em.assets.mesh(batch.mesh) else {
+            continue;
+        };
+        depth_shader.set_int("instance_offset", batch.offset);
+        depth_shader.set_bool("alpha_test", batch.flags.alpha_test);
+        if batch.flags.alpha_test {
+            depth_shader.set_material_params(&batch.material);
+            model.draw_depth_alpha_tested(depth_shader, batch.count);
+        } else {
+            model.draw_depth_instanced(depth_shader, batch.count);
+        }
+    }
+    depth_shader.set_bool("is_instanced", false);
+    depth_shader.set_bool("is_animated", true);
 
-            // Texture Coordinate Attribute
-            gl_call!(gl::EnableVertexAttribArray(1));
-            gl_call!(gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (3 * std::mem::size_of::<f32>()) as *const _));
+    for id in ani_ids.iter().copied() {
+        if let Some(animator) = em.animators.get(id) {
+            let ani_model = em.ani_model(id).unwrap();
+            let animation = animator.animations.get(&animator.current_animation).unwrap();
+            let trans = em.transforms.get(id).unwrap();
 
-            gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, 0));
-            gl_call!(gl::BindVertexArray(0));
-        }
+            depth_shader.set_mat4_array("bone_transforms", &animation.current_pose);
+            ani_model.bind_morph_targets(depth_shader, &animation.current_morph_weights);
 
-        // Draw the quad
-        unsafe {
-            gl_call!(gl::BindVertexArray(vao));
-            gl_call!(gl::DrawArrays(gl::TRIANGLES, 0, 6));
-            gl_call!(gl::BindVertexArray(0));
+            let mat = Mat4::from_scale_rotation_translation(trans.scale, trans.rotation, trans.position);
+            depth_shader.set_mat4("model", mat);
+
+            let flags = em.render_flags.get(id).copied().unwrap_or_default();
+            depth_shader.set_bool("alpha_test", flags.alpha_test);
+            if flags.alpha_test {
+                depth_shader.set_material_params(em.material_params.get(id).unwrap_or(&MaterialParams::default()));
+                ani_model.draw_depth_alpha_tested(depth_shader, 1);
+            } else {
+                ani_model.draw_depth(depth_shader);
+            }
         }
     }
+    depth_shader.set_bool("alpha_test", false);
+}
+
+fn create_quad_vao() -> u32 {
+    let mut vao = 0;
+    let mut vbo = 0;
+
+    let quad_vertices: [f32; 30] = [
+        // Positions      // Texture Coords
+        -1.0,  1.0, 0.0,  0.0, 1.0,
+        -1.0, -1.0, 0.0,  0.0, 0.0,
+         1.0, -1.0, 0.0,  1.0, 0.0,
+
+        -1.0,  1.0, 0.0,  0.0, 1.0,
+         1.0, -1.0, 0.0,  1.0, 0.0,
+         1.0,  1.0, 0.0,  1.0, 1.0
+    ];
+
+    unsafe {
+        gl_call!(gl::GenVertexArrays(1, &mut vao));
+        gl_call!(gl::GenBuffers(1, &mut vbo));
+        gl_call!(gl::BindVertexArray(vao));
+
+        gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, vbo));
+        gl_call!(gl::BufferData(
+            gl::ARRAY_BUFFER,
+            (quad_vertices.len() * std::mem::size_of::<f32>()) as isize,
+            quad_vertices.as_ptr() as *const _,
+            gl::STATIC_DRAW
+        ));
+
+        let stride = (5 * std::mem::size_of::<f32>()) as i32;
+
+        // Position Attribute
+        gl_call!(gl::EnableVertexAttribArray(0));
+        gl_call!(gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null()));
+
+        // Texture Coordinate Attribute
+        gl_call!(gl::EnableVertexAttribArray(1));
+        gl_call!(gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (3 * std::mem::size_of::<f32>()) as *const _));
+
+        gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, 0));
+        gl_call!(gl::BindVertexArray(0));
+    }
+
+    vao
 }
@@ -1,36 +1,102 @@
 #![allow(dead_code, clippy::while_let_on_iterator, clippy::collapsible_if)]
-use std::{collections::HashMap, ffi::CString, fs::read_to_string, ptr};
+use std::{collections::HashMap, ffi::CString, fs::{self, read_to_string}, ptr, time::SystemTime};
 
 use gl::types::{GLint, GLuint};
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
 
-use crate::{gl_call, lights::{DirLight, PointLight}};
+use crate::{config::entity_config::MaterialParams, error::{LoadError, LoadResult}, gl_call, lights::{DirLight, PointLight}};
 
 pub struct Shader {
     pub id: GLuint,
     pub uniform_locations: HashMap<String, GLint>,
+
+    // For hot reloading
+    pub file_path: String,
+    pub modified: Option<SystemTime>,
 }
 
 impl Shader {
-    pub fn new(file_path: &str) -> Self {
-        let id = init_shader_program(file_path);
+    pub fn new(file_path: &str) -> LoadResult<Self> {
+        let id = build_shader_program(file_path).map_err(|log| LoadError::Shader {
+            path: file_path.to_string(),
+            log,
+        })?;
 
         let mut shader = Self {
             id,
             uniform_locations: HashMap::new(),
+            file_path: file_path.to_string(),
+            modified: modified_time(file_path),
         }; 
 
         shader.parse_and_store_uniforms(file_path);
 
+        Ok(shader)
+    }
+
+    /// Stand in for a shader that failed to build. Draws everything flat magenta with the usual
+    /// model/view/projection uniforms, and keeps the file path so hot reloading can swap the real
+    /// program in once the file is fixed.
+    pub fn placeholder(file_path: &str) -> Self {
+        let id = compile_program_from_sources(PLACEHOLDER_VS, None, PLACEHOLDER_FS)
+            .expect("built in placeholder shader should always compile");
+
+        let mut shader = Self {
+            id,
+            uniform_locations: HashMap::new(),
+            file_path: file_path.to_string(),
+            modified: modified_time(file_path),
+        };
+
+        // Uniforms the real shader would have declared, so the locations resolve after a reload
+        shader.parse_and_store_uniforms(file_path);
+        for name in ["model", "view", "projection"] {
+            shader.store_uniform_location(name);
+        }
+
         shader
     }
 
+    /// True if the file on disk changed since it was last (re)loaded.
+    pub fn is_stale(&self) -> bool {
+        let modified = modified_time(&self.file_path);
+        modified.is_some() && modified != self.modified
+    }
+
+    /// Recompiles from disk. On failure the old program is kept and the compile/link log is returned.
+    /// Manually stored uniform locations survive as long as the new source still declares them.
+    pub fn reload(&mut self) -> Result<(), String> {
+        self.modified = modified_time(&self.file_path);
+
+        let id = build_shader_program(&self.file_path)?;
+
+        unsafe {
+            gl_call!(gl::DeleteProgram(self.id));
+        }
+        self.id = id;
+
+        let names: Vec<String> = self.uniform_locations.keys().cloned().collect();
+        self.uniform_locations.clear();
+        let file_path = self.file_path.clone();
+        self.parse_and_store_uniforms(&file_path);
+        for name in names {
+            if !self.uniform_locations.contains_key(&name) {
+                self.store_uniform_location(&name);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn activate(&self) {
         unsafe { gl_call!(gl::UseProgram(self.id)) }
     }
 
     fn parse_and_store_uniforms(&mut self, file_path: &str) {
-        let data = read_to_string(file_path).unwrap();
+        // A missing file already got reported when building the program
+        let Ok(data) = read_to_string(file_path) else {
+            return;
+        };
         let mut lines = data.lines();
 
         while let Some(line) = lines.next() {
@@ -40,7 +106,7 @@ impl Shader {
                 continue;
             }
 
-            if parts[0] == "uniform" {
+            if parts[0] == "uniform" && parts.len() > 2 {
                 let cleaned_part = parts[2].split('[').collect::<Vec<&str>>()[0].replace(';', "");
 
                 match parts[1] {
@@ -90,12 +156,23 @@ impl Shader {
         self.store_uniform_location(format!("{}.Specular", name).as_str());
         self.store_uniform_location(format!("{}.Emissive", name).as_str());
         self.store_uniform_location(format!("{}.Opacity", name).as_str());
+        self.store_uniform_location(format!("{}.NormalMap", name).as_str());
+        self.store_uniform_location(format!("{}.Roughness", name).as_str());
+        self.store_uniform_location(format!("{}.Metalness", name).as_str());
+        self.store_uniform_location(format!("{}.Displacement", name).as_str());
     }
 
     pub fn get_uniform_location(&self, name: &str) -> GLint {
         *self.uniform_locations.get(name).unwrap_or(&-1)
     }
 
+    pub fn set_vec2(&self, name: &str, value: Vec2) {
+        let location = self.get_uniform_location(name);
+        if location != -1 {
+            unsafe { gl_call!(gl::Uniform2f(location, value.x, value.y)) }
+        }
+    }
+
     pub fn set_vec3(&self, name: &str, value: Vec3) {
         let location = self.get_uniform_location(name);
         if location != -1 {
@@ -151,6 +228,17 @@ impl Shader {
         }
     }
 
+    pub fn set_material_params(&self, value: &MaterialParams) {
+        let base_color = self.get_uniform_location("base_color_factor");
+        if base_color != -1 {
+            let [r, g, b, a] = value.base_color_factor;
+            unsafe { gl_call!(gl::Uniform4f(base_color, r, g, b, a)) }
+        }
+        self.set_float("roughness_factor", value.roughness);
+        self.set_float("metalness_factor", value.metalness);
+        self.set_float("emissive_strength", value.emissive_strength);
+    }
+
     pub fn set_point_light(&self, name: &str, value: &PointLight) {
         let position = self.get_uniform_location(format!("{}.position", name).as_str());
         let ambient = self.get_uniform_location(format!("{}.ambient", name).as_str());
@@ -174,7 +262,25 @@ impl Shader {
         }
     }
 
-    pub fn set_mat4_array(&self, name: &str, value: &Vec<Mat4>) {
+    pub fn set_float_array(&self, name: &str, value: &[f32]) {
+        let location = self.get_uniform_location(name);
+        if location != -1 {
+            unsafe {
+                gl_call!(gl::Uniform1fv(location, value.len() as i32, value.as_ptr()));
+            }
+        }
+    }
+
+    pub fn set_vec3_array(&self, name: &str, value: &[Vec3]) {
+        let location = self.get_uniform_location(name);
+        if location != -1 {
+            unsafe {
+                gl_call!(gl::Uniform3fv(location, value.len() as i32, value.as_ptr().cast()));
+            }
+        }
+    }
+
+    pub fn set_mat4_array(&self, name: &str, value: &[Mat4]) {
         let location = self.get_uniform_location(name);
         if location != -1 {
             let mut float_data = Vec::with_capacity(value.len() * 16);
@@ -191,53 +297,91 @@ impl Shader {
 
 }
 
-pub fn init_shader_program(file_path: &str) -> u32 {
-    let (vs_source, gs_source, fs_source) = extract_shader_sources(file_path);
+pub fn build_shader_program(file_path: &str) -> Result<u32, String> {
+    let (vs_source, gs_source, fs_source) = extract_shader_sources(file_path)?;
+    compile_program_from_sources(&vs_source, gs_source.as_deref(), &fs_source)
+}
 
-    let vs_cstr = CString::new(vs_source).expect("Failed to convert vs source to C string");
-    let fs_cstr = CString::new(fs_source).expect("Failed to convert fs source to C string");
+fn compile_program_from_sources(vs_source: &str, gs_source: Option<&str>, fs_source: &str) -> Result<u32, String> {
+    let vs_cstr = CString::new(vs_source).map_err(|_| "vertex source contains a nul byte".to_string())?;
+    let fs_cstr = CString::new(fs_source).map_err(|_| "fragment source contains a nul byte".to_string())?;
     
     unsafe {
         let shader = gl::CreateProgram();
+        let mut stages = vec![];
         
         // Vertex Shader
         let vertex_shader = gl::CreateShader(gl::VERTEX_SHADER);
         gl::ShaderSource(vertex_shader, 1, &vs_cstr.as_ptr(), ptr::null());
-        compile_shader(vertex_shader);
+        stages.push(vertex_shader);
+        let mut result = compile_shader(vertex_shader).map_err(|e| format!("VERTEX_SHADER: {}", e));
         gl::AttachShader(shader, vertex_shader);
 
         // Fragment Shader
         let fragment_shader = gl::CreateShader(gl::FRAGMENT_SHADER);
         gl::ShaderSource(fragment_shader, 1, &fs_cstr.as_ptr(), ptr::null());
-        compile_shader(fragment_shader);
+        stages.push(fragment_shader);
+        if result.is_ok() {
+            result = compile_shader(fragment_shader).map_err(|e| format!("FRAGMENT_SHADER: {}", e));
+        }
         gl::AttachShader(shader, fragment_shader);
         
         // optional geometry shader
-        let geometry_shader = if let Some(gs_source) = gs_source {
-            let gs_cstr = CString::new(gs_source).expect("Failed to convert gs source to C string");
+        if let Some(gs_source) = gs_source {
+            let gs_cstr = CString::new(gs_source).map_err(|_| "geometry source contains a nul byte".to_string())?;
             let geometry_shader = gl::CreateShader(gl::GEOMETRY_SHADER);
             gl_call!(gl::ShaderSource(geometry_shader, 1, &gs_cstr.as_ptr(), ptr::null()));
-            compile_shader(geometry_shader);
-            Some(geometry_shader)
-        } else {
-            None
-        };
-
+            stages.push(geometry_shader);
+            if result.is_ok() {
+                result = compile_shader(geometry_shader).map_err(|e| format!("GEOMETRY_SHADER: {}", e));
+            }
+        }
 
-        gl_call!(gl::LinkProgram(shader));
+        if result.is_ok() {
+            gl_call!(gl::LinkProgram(shader));
+            result = check_link(shader);
+        }
 
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
+        for stage in stages {
+            gl::DeleteShader(stage);
+        }
 
-        if let Some(geometry_shader) = geometry_shader {
-            gl::DeleteShader(geometry_shader);
+        match result {
+            Ok(()) => Ok(shader),
+            Err(e) => {
+                gl_call!(gl::DeleteProgram(shader));
+                Err(e)
+            }
         }
-        shader
     }
 }
 
-fn extract_shader_sources(file_path: &str) -> (String, Option<String>, String) {
-    let data = read_to_string(file_path).unwrap();
+const PLACEHOLDER_VS: &str = "#version 460 core
+layout (location = 0) in vec3 aPos;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+void main() {
+    gl_Position = projection * view * model * vec4(aPos, 1.0);
+}
+";
+
+const PLACEHOLDER_FS: &str = "#version 460 core
+out vec4 FragColor;
+
+void main() {
+    FragColor = vec4(1.0, 0.0, 1.0, 1.0);
+}
+";
+
+fn modified_time(file_path: &str) -> Option<SystemTime> {
+    fs::metadata(file_path).and_then(|m| m.modified()).ok()
+}
+
+fn extract_shader_sources(file_path: &str) -> Result<(String, Option<String>, String), String> {
+    let data = read_to_string(file_path).map_err(|e| format!("could not read {}: {}", file_path, e))?;
     let mut lines = data.lines();
 
     let mut current_shader = None;
@@ -273,25 +417,45 @@ fn extract_shader_sources(file_path: &str) -> (String, Option<String>, String) {
         }
     }
 
-    (
-        shader_sources.remove("VERTEX_SHADER").unwrap(), 
+    Ok((
+        shader_sources.remove("VERTEX_SHADER").ok_or("missing // VERTEX_SHADER section")?, 
         shader_sources.remove("GEOMETRY_SHADER"),
-        shader_sources.remove("FRAGMENT_SHADER").unwrap()
-    )
+        shader_sources.remove("FRAGMENT_SHADER").ok_or("missing // FRAGMENT_SHADER section")?
+    ))
 }
 
-fn compile_shader(input: u32) {
+fn compile_shader(input: u32) -> Result<(), String> {
     unsafe {
         gl_call!(gl::CompileShader(input));
 
         let mut success:i32 = 0;
-        let mut info_log = vec![0u8; 512];
+        let mut info_log = vec![0u8; 1024];
 
         gl_call!(gl::GetShaderiv(input, gl::COMPILE_STATUS, &mut success));
 
         if success == 0 {
-            gl_call!(gl::GetShaderInfoLog(input, 512, core::ptr::null_mut(), info_log.as_mut_ptr() as *mut i8));
-            println!("Problem compiling shader: {:?}", String::from_utf8_lossy(&info_log));
+            let mut len = 0;
+            gl_call!(gl::GetShaderInfoLog(input, info_log.len() as i32, &mut len, info_log.as_mut_ptr() as *mut i8));
+            return Err(String::from_utf8_lossy(&info_log[..len as usize]).to_string());
+        }
+    }
+
+    Ok(())
+}
+
+fn check_link(program: u32) -> Result<(), String> {
+    unsafe {
+        let mut success:i32 = 0;
+        let mut info_log = vec![0u8; 1024];
+
+        gl_call!(gl::GetProgramiv(program, gl::LINK_STATUS, &mut success));
+
+        if success == 0 {
+            let mut len = 0;
+            gl_call!(gl::GetProgramInfoLog(program, info_log.len() as i32, &mut len, info_log.as_mut_ptr() as *mut i8));
+            return Err(format!("LINK: {}", String::from_utf8_lossy(&info_log[..len as usize])));
         }
     }
+
+    Ok(())
 }
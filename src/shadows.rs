@@ -0,0 +1,79 @@
+use glam::{vec3, vec4, Mat4, Vec3};
+
+use crate::{camera::Camera, lights::Lights, some_data::{SHADOW_CASCADES, SHADOW_HEIGHT, SHADOW_WIDTH}};
+
+/// Per frame light space matrices for each cascade and the view space distance where each one
+/// ends. model.glsl picks the first cascade whose split is beyond the fragment.
+pub struct ShadowCascades {
+    pub light_spaces: [Mat4; SHADOW_CASCADES],
+    pub splits: [f32; SHADOW_CASCADES],
+}
+
+impl ShadowCascades {
+    pub fn new() -> Self {
+        Self {
+            light_spaces: [Mat4::IDENTITY; SHADOW_CASCADES],
+            splits: [0.0; SHADOW_CASCADES],
+        }
+    }
+
+    /// Slices the camera frustum between z_near and the light's shadow distance and fits an
+    /// orthographic box around each slice. The box is sized from the slice's bounding sphere so it
+    /// doesn't change size as the camera turns, and its origin is snapped to whole shadow map texels
+    /// so shadow edges don't shimmer as the camera moves.
+    pub fn fit(&mut self, camera: &Camera, lights: &Lights, aspect: f32) {
+        let light_dir = lights.dir_light.direction.normalize();
+        let near = camera.z_near;
+        let far = lights.shadow_distance.min(camera.z_far);
+
+        // Practical split scheme, a blend of logarithmic and uniform splits
+        let mut slice_near = near;
+        for (i, (light_space, split)) in self.light_spaces.iter_mut().zip(self.splits.iter_mut()).enumerate() {
+            let p = (i + 1) as f32 / SHADOW_CASCADES as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            let slice_far = lights.cascade_lambda * log + (1.0 - lights.cascade_lambda) * uniform;
+
+            *light_space = fit_slice(camera, light_dir, lights.cascade_z_extension, aspect, slice_near, slice_far);
+            *split = slice_far;
+            slice_near = slice_far;
+        }
+    }
+}
+
+fn fit_slice(camera: &Camera, light_dir: Vec3, z_extension: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let projection = Mat4::perspective_rh_gl(camera.fovy, aspect, near, far);
+    let inv = (projection * camera.view).inverse();
+
+    let mut corners = [Vec3::ZERO; 8];
+    let mut i = 0;
+    for x in [-1.0, 1.0] {
+        for y in [-1.0, 1.0] {
+            for z in [-1.0, 1.0] {
+                let corner = inv * vec4(x, y, z, 1.0);
+                corners[i] = corner.truncate() / corner.w;
+                i += 1;
+            }
+        }
+    }
+
+    let center = corners.iter().copied().sum::<Vec3>() / 8.0;
+    let radius = corners.iter().map(|c| c.distance(center)).fold(0.0, f32::max);
+    // Rounding keeps float noise in the corners from resizing the box every frame
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // Casters between the slice and the light still need to land in the map, hence the extension
+    let up = if light_dir.y.abs() > 0.99 { vec3(0.0, 0.0, 1.0) } else { vec3(0.0, 1.0, 0.0) };
+    let light_view = Mat4::look_at_rh(center + light_dir * (radius + z_extension), center, up);
+    let mut light_projection = Mat4::orthographic_rh_gl(-radius, radius, -radius, radius, 0.0, 2.0 * radius + z_extension);
+
+    // Snap the world origin to the texel grid and shift the projection by the rounding error
+    let shadow_origin = (light_projection * light_view).transform_point3(Vec3::ZERO);
+    let texel = vec3(SHADOW_WIDTH as f32 / 2.0, SHADOW_HEIGHT as f32 / 2.0, 1.0);
+    let scaled = shadow_origin * texel;
+    let offset = (scaled.round() - scaled) / texel;
+    light_projection.w_axis.x += offset.x;
+    light_projection.w_axis.y += offset.y;
+
+    light_projection * light_view
+}
@@ -0,0 +1,131 @@
+use std::f32::consts::PI;
+
+use glam::{vec3, Vec3};
+
+use crate::shaders::Shader;
+
+// Starlight so nights aren't pitch black, must match NIGHT_SKY in skybox.glsl
+const NIGHT_SKY: Vec3 = vec3(0.004, 0.006, 0.012);
+
+pub struct FogSettings {
+    pub enabled: bool,
+    // Extinction per world unit at base_height
+    pub density: f32,
+    // How quickly the fog thins out going up
+    pub height_falloff: f32,
+    pub base_height: f32,
+    // Only used with the cubemap, the procedural sky picks its own horizon colors
+    pub color: Vec3,
+}
+
+/// Either the FACES_CUBEMAP skybox or an analytic single scattering sky lit by the DirLight, plus
+/// the exponential height fog model.glsl fades distant surfaces into. With the procedural sky the
+/// fog takes its colors from the sky's horizon, so the two stay matched as the sun moves.
+pub struct Sky {
+    pub procedural: bool,
+    pub sun_intensity: f32,
+    // Optical depth looking straight up, Rayleigh per channel and Mie for all of them
+    pub rayleigh: Vec3,
+    pub mie: f32,
+    // Mie forward scattering, higher makes a tighter glow around the sun
+    pub mie_g: f32,
+    pub ground_color: Vec3,
+    // Multiplies the cubemap, the time of day darkens and warms it
+    pub cubemap_tint: Vec3,
+    pub fog: FogSettings,
+}
+
+impl Sky {
+    pub fn new() -> Self {
+        Self {
+            procedural: true,
+            sun_intensity: 20.0,
+            rayleigh: vec3(0.0464, 0.108, 0.265),
+            mie: 0.021,
+            mie_g: 0.76,
+            ground_color: vec3(0.35, 0.3, 0.25),
+            cubemap_tint: Vec3::ONE,
+            fog: FogSettings {
+                enabled: true,
+                density: 0.004,
+                height_falloff: 0.08,
+                base_height: 0.0,
+                color: vec3(0.6, 0.68, 0.78),
+            },
+        }
+    }
+
+    /// Linear radiance looking along dir, without the sun disk. Mirrors sky_radiance in skybox.glsl.
+    pub fn radiance(&self, dir: Vec3, sun_dir: Vec3) -> Vec3 {
+        let dir = dir.normalize_or_zero();
+        let sun_dir = sun_dir.normalize_or_zero();
+        let mu = dir.dot(sun_dir);
+        let total = self.rayleigh + Vec3::splat(self.mie);
+
+        // Sunlight left after crossing the atmosphere, fading out through twilight
+        let sun_transmittance = exp3(-total * air_mass(sun_dir.y)) * smoothstep(-0.1, 0.02, sun_dir.y);
+        let scatter = (self.rayleigh * rayleigh_phase(mu) + Vec3::splat(self.mie * mie_phase(mu, self.mie_g))) / total;
+        let in_scatter = Vec3::ONE - exp3(-total * air_mass(dir.y));
+
+        self.sun_intensity * sun_transmittance * scatter * in_scatter + NIGHT_SKY
+    }
+
+    /// Uniforms for the procedural branch of skybox.glsl.
+    pub fn bind_sky(&self, shader: &Shader, sun_dir: Vec3) {
+        shader.set_bool("procedural_sky", self.procedural);
+        shader.set_vec3("sun_direction", sun_dir.normalize_or_zero());
+        shader.set_float("sun_intensity", self.sun_intensity);
+        shader.set_vec3("rayleigh", self.rayleigh);
+        shader.set_float("mie", self.mie);
+        shader.set_float("mie_g", self.mie_g);
+        shader.set_vec3("ground_color", self.ground_color);
+        shader.set_vec3("cubemap_tint", self.cubemap_tint);
+    }
+
+    /// Fog uniforms for model.glsl. Away from the sun the fog takes the horizon color, towards it
+    /// the brighter horizon under the sun.
+    pub fn bind_fog(&self, shader: &Shader, sun_dir: Vec3) {
+        let (fog_color, fog_sun_color) = if self.procedural {
+            let toward_sun = vec3(sun_dir.x, 0.0, sun_dir.z).try_normalize().unwrap_or(Vec3::X);
+            let across = vec3(-toward_sun.z, 0.0, toward_sun.x);
+            (self.radiance(across, sun_dir), self.radiance(toward_sun, sun_dir))
+        } else {
+            // Picked by eye against the gamma encoded cubemap
+            let color = (self.fog.color * self.cubemap_tint).powf(2.2);
+            (color, color)
+        };
+
+        shader.set_bool("use_fog", self.fog.enabled);
+        shader.set_vec3("fog_color", fog_color);
+        shader.set_vec3("fog_sun_color", fog_sun_color);
+        shader.set_float("fog_density", self.fog.density);
+        shader.set_float("fog_height_falloff", self.fog.height_falloff);
+        shader.set_float("fog_base_height", self.fog.base_height);
+    }
+}
+
+// Kasten-Young, relative length of the path through the atmosphere, 1 straight up and ~38 flat
+fn air_mass(cos_zenith: f32) -> f32 {
+    let cos_zenith = cos_zenith.clamp(0.0, 1.0);
+    let zenith_degrees = cos_zenith.acos().to_degrees();
+    1.0 / (cos_zenith + 0.50572 * (96.07995 - zenith_degrees).powf(-1.6364))
+}
+
+fn rayleigh_phase(mu: f32) -> f32 {
+    3.0 / (16.0 * PI) * (1.0 + mu * mu)
+}
+
+// Henyey-Greenstein
+fn mie_phase(mu: f32, g: f32) -> f32 {
+    let g2 = g * g;
+    (1.0 - g2) / (4.0 * PI * (1.0 + g2 - 2.0 * g * mu).powf(1.5))
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn exp3(v: Vec3) -> Vec3 {
+    vec3(v.x.exp(), v.y.exp(), v.z.exp())
+}
@@ -161,8 +161,21 @@ pub const POINT_LIGHT_POSITIONS:[Vec3; 4] = [
     Vec3::new(0.0, 0.0, -3.0),
 ];
 
-pub const SHADOW_WIDTH:i32 = 10_000;
-pub const SHADOW_HEIGHT:i32 = 10_000;
+// Per cascade, the depth texture array holds SHADOW_CASCADES layers of this size
+pub const SHADOW_WIDTH:i32 = 2048;
+pub const SHADOW_HEIGHT:i32 = 2048;
+pub const SHADOW_CASCADES: usize = 4;
+
+// Point light shadows, one cube per caster in a cube map array
+pub const MAX_SHADOW_POINT_LIGHTS: usize = 4;
+pub const POINT_SHADOW_SIZE: i32 = 1024;
+// SSBO binding for the LightBuffer in model.glsl, 3 is taken by morph targets
+pub const LIGHT_BUFFER_BINDING: u32 = 4;
+// Clustered lighting, per cluster (offset, count) ranges and the light index list they point into
+pub const CLUSTER_RANGES_BINDING: u32 = 5;
+pub const CLUSTER_INDICES_BINDING: u32 = 6;
+// Per instance model matrices and flags for instanced static models
+pub const INSTANCE_BUFFER_BINDING: u32 = 7;
 
 
 pub const GROUND_PLANE:[f32; 36] = [
@@ -202,3 +215,7 @@ pub const TREES:[&str; 6] = [
 
 pub const MAX_BONE_INFLUENCE: usize = 4;
 pub const MAX_BONES: u32 = 200;
+// Must match MAX_MORPH_TARGETS in model.glsl and depth_shader.glsl
+pub const MAX_MORPH_TARGETS: usize = 8;
+// SSBO binding point for the morph target deltas
+pub const MORPH_DELTA_BINDING: u32 = 3;
@@ -4,12 +4,13 @@ use std::{cell::Cell, collections::HashMap, ffi::CString};
 
 use glam::Vec3;
 
-use crate::{camera::Camera, config::game_config::GameConfig, sound::fmod::{FMOD_Studio_EventDescription_LoadSampleData, FMOD_INIT_3D_RIGHTHANDED}};
+use crate::{camera::Camera, config::game_config::GameConfig, error::LoadError, sound::fmod::{FMOD_Studio_EventDescription_LoadSampleData, FMOD_INIT_3D_RIGHTHANDED}};
 
 use super::fmod::{FMOD_Studio_EventDescription_CreateInstance, FMOD_Studio_EventInstance_Release, FMOD_Studio_EventInstance_Set3DAttributes, FMOD_Studio_EventInstance_SetParameterByName, FMOD_Studio_EventInstance_Start, FMOD_Studio_EventInstance_Stop, FMOD_Studio_System_Create, FMOD_Studio_System_GetEvent, FMOD_Studio_System_Initialize, FMOD_Studio_System_LoadBankFile, FMOD_Studio_System_SetListenerAttributes, FMOD_Studio_System_Update, FMOD_3D_ATTRIBUTES, FMOD_INIT_NORMAL, FMOD_STUDIO_BANK, FMOD_STUDIO_EVENTDESCRIPTION, FMOD_STUDIO_EVENTINSTANCE, FMOD_STUDIO_INIT_NORMAL, FMOD_STUDIO_SYSTEM, FMOD_VECTOR, FMOD_VERSION};
 
 pub struct SoundData {
     description: FMOD_STUDIO_EVENTDESCRIPTION,
+    path: String,
     // instance: FMOD_STUDIO_EVENTINSTANCE,
 }
 
@@ -42,7 +43,9 @@ pub struct SoundManager {
 } 
 
 impl SoundManager {
-    pub fn new(config: &GameConfig) -> SoundManager {
+    /// Bank and event failures are pushed onto errors and the affected sounds just don't play.
+    /// Failing to bring up the FMOD system itself still panics since nothing else can work without it.
+    pub fn new(config: &GameConfig, errors: &mut Vec<LoadError>) -> SoundManager {
         let sound_props = &config.sounds;
 
         let mut fmod_system: FMOD_STUDIO_SYSTEM = std::ptr::null_mut();
@@ -78,7 +81,11 @@ impl SoundManager {
             );
 
             if result != 0 {
-                panic!("FMOD Bank load failed with error code {}", result);
+                errors.push(LoadError::Sound {
+                    name: "Master.bank".to_string(),
+                    event: "resources/fmod/Desktop/Master.bank".to_string(),
+                    code: result,
+                });
             }
 
             let strings_bank_path = CString::new("resources/fmod/Desktop/Master.strings.bank").expect("CString::new failed");
@@ -91,28 +98,32 @@ impl SoundManager {
             );
 
             if result != 0 {
-                panic!("FMOD Strings Bank load failed with error code {}", result);
+                errors.push(LoadError::Sound {
+                    name: "Master.strings.bank".to_string(),
+                    event: "resources/fmod/Desktop/Master.strings.bank".to_string(),
+                    code: result,
+                });
             }
 
             /***************** CREATE EVENT DESC AND INSTANCES ****************/
 
 
             for (sound_name, path) in sound_props {
-                let event_path = CString::new(path.as_str()).expect("CString::new failed");
-                let mut description: FMOD_STUDIO_EVENTDESCRIPTION = std::ptr::null_mut();
-
-                let result = FMOD_Studio_System_GetEvent(
-                    fmod_system, 
-                    event_path.as_ptr(), 
-                    &mut description
-                );
-                if result != 0 {
-                    panic!("Failed to load the event for {:?} with code {}", sound_name, result);
-                }
+                let description = match load_event_description(fmod_system, path) {
+                    Ok(description) => description,
+                    Err(code) => {
+                        errors.push(LoadError::Sound {
+                            name: sound_name.to_string(),
+                            event: path.to_string(),
+                            code,
+                        });
+                        continue;
+                    }
+                };
 
-                FMOD_Studio_EventDescription_LoadSampleData(description);
                 sounds.insert(sound_name.to_string(), SoundData {
                     description, 
+                    path: path.to_string(),
                 });
             }
         }
@@ -127,6 +138,31 @@ impl SoundManager {
 
     }
 
+    /// Picks up changes to the sound name -> event path mapping in game_config.json. A mapping that
+    /// fails to load is reported and the old one (if any) is kept.
+    pub fn reload_sounds(&mut self, config: &GameConfig) {
+        self.sounds.retain(|name, _| config.sounds.contains_key(name));
+
+        for (sound_name, path) in config.sounds.iter() {
+            if self.sounds.get(sound_name).is_some_and(|s| s.path == *path) {
+                continue;
+            }
+
+            match load_event_description(self.fmod_system, path) {
+                Ok(description) => {
+                    println!("Remapped sound {} to {}", sound_name, path);
+                    self.sounds.insert(sound_name.to_string(), SoundData {
+                        description,
+                        path: path.to_string(),
+                    });
+                }
+                Err(result) => {
+                    eprintln!("Failed to load the event for {:?} with code {}", sound_name, result);
+                }
+            }
+        }
+    }
+
     pub fn update(&self, camera: &Camera) {
         unsafe {
             let result = FMOD_Studio_System_Update(self.fmod_system);
@@ -271,3 +307,23 @@ impl SoundManager {
         FMOD_VECTOR { x: v.x, y: v.y, z: -v.z }
     }
 }
+
+fn load_event_description(fmod_system: FMOD_STUDIO_SYSTEM, path: &str) -> Result<FMOD_STUDIO_EVENTDESCRIPTION, i32> {
+    let event_path = CString::new(path).expect("CString::new failed");
+    let mut description: FMOD_STUDIO_EVENTDESCRIPTION = std::ptr::null_mut();
+
+    unsafe {
+        let result = FMOD_Studio_System_GetEvent(
+            fmod_system, 
+            event_path.as_ptr(), 
+            &mut description
+        );
+        if result != 0 {
+            return Err(result);
+        }
+
+        FMOD_Studio_EventDescription_LoadSampleData(description);
+    }
+
+    Ok(description)
+}
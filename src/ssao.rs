@@ -0,0 +1,251 @@
+use glam::{vec2, vec3, Mat4, Vec3};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{assets::Shaders, enums_types::ShaderType, gl_call, post_process::{create_target, draw_quad}, shaders::Shader};
+
+// Must match KERNEL_SIZE in ssao.glsl
+const SSAO_KERNEL_SIZE: usize = 32;
+// Noise tile is NOISE_SIZE squared texels, ssao_blur.glsl blurs over the same size
+const NOISE_SIZE: i32 = 4;
+// model.glsl reads the blurred result from this unit, past the point shadow cube array on 9
+const SSAO_TEXTURE_UNIT: u32 = 10;
+
+/// Screen space ambient occlusion. A prepass renders view space normals and depth for the visible
+/// geometry, ssao.glsl compares a hemisphere of samples around each pixel against that depth and
+/// ssao_blur.glsl smooths out the noise. model.glsl then scales its ambient term by the result.
+pub struct Ssao {
+    pub enabled: bool,
+    // World units the hemisphere reaches out
+    pub radius: f32,
+    // Exponent on the occlusion factor, above 1.0 darkens creases harder
+    pub intensity: f32,
+    pub bias: f32,
+    // Shows the blurred occlusion instead of the scene
+    pub debug_view: bool,
+
+    kernel: Vec<Vec3>,
+    noise_texture: u32,
+
+    width: i32,
+    height: i32,
+    prepass_fbo: u32,
+    normal_texture: u32,
+    depth_texture: u32,
+    ao_fbo: u32,
+    ao_texture: u32,
+    blur_fbo: u32,
+    blur_texture: u32,
+}
+
+impl Ssao {
+    pub fn new() -> Self {
+        // Fixed seed so the noise pattern is the same every run
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+
+        let kernel = (0..SSAO_KERNEL_SIZE)
+            .map(|i| {
+                let sample = vec3(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(0.0..1.0),
+                ).normalize_or_zero() * rng.random_range(0.0..1.0);
+
+                // Bunch the samples up near the center, close geometry matters most
+                let t = i as f32 / SSAO_KERNEL_SIZE as f32;
+                sample * (0.1 + 0.9 * t * t)
+            })
+            .collect();
+
+        let noise: Vec<f32> = (0..NOISE_SIZE * NOISE_SIZE)
+            .flat_map(|_| [rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0), 0.0])
+            .collect();
+
+        let mut noise_texture = 0;
+        let mut prepass_fbo = 0;
+        let mut ao_fbo = 0;
+        let mut blur_fbo = 0;
+        unsafe {
+            gl_call!(gl::GenTextures(1, &mut noise_texture));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, noise_texture));
+            gl_call!(gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB16F as i32, NOISE_SIZE, NOISE_SIZE, 0, gl::RGB, gl::FLOAT, noise.as_ptr().cast()));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32));
+            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+
+            gl_call!(gl::GenFramebuffers(1, &mut prepass_fbo));
+            gl_call!(gl::GenFramebuffers(1, &mut ao_fbo));
+            gl_call!(gl::GenFramebuffers(1, &mut blur_fbo));
+        }
+
+        Self {
+            enabled: true,
+            radius: 0.5,
+            intensity: 1.5,
+            bias: 0.025,
+            debug_view: false,
+
+            kernel,
+            noise_texture,
+
+            width: 0,
+            height: 0,
+            prepass_fbo,
+            normal_texture: 0,
+            depth_texture: 0,
+            ao_fbo,
+            ao_texture: 0,
+            blur_fbo,
+            blur_texture: 0,
+        }
+    }
+
+    /// Binds and clears the normal/depth target, resizing it first if the framebuffer changed size.
+    pub fn begin_prepass(&mut self, fb_width: u32, fb_height: u32) {
+        // Compare against the 1x1 minimum resize keeps so a minimised window doesn't realloc each frame
+        let (width, height) = ((fb_width as i32).max(1), (fb_height as i32).max(1));
+        if width != self.width || height != self.height {
+            self.resize(width, height);
+        }
+
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.prepass_fbo));
+            gl_call!(gl::Viewport(0, 0, self.width, self.height));
+            gl_call!(gl::ClearColor(0.0, 0.0, 1.0, 1.0));
+            gl_call!(gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT));
+            gl_call!(gl::Enable(gl::DEPTH_TEST));
+            gl_call!(gl::DepthMask(gl::TRUE));
+            gl_call!(gl::Disable(gl::BLEND));
+            gl_call!(gl::Disable(gl::CULL_FACE));
+        }
+    }
+
+    /// Turns the prepass into the blurred occlusion texture. Leaves output_fbo bound.
+    pub fn compute(&self, shaders: &Shaders, quad_vao: u32, projection: Mat4, output_fbo: u32) {
+        unsafe {
+            gl_call!(gl::Disable(gl::DEPTH_TEST));
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.ao_fbo));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_texture));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE1));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.normal_texture));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE2));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.noise_texture));
+        }
+
+        let shader = shaders.get(&ShaderType::Ssao).unwrap();
+        shader.activate();
+        shader.set_int("depth_map", 0);
+        shader.set_int("normal_map", 1);
+        shader.set_int("noise_map", 2);
+        shader.set_vec3_array("samples", &self.kernel);
+        shader.set_mat4("projection", projection);
+        shader.set_mat4("inv_projection", projection.inverse());
+        shader.set_vec2("noise_scale", vec2(self.width as f32, self.height as f32) / NOISE_SIZE as f32);
+        shader.set_float("radius", self.radius);
+        shader.set_float("bias", self.bias);
+        shader.set_float("intensity", self.intensity);
+        draw_quad(quad_vao);
+
+        unsafe {
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE1));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.blur_fbo));
+        }
+        self.blur(shaders, quad_vao);
+
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, output_fbo));
+            gl_call!(gl::Enable(gl::DEPTH_TEST));
+        }
+    }
+
+    /// Every model.glsl pass calls this, the sampler has to point at its own unit even while SSAO
+    /// is off or it would alias the shadow map on unit 0.
+    pub fn bind(&self, shader: &Shader) {
+        shader.set_bool("use_ssao", self.enabled);
+        shader.set_int("ssao_map", SSAO_TEXTURE_UNIT);
+        unsafe {
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0 + SSAO_TEXTURE_UNIT));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, if self.enabled { self.blur_texture } else { 0 }));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+        }
+    }
+
+    /// Draws the occlusion over all of output_fbo when debug_view is on.
+    pub fn draw_debug(&self, shaders: &Shaders, quad_vao: u32, output_fbo: u32) {
+        if !self.enabled || !self.debug_view {
+            return;
+        }
+
+        unsafe {
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, output_fbo));
+            gl_call!(gl::Disable(gl::DEPTH_TEST));
+            gl_call!(gl::Disable(gl::BLEND));
+        }
+        self.blur(shaders, quad_vao);
+        unsafe {
+            gl_call!(gl::Enable(gl::DEPTH_TEST));
+        }
+    }
+
+    // Blurs the raw occlusion into whatever framebuffer is bound
+    fn blur(&self, shaders: &Shaders, quad_vao: u32) {
+        let shader = shaders.get(&ShaderType::SsaoBlur).unwrap();
+        shader.activate();
+        shader.set_int("ssao_input", 0);
+
+        unsafe {
+            gl_call!(gl::Viewport(0, 0, self.width, self.height));
+            gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.ao_texture));
+        }
+        draw_quad(quad_vao);
+        unsafe {
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        }
+    }
+
+    fn resize(&mut self, width: i32, height: i32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+
+        unsafe {
+            if self.normal_texture != 0 {
+                for texture in [self.normal_texture, self.depth_texture, self.ao_texture, self.blur_texture] {
+                    gl_call!(gl::DeleteTextures(1, &texture));
+                }
+            }
+
+            self.normal_texture = create_target(gl::RGB16F, gl::RGB, gl::FLOAT, self.width, self.height);
+            self.depth_texture = create_target(gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, gl::FLOAT, self.width, self.height);
+            self.ao_texture = create_target(gl::R8, gl::RED, gl::UNSIGNED_BYTE, self.width, self.height);
+            self.blur_texture = create_target(gl::R8, gl::RED, gl::UNSIGNED_BYTE, self.width, self.height);
+
+            // Filtering depth or normals across silhouettes makes halos
+            for texture in [self.normal_texture, self.depth_texture] {
+                gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture));
+                gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
+                gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
+            }
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.prepass_fbo));
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.normal_texture, 0));
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, self.depth_texture, 0));
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                println!("SSAO prepass framebuffer incomplete at {}x{}", self.width, self.height);
+            }
+
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.ao_fbo));
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.ao_texture, 0));
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.blur_fbo));
+            gl_call!(gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.blur_texture, 0));
+
+            gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+        }
+    }
+}
@@ -1,9 +1,9 @@
 use gl::ActiveTexture;
-use glam::{vec2, vec3, Vec3};
+use glam::{vec2, vec3, Vec3, Vec4};
 use image::GenericImageView;
 use imgui::sys::igSetWindowPosVec2;
 
-use crate::{animation::animation::{texture_from_file, Model, Vertex}, enums_types::TextureType, some_data::MAX_BONE_INFLUENCE};
+use crate::{animation::animation::{texture_from_file, Model, Vertex}, enums_types::TextureType, error::{LoadError, LoadResult}, some_data::MAX_BONE_INFLUENCE};
 
 pub struct Terrain {
     vertices: Vec<[f32; 3]>,
@@ -17,10 +17,20 @@ pub struct Terrain {
 }
 
 impl Terrain {
-    pub fn from_height_map(path: &str) -> Self {
-        let img = image::open(path).expect("Failed to load terrain image");
+    pub fn from_height_map(path: &str) -> LoadResult<Self> {
+        let img = image::open(path).map_err(|e| LoadError::Image {
+            path: path.to_string(),
+            message: e.to_string(),
+        })?;
         let (width, height) = img.dimensions();
 
+        if width < 2 || height < 2 {
+            return Err(LoadError::Image {
+                path: path.to_string(),
+                message: format!("height map has to be at least 2x2, got {}x{}", width, height),
+            });
+        }
+
         let grayscale = img.to_luma8();
 
         let height_map: Vec<f32> = grayscale
@@ -29,6 +39,16 @@ impl Terrain {
             .map(|p| p[0] as f32 / 255.0 * 10.0)
             .collect();
 
+        Ok(Self::from_heights(width, height, height_map))
+    }
+
+    /// Placeholder for when the height map can't be loaded.
+    pub fn flat(width: u32, height: u32) -> Self {
+        Self::from_heights(width, height, vec![5.0; (width * height) as usize])
+    }
+
+    fn from_heights(width: u32, height: u32, height_map: Vec<f32>) -> Self {
+
         // VERTICES
         let mut vertices = Vec::new();
         for y in 0..height {
@@ -107,6 +127,7 @@ impl Terrain {
                 position: vec3(v[0], v[1], v[2]),
                 normal: vec3(n[0], n[1], n[2]),
                 uv: vec2(0.0, 0.0),
+                tangent: Vec4::X,
 
                 bone_ids: [-1; MAX_BONE_INFLUENCE],
                 bone_weights: [0.0; MAX_BONE_INFLUENCE],
@@ -114,7 +135,9 @@ impl Terrain {
         };
 
         model.directory = "resources/models/static/terrain".to_string();
-        texture_from_file(&mut model, "not_found.png".to_string(), TextureType::Diffuse);
+        if let Err(e) = texture_from_file(&mut model, 0, "not_found.png".to_string(), TextureType::Diffuse) {
+            println!("WARNING: {}", e);
+        }
 
         model.indices = self.indices.clone();
         model.setup_opengl();
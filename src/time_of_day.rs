@@ -0,0 +1,139 @@
+use std::f32::consts::PI;
+
+use glam::{vec3, Vec3};
+
+use crate::{config::game_config::{TimeKey, TimeOfDayConfig}, lights::DirLight, sky::Sky};
+
+/// Game clock for the day/night cycle. Moves the sun along an arc between sunrise and sunset (and
+/// under the ground the rest of the time) and blends the config's keys into the DirLight and sky.
+pub struct TimeOfDay {
+    // Off leaves the DirLight and sky to the Lights and Sky windows
+    pub enabled: bool,
+    pub paused: bool,
+    pub hour: f32,
+    pub speed: f32,
+    // What the current level asked for, written back when the level gets saved
+    pub level_hour: Option<f32>,
+    config: TimeOfDayConfig,
+}
+
+impl TimeOfDay {
+    pub fn new(config: &TimeOfDayConfig, level_hour: Option<f32>) -> Self {
+        Self {
+            enabled: config.enabled,
+            paused: false,
+            hour: level_hour.unwrap_or(config.start_hour).rem_euclid(24.0),
+            speed: config.speed,
+            level_hour,
+            config: normalized(config),
+        }
+    }
+
+    /// Hot reloaded config, the clock keeps running from where it was.
+    pub fn apply_config(&mut self, config: &TimeOfDayConfig) {
+        self.enabled = config.enabled;
+        self.speed = config.speed;
+        self.config = normalized(config);
+    }
+
+    /// A newly loaded level starts at its own hour if it has one.
+    pub fn start_level(&mut self, level_hour: Option<f32>) {
+        self.level_hour = level_hour;
+        self.hour = level_hour.unwrap_or(self.config.start_hour).rem_euclid(24.0);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if !self.enabled || self.paused {
+            return;
+        }
+        self.hour = (self.hour + dt * self.speed).rem_euclid(24.0);
+    }
+
+    /// Writes the current sun and key into the DirLight and sky.
+    pub fn apply(&self, dir_light: &mut DirLight, sky: &mut Sky) {
+        if !self.enabled {
+            return;
+        }
+
+        let key = self.sample();
+        dir_light.direction = self.sun_direction();
+        dir_light.view_pos = dir_light.direction * dir_light.distance;
+        dir_light.diffuse = Vec3::from(key.sun_color) * key.sun_intensity;
+        dir_light.specular = dir_light.diffuse;
+        dir_light.ambient = key.ambient.into();
+
+        sky.sun_intensity = key.sky_intensity;
+        sky.cubemap_tint = key.sky_tint.into();
+    }
+
+    /// Points towards the sun. Half a turn from sunrise to sunset, the other half through the night.
+    pub fn sun_direction(&self) -> Vec3 {
+        let TimeOfDayConfig { sunrise_hour, sunset_hour, .. } = self.config;
+        let day_length = (sunset_hour - sunrise_hour).clamp(0.1, 23.9);
+        let since_sunrise = (self.hour - sunrise_hour).rem_euclid(24.0);
+
+        let angle = if since_sunrise < day_length {
+            since_sunrise / day_length * PI
+        } else {
+            PI + (since_sunrise - day_length) / (24.0 - day_length) * PI
+        };
+
+        let azimuth = self.config.sun_azimuth.to_radians();
+        let rise = vec3(azimuth.cos(), 0.0, azimuth.sin());
+        // Top of the arc, tilted from straight up towards the horizon
+        let elevation = self.config.sun_max_elevation.to_radians();
+        let across = vec3(-rise.z, 0.0, rise.x);
+        let top = Vec3::Y * elevation.sin() + across * elevation.cos();
+
+        (rise * angle.cos() + top * angle.sin()).normalize()
+    }
+
+    /// The keys on either side of the current hour, blended. Wraps past midnight.
+    pub fn sample(&self) -> TimeKey {
+        let keys = &self.config.keys;
+        let Some(first) = keys.first() else {
+            return TimeKey {
+                hour: self.hour,
+                sun_color: [1.0, 1.0, 1.0],
+                sun_intensity: 1.0,
+                ambient: [0.6, 0.6, 0.6],
+                sky_intensity: 20.0,
+                sky_tint: [1.0, 1.0, 1.0],
+            };
+        };
+        let last = keys.last().unwrap();
+
+        let (from, from_hour, to, to_hour) = match keys.iter().position(|k| k.hour > self.hour) {
+            Some(0) => (last, last.hour - 24.0, first, first.hour),
+            Some(i) => (&keys[i - 1], keys[i - 1].hour, &keys[i], keys[i].hour),
+            None => (last, last.hour, first, first.hour + 24.0),
+        };
+        let t = ((self.hour - from_hour) / (to_hour - from_hour).max(0.0001)).clamp(0.0, 1.0);
+
+        let lerp3 = |a: [f32; 3], b: [f32; 3]| Vec3::from(a).lerp(Vec3::from(b), t).to_array();
+        TimeKey {
+            hour: self.hour,
+            sun_color: lerp3(from.sun_color, to.sun_color),
+            sun_intensity: from.sun_intensity + (to.sun_intensity - from.sun_intensity) * t,
+            ambient: lerp3(from.ambient, to.ambient),
+            sky_intensity: from.sky_intensity + (to.sky_intensity - from.sky_intensity) * t,
+            sky_tint: lerp3(from.sky_tint, to.sky_tint),
+        }
+    }
+
+    /// "HH:MM" for the UI.
+    pub fn clock(&self) -> String {
+        let minutes = (self.hour * 60.0) as u32;
+        format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+    }
+}
+
+/// Wraps key hours into 0..24 and sorts them, sample brackets the hour assuming both.
+fn normalized(config: &TimeOfDayConfig) -> TimeOfDayConfig {
+    let mut config = config.clone();
+    for key in &mut config.keys {
+        key.hour = key.hour.rem_euclid(24.0);
+    }
+    config.keys.sort_by(|a, b| a.hour.total_cmp(&b.hour));
+    config
+}
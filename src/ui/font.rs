@@ -138,6 +138,8 @@ impl FontManager {
 
     pub fn render_phrase(&self, phrase: &str, x: f32, y: f32, fb_width: f32, fb_height: f32, shader: &Shader, scale: f32) {
         shader.activate();
+        // Set per draw since reloading text.glsl resets every uniform
+        shader.set_int("textTexture", 1);
 
         unsafe {
             gl::Enable(gl::BLEND);
@@ -1,38 +1,17 @@
-use std::{collections::{HashMap, HashSet}, ffi::c_void};
+use std::collections::{HashMap, HashSet};
 
 use glam::{Vec2, Vec4};
 use glfw::CursorMode;
-use image::GenericImageView;
 
-use crate::{enums_types::CameraState, gl_call, shaders::Shader};
+use crate::{assets::{Assets, TextureHandle}, enums_types::CameraState, gl_call, shaders::Shader};
 
 use super::{color::hex_to_vec4, font::FontManager, message_queue::{MessageQueue, UiMessage}};
 
-pub struct TextureCache {
-    pub map: HashMap<String, u32>,
-}
-
-impl TextureCache {
-    pub fn new() -> Self {
-        Self { map: HashMap::new() }
-    }
-
-    pub fn get_or_load(&mut self, path: &str) -> u32 {
-        if let Some(&id) = self.map.get(path) {
-            return id;
-        }
-
-        let id = create_2d_texture(path);
-        self.map.insert(path.to_string(), id);
-        id
-    }
-}
-
 pub struct GameUiContext {
     pub vao: u32,
     pub vbo: u32,
     pub quad_vertices: Vec<f32>,
-    pub tex_cache: TextureCache,
+    pub icons: HashMap<String, TextureHandle>,
 }
 
 impl GameUiContext {
@@ -66,9 +45,18 @@ impl GameUiContext {
             vao,
             vbo,
             quad_vertices: vec![0.0; 54],
-            tex_cache: TextureCache::new(),
+            icons: HashMap::new(),
         }
     }
+
+    /// Each icon keeps a single reference in the asset store for as long as the ui is around.
+    pub fn icon(&mut self, path: &str, assets: &mut Assets) -> u32 {
+        let handle = *self.icons
+            .entry(path.to_string())
+            .or_insert_with(|| assets.load_texture(path, gl::NEAREST));
+
+        assets.texture_id(handle)
+    }
 }
 
 #[derive(Clone)]
@@ -82,7 +70,7 @@ pub struct Rect {
     pub texture_id: Option<u32>,
 }
 
-pub fn do_ui(fb_width: f32, fb_height: f32, mouse_pos: Vec2, fm: &mut FontManager, shader: &Shader, font_shader: &Shader, mq: &mut MessageQueue, paused: bool, cm: CursorMode, cs: &CameraState, pk: &HashSet<glfw::Key>, ui_ctx: &mut GameUiContext) {
+pub fn do_ui(fb_width: f32, fb_height: f32, mouse_pos: Vec2, fm: &mut FontManager, shader: &Shader, font_shader: &Shader, mq: &mut MessageQueue, paused: bool, cm: CursorMode, cs: &CameraState, pk: &HashSet<glfw::Key>, ui_ctx: &mut GameUiContext, assets: &mut Assets) {
     let mut rects = vec![];
     // =============================================================
     // PAUSE PANEL
@@ -183,7 +171,7 @@ pub fn do_ui(fb_width: f32, fb_height: f32, mouse_pos: Vec2, fm: &mut FontManage
                         cm, 
                         pk,
                         Some(
-                            ui_ctx.tex_cache.get_or_load("resources/textures/guy.png")
+                            ui_ctx.icon("resources/textures/guy.png", assets)
                         ),
                     ) {
                         println!("Activated 1");
@@ -202,7 +190,7 @@ pub fn do_ui(fb_width: f32, fb_height: f32, mouse_pos: Vec2, fm: &mut FontManage
                         cm, 
                         pk,
                         Some(
-                            ui_ctx.tex_cache.get_or_load("resources/textures/tree.png")
+                            ui_ctx.icon("resources/textures/tree.png", assets)
                         ),
                     ) {
                         println!("Activated 2");
@@ -221,7 +209,7 @@ pub fn do_ui(fb_width: f32, fb_height: f32, mouse_pos: Vec2, fm: &mut FontManage
                         cm, 
                         pk,
                         Some(
-                            ui_ctx.tex_cache.get_or_load("resources/textures/moose.png")
+                            ui_ctx.icon("resources/textures/moose.png", assets)
                         ),
                     ) {
                         println!("Activated 3");
@@ -399,45 +387,3 @@ pub fn button(
 
     clicked
 }
-
-fn create_2d_texture(path: &str) -> u32 { 
-    let mut texture_id = 0;
-
-    unsafe {
-        gl_call!(gl::GenTextures(1, &mut texture_id));
-
-        let img = match image::open(path) {
-            Ok(data) => Some(data),
-            Err(_) => panic!("Failed to load 2D GameUI image")
-        };
-
-        if let Some(img) = img {
-            let (img_width, img_height) = img.dimensions();
-            let rgba = img.to_rgba8();
-            let raw = rgba.as_raw();
-
-            gl_call!(gl::BindTexture(gl::TEXTURE_2D, texture_id));
-            gl_call!(gl::TexImage2D(
-                gl::TEXTURE_2D, 
-                0, 
-                gl::RGBA as i32, 
-                img_width as i32, 
-                img_height as i32, 
-                0, 
-                gl::RGBA, 
-                gl::UNSIGNED_BYTE, 
-                raw.as_ptr() as *const c_void
-            ));
-
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
-            gl_call!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
-            gl_call!(gl::GenerateMipmap(gl::TEXTURE_2D));
-        };
-
-    }
-
-    texture_id
-}
-
@@ -1,8 +1,8 @@
-use glam::{Quat, Vec3};
+use glam::{Mat4, Quat, Vec3};
 use glfw::{Action, MouseButton, PWindow, WindowEvent};
 use imgui::Drag;
 
-use crate::{animation::animation::Animator, camera::Camera, config::world_data::{EntityInstance, WorldData}, entity_manager::EntityManager, enums_types::{CameraState, EntityType, Faction}, gl_call, lights::Lights, renderer::Renderer, sound::sound_manager::SoundManager};
+use crate::{animation::animation::Animator, camera::Camera, capture::Capture, config::{watcher::{ConfigFile, ConfigWatcher}, world_data::{EntityInstance, WorldData}}, debug::skeleton::{bone_labels, flatten_bones}, entity_manager::EntityManager, enums_types::{CameraState, EntityType, Faction}, error::LoadError, gl_call, lights::{Lights, PointLight, SpotLight}, post_process::Tonemapper, renderer::Renderer, some_data::{SHADOW_CASCADES, WHITE}, sound::sound_manager::SoundManager, time_of_day::TimeOfDay};
 
 pub struct ImguiManager {
     pub imgui: imgui::Context,
@@ -61,7 +61,7 @@ impl ImguiManager {
         }
     }
 
-    pub fn draw(&mut self, window: &mut PWindow, width: f32, height: f32, delta: f32, lm: &mut Lights, rdr: &mut Renderer, sm: &mut SoundManager, camera: &Camera, em: &mut EntityManager) {
+    pub fn draw(&mut self, window: &mut PWindow, width: f32, height: f32, delta: f32, lm: &mut Lights, tod: &mut TimeOfDay, capture: &mut Capture, rdr: &mut Renderer, sm: &mut SoundManager, camera: &Camera, em: &mut EntityManager, cw: &mut ConfigWatcher, load_errors: &mut Vec<LoadError>) {
         {
             let io = self.imgui.io_mut();
             io.display_size = [width, height];
@@ -75,6 +75,9 @@ impl ImguiManager {
                 .position([50.0, 50.0], imgui::Condition::FirstUseEver)
                 .build(|| {
                     ui.text("Controls for Various Lights");
+                    if tod.enabled {
+                        ui.text_disabled("Dir Light is driven by the time of day, see the Sky window");
+                    }
                     ui.separator();
                     // ui.input_float("Dir Light distance", &mut lm.dir_light.distance).build();
                     if ui.slider("Dir Light X", -1.0, 1.0, &mut lm.dir_light.direction.x) {
@@ -90,16 +93,19 @@ impl ImguiManager {
                     };
 
                     ui.checkbox("Shadow Debug",&mut rdr.shadow_debug);
+                    ui.checkbox("PBR Shading", &mut rdr.use_pbr);
+                    ui.checkbox("Frustum Culling", &mut rdr.frustum_culling);
+                    ui.text(format!("Drawn: {}  Culled: {}", rdr.cull_stats.camera.drawn, rdr.cull_stats.camera.culled));
+                    ui.text(format!("Shadow Casters Drawn: {}  Culled: {}", rdr.cull_stats.shadow.drawn, rdr.cull_stats.shadow.culled));
 
 
                     ui.separator();
 
-                    if ui.slider("Ortho Near", 0.0, 10.0, &mut lm.near) {
-                    };
-                    if ui.slider("Ortho Far", 0.0, 500.0, &mut lm.far) {
-                    };
-                    if ui.slider("Bounds", 0.0, 100.0, &mut lm.bounds) {
-                    };
+                    ui.slider("Shadow Distance", 10.0, 500.0, &mut lm.shadow_distance);
+                    ui.slider("Cascade Split Lambda", 0.0, 1.0, &mut lm.cascade_lambda);
+                    ui.slider("Cascade Z Extension", 0.0, 200.0, &mut lm.cascade_z_extension);
+                    ui.checkbox("Color Cascades", &mut rdr.cascade_debug);
+                    ui.slider("Shadow Debug Cascade", 0, SHADOW_CASCADES as u32 - 1, &mut rdr.shadow_debug_cascade);
 
                     // if ui.slider("Bias Scalar", 0.0, 0.3, &mut lm.bias_scalar) {
                     // };
@@ -110,6 +116,73 @@ impl ImguiManager {
                         .build(ui, &mut lm.bias_scalar) {
                     }
 
+                    ui.separator();
+                    ui.checkbox("SSAO", &mut rdr.ssao.enabled);
+                    ui.same_line();
+                    ui.checkbox("SSAO Debug View", &mut rdr.ssao.debug_view);
+                    ui.slider("SSAO Radius", 0.05, 3.0, &mut rdr.ssao.radius);
+                    ui.slider("SSAO Intensity", 0.0, 4.0, &mut rdr.ssao.intensity);
+                    ui.slider("SSAO Bias", 0.0, 0.2, &mut rdr.ssao.bias);
+
+                    ui.separator();
+                    ui.text(format!("Point Lights: {}  Spot Lights: {}", lm.point_lights.len(), lm.spot_lights.len()));
+                    ui.checkbox("Light Gizmos", &mut rdr.show_light_gizmos);
+                    ui.checkbox("Clustered Lighting", &mut rdr.clusters.enabled);
+                    ui.same_line();
+                    ui.checkbox("Cluster Heatmap", &mut rdr.clusters.heatmap);
+                    ui.text(format!("Most lights in one cluster: {}", rdr.clusters.max_lights_in_cluster));
+
+                    // New lights spawn at the camera, spots aim where it looks
+                    if ui.button("Add Point Light") && lm.has_room() {
+                        lm.add_point_light(PointLight::with_default_strength(camera.position, Vec3::ZERO, WHITE, WHITE), 32);
+                    }
+                    ui.same_line();
+                    if ui.button("Add Spot Light") && lm.has_room() {
+                        lm.add_spot_light(SpotLight::with_default_strength(camera.position, camera.forward, WHITE), 50);
+                    }
+                    ui.same_line();
+                    if ui.button("Clear Lights") {
+                        lm.clear_local_lights();
+                    }
+
+                    for entry in lm.point_lights.iter_mut() {
+                        let _id = ui.push_id_usize(entry.key());
+                        ui.text(format!("Point Light {}", entry.key()));
+                        let light = entry.value_mut();
+
+                        let mut position = light.position.to_array();
+                        if ui.input_float3("Position", &mut position).build() {
+                            light.position = position.into();
+                        }
+                        let mut color = light.diffuse.to_array();
+                        if ui.color_edit3("Color", &mut color) {
+                            light.diffuse = color.into();
+                        }
+                        ui.checkbox("Casts Shadow", &mut light.casts_shadow);
+                    }
+
+                    for entry in lm.spot_lights.iter_mut() {
+                        let _id = ui.push_id_usize(entry.key());
+                        ui.text(format!("Spot Light {}", entry.key()));
+                        let light = entry.value_mut();
+
+                        let mut position = light.position.to_array();
+                        if ui.input_float3("Position", &mut position).build() {
+                            light.position = position.into();
+                        }
+                        let mut direction = light.direction.to_array();
+                        if ui.input_float3("Direction", &mut direction).build() {
+                            light.direction = direction.into();
+                        }
+                        let mut color = light.diffuse.to_array();
+                        if ui.color_edit3("Color", &mut color) {
+                            light.diffuse = color.into();
+                        }
+                        ui.slider("Inner Angle", 0.0, 90.0, &mut light.inner_angle);
+                        ui.slider("Outer Angle", 0.0, 90.0, &mut light.outer_angle);
+                        light.outer_angle = light.outer_angle.max(light.inner_angle);
+                    }
+
                     lm.dir_light.view_pos.x = lm.dir_light.direction.x * lm.dir_light.distance;
                     lm.dir_light.view_pos.y = lm.dir_light.direction.y * lm.dir_light.distance;
                     lm.dir_light.view_pos.z = lm.dir_light.direction.z * lm.dir_light.distance;
@@ -144,6 +217,7 @@ impl ImguiManager {
                 .build(|| {
                     if ui.button("Save Entity State") {
                         let mut save_data = WorldData {
+                            time_of_day: tod.level_hour,
                             entities: vec![]
                         };
 
@@ -220,7 +294,378 @@ impl ImguiManager {
 
                 });
 
-        } 
+            ui.window("Skeleton")
+                .size([400.0, 500.0], imgui::Condition::FirstUseEver)
+                .position([1100.0, 250.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    ui.checkbox("Draw Skeletons", &mut rdr.skeleton_debug.enabled);
+                    ui.checkbox("Bone Labels", &mut rdr.skeleton_debug.show_labels);
+                    ui.checkbox("Octahedral Bones", &mut rdr.skeleton_debug.octahedra);
+                    ui.separator();
+
+                    let Some(id) = rdr.skeleton_debug.inspected_entity(em) else {
+                        ui.text("Select an animated entity to inspect its bones");
+                        return;
+                    };
+
+                    let skellington = em.skellingtons.get(id).unwrap();
+                    let mut bones = vec![];
+                    flatten_bones(skellington, 0, &mut bones);
+
+                    ui.text(format!("Entity: {}, Bones: {}", id, bones.len()));
+
+                    ui.child_window("Bone List")
+                        .size([0.0, 200.0])
+                        .border(true)
+                        .build(|| {
+                            for (depth, bone_id, name) in bones.iter() {
+                                let label = format!("{}{}##{}", "  ".repeat(*depth), name, bone_id);
+                                let is_selected = rdr.skeleton_debug.selected_bone == Some(*bone_id);
+
+                                if ui.selectable_config(label).selected(is_selected).build() {
+                                    rdr.skeleton_debug.selected_bone = Some(*bone_id);
+                                }
+                            }
+                        });
+
+                    let Some(bone_id) = rdr.skeleton_debug.selected_bone else {
+                        return;
+                    };
+                    let animator = em.animators.get(id).unwrap();
+                    let Some(animation) = animator.animations.get(&animator.current_animation) else {
+                        return;
+                    };
+                    let idx = bone_id as usize;
+                    let Some(join) = animation.model_animation_join.get(idx) else {
+                        return;
+                    };
+
+                    ui.separator();
+                    ui.text(format!("Bone {}: {}", bone_id, join.name));
+
+                    if let (Some(local), Some(global)) = (animation.current_locals.get(idx), animation.current_globals.get(idx)) {
+                        transform_text(ui, "Local", local);
+                        transform_text(ui, "Global", global);
+                    }
+
+                    if let Some(track) = animation.bone_transforms.get(&join.name) {
+                        ui.separator();
+                        ui.text(format!(
+                            "Keys: {} pos, {} rot, {} scale",
+                            track.positions.len(),
+                            track.rotations.len(),
+                            track.scales.len(),
+                        ));
+
+                        let segment = (animation.current_segment as usize).min(track.positions.len().saturating_sub(1));
+                        ui.text(format!("Segment: {} / {}", segment, track.positions.len()));
+
+                        if let (Some(time), Some(pos), Some(rot), Some(scale)) = (
+                            track.position_timestamps.get(segment),
+                            track.positions.get(segment),
+                            track.rotations.get(segment),
+                            track.scales.get(segment),
+                        ) {
+                            ui.text(format!("t: {:.3}s", time));
+                            ui.text(format!("pos: {:.3} {:.3} {:.3}", pos.x, pos.y, pos.z));
+                            ui.text(format!("rot: {:.3} {:.3} {:.3} {:.3}", rot.x, rot.y, rot.z, rot.w));
+                            ui.text(format!("scale: {:.3} {:.3} {:.3}", scale.x, scale.y, scale.z));
+                        }
+                    } else {
+                        ui.text_colored([1.0, 0.3, 0.3, 1.0], "No track for this bone in the current clip");
+                    }
+                });
+
+
+            ui.window("Animator")
+                .size([400.0, 380.0], imgui::Condition::FirstUseEver)
+                .position([1100.0, 760.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    let Some(id) = em.selected.iter().copied().find(|id| em.animators.contains(*id)) else {
+                        ui.text("Select an animated entity to inspect its animator");
+                        return;
+                    };
+                    let animator = em.animators.get_mut(id).unwrap();
+
+                    ui.text(format!("Entity: {}", id));
+                    ui.text(format!("Current: {}", animator.current_animation));
+                    ui.text(format!("Next: {}", animator.next_animation));
+
+                    let blend = if animator.current_animation != animator.next_animation { animator.blend_factor } else { 0.0 };
+                    imgui::ProgressBar::new(blend)
+                        .overlay_text(format!("Blend {:.2}", blend))
+                        .build(ui);
+                    ui.slider("Blend Time", 0.01, 2.0, &mut animator.blend_time);
+
+                    ui.separator();
+
+                    ui.checkbox("Paused", &mut animator.paused);
+                    ui.same_line();
+                    if ui.button("Step") {
+                        animator.paused = true;
+                        animator.step();
+                    }
+                    ui.same_line();
+                    if ui.button("1x") {
+                        animator.playback_speed = 1.0;
+                    }
+                    ui.slider("Speed", 0.0, 3.0, &mut animator.playback_speed);
+
+                    let current = animator.current_animation.clone();
+                    let Some(animation) = animator.animations.get_mut(&current) else {
+                        return;
+                    };
+
+                    ui.separator();
+                    ui.text(format!(
+                        "Clip: {} ({:.0} fps), segment {}",
+                        current,
+                        animation.fps(),
+                        animation.current_segment,
+                    ));
+
+                    // Scrubbing only makes sense while paused, otherwise the clip runs away from the slider
+                    let duration = animation.duration.max(0.001);
+                    if ui.slider("Time", 0.0, duration, &mut animation.current_time) {
+                        animator.paused = true;
+                    }
+
+                    // Timeline with the playhead and a marker for every OneShot
+                    let draw_list = ui.get_window_draw_list();
+                    let [x, y] = ui.cursor_screen_pos();
+                    let w = ui.content_region_avail()[0];
+                    let h = 24.0;
+
+                    draw_list.add_rect([x, y], [x + w, y + h], [0.2, 0.2, 0.2, 1.0]).filled(true).build();
+
+                    for os in animation.one_shots.iter() {
+                        let Some(t) = animation.segment_start_time(os.segment) else {
+                            continue;
+                        };
+                        let mx = x + (t / duration).clamp(0.0, 1.0) * w;
+                        let color = if os.triggered.get() { [1.0, 0.9, 0.2, 1.0] } else { [0.9, 0.5, 0.1, 1.0] };
+                        draw_list.add_line([mx, y], [mx, y + h], color).thickness(2.0).build();
+                    }
+
+                    let px = x + (animation.current_time / duration).clamp(0.0, 1.0) * w;
+                    draw_list.add_line([px, y], [px, y + h], [1.0, 1.0, 1.0, 1.0]).thickness(2.0).build();
+
+                    ui.dummy([w, h]);
+
+                    if animation.one_shots.is_empty() {
+                        ui.text("No one shots on this clip");
+                    }
+
+                    for os in animation.one_shots.iter() {
+                        let t = animation.segment_start_time(os.segment).unwrap_or(0.0);
+                        let text = format!("{} @ segment {} ({:.3}s)", os.sound_type, os.segment, t);
+                        if os.triggered.get() {
+                            ui.text_colored([1.0, 0.9, 0.2, 1.0], text);
+                        } else {
+                            ui.text(text);
+                        }
+                    }
+
+                    if !animation.current_morph_weights.is_empty() {
+                        ui.separator();
+                        let mut names: Vec<&String> = animation.current_morph_weights.keys().collect();
+                        names.sort();
+
+                        for name in names {
+                            let weight = animation.current_morph_weights[name];
+                            imgui::ProgressBar::new(weight.clamp(0.0, 1.0))
+                                .overlay_text(format!("{}: {:.2}", name, weight))
+                                .build(ui);
+                        }
+                    }
+                });
+
+            ui.window("Assets")
+                .size([400.0, 300.0], imgui::Condition::FirstUseEver)
+                .position([50.0, 760.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    let assets = &em.assets;
+                    if ui.collapsing_header(format!("Meshes ({})", assets.meshes.len()), imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                        for (handle, path, refs) in assets.meshes.entries() {
+                            ui.text(format!("{:?} x{} {}", handle, refs, path.unwrap_or("<generated>")));
+                            if let Some(model) = assets.mesh(handle) {
+                                if model.submeshes.len() > 1 {
+                                    ui.same_line();
+                                    ui.text_disabled(format!("({} submeshes, {} materials)", model.submeshes.len(), model.materials.len()));
+                                }
+                            }
+                        }
+                    }
+                    if ui.collapsing_header(format!("Skeletons ({})", assets.skeletons.len()), imgui::TreeNodeFlags::empty()) {
+                        for (handle, path, refs) in assets.skeletons.entries() {
+                            ui.text(format!("{:?} x{} {}", handle, refs, path.unwrap_or("<generated>")));
+                        }
+                    }
+                    if ui.collapsing_header(format!("Textures ({})", assets.textures.len()), imgui::TreeNodeFlags::empty()) {
+                        for (handle, path, refs) in assets.textures.entries() {
+                            ui.text(format!("{:?} x{} {}", handle, refs, path.unwrap_or("<generated>")));
+                        }
+                    }
+                    let shaders = &rdr.shaders.store;
+                    if ui.collapsing_header(format!("Shaders ({})", shaders.len()), imgui::TreeNodeFlags::empty()) {
+                        for (handle, path, refs) in shaders.entries() {
+                            ui.text(format!("{:?} x{} {}", handle, refs, path.unwrap_or("<generated>")));
+                        }
+                    }
+                });
+
+            ui.window("Sky")
+                .size([400.0, 300.0], imgui::Condition::FirstUseEver)
+                .position([870.0, 760.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    ui.checkbox("Day/Night Cycle", &mut tod.enabled);
+                    ui.same_line();
+                    ui.checkbox("Pause Clock", &mut tod.paused);
+                    ui.text(format!("Time: {}", tod.clock()));
+                    ui.slider("Hour", 0.0, 24.0, &mut tod.hour);
+                    tod.hour = tod.hour.rem_euclid(24.0);
+                    Drag::new("Game Hours / Second").speed(0.001).range(0.0, 10.0).display_format("%.3f").build(ui, &mut tod.speed);
+                    ui.separator();
+
+                    let sky = &mut rdr.sky;
+                    ui.checkbox("Procedural Sky", &mut sky.procedural);
+                    ui.text_disabled("Sun follows the Dir Light direction");
+                    ui.slider("Sun Intensity", 0.0, 60.0, &mut sky.sun_intensity);
+                    let mut rayleigh = sky.rayleigh.to_array();
+                    if Drag::new("Rayleigh").speed(0.001).range(0.0, 1.0).build_array(ui, &mut rayleigh) {
+                        sky.rayleigh = rayleigh.into();
+                    }
+                    ui.slider("Mie", 0.0, 0.2, &mut sky.mie);
+                    ui.slider("Mie G", 0.0, 0.99, &mut sky.mie_g);
+                    let mut ground_color = sky.ground_color.to_array();
+                    if ui.color_edit3("Ground Color", &mut ground_color) {
+                        sky.ground_color = ground_color.into();
+                    }
+                    ui.separator();
+
+                    ui.checkbox("Fog", &mut sky.fog.enabled);
+                    Drag::new("Fog Density").speed(0.0001).range(0.0, 0.2).display_format("%.4f").build(ui, &mut sky.fog.density);
+                    ui.slider("Fog Height Falloff", 0.0, 1.0, &mut sky.fog.height_falloff);
+                    ui.slider("Fog Base Height", -20.0, 50.0, &mut sky.fog.base_height);
+                    let mut fog_color = sky.fog.color.to_array();
+                    if ui.color_edit3("Fog Color (cubemap)", &mut fog_color) {
+                        sky.fog.color = fog_color.into();
+                    }
+                });
+
+            ui.window("Post Processing")
+                .size([400.0, 260.0], imgui::Condition::FirstUseEver)
+                .position([460.0, 760.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    let post = &mut rdr.post;
+                    ui.checkbox("HDR", &mut post.enabled);
+                    ui.slider("Exposure", 0.05, 8.0, &mut post.exposure);
+                    for tonemapper in Tonemapper::ALL {
+                        ui.radio_button(tonemapper.name(), &mut post.tonemapper, tonemapper);
+                        ui.same_line();
+                    }
+                    ui.new_line();
+                    ui.separator();
+
+                    ui.checkbox("Bloom", &mut post.bloom.enabled);
+                    ui.slider("Bloom Threshold", 0.0, 5.0, &mut post.bloom.threshold);
+                    ui.slider("Bloom Knee", 0.0, 1.0, &mut post.bloom.knee);
+                    ui.slider("Bloom Intensity", 0.0, 3.0, &mut post.bloom.intensity);
+                    ui.slider("Bloom Radius", 0.001, 0.02, &mut post.bloom.filter_radius);
+                });
+
+            ui.window("Capture")
+                .size([400.0, 160.0], imgui::Condition::FirstUseEver)
+                .position([1100.0, 210.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    if ui.button("Screenshot (F12)") {
+                        capture.request_screenshot();
+                    }
+                    ui.same_line();
+                    let label = if capture.is_recording() { "Stop Recording (F10)" } else { "Record Sequence (F10)" };
+                    if ui.button(label) {
+                        capture.toggle_recording();
+                    }
+                    ui.checkbox("Include UI", &mut capture.include_ui);
+                    ui.slider("Record FPS", 1, 120, &mut capture.record_fps);
+                    if let Some(output) = &capture.last_output {
+                        ui.text_wrapped(format!("Last saved: {}", output));
+                    }
+                });
+        }
+
+        if camera.move_state == CameraState::Locked || !cw.errors.is_empty() {
+            ui.window("Config")
+                .size([500.0, 150.0], imgui::Condition::FirstUseEver)
+                .position([550.0, 540.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    ui.checkbox("Watch config/", &mut cw.enabled);
+                    ui.checkbox("Reload level on world_data change", &mut cw.reload_level);
+
+                    let mut files: Vec<&ConfigFile> = cw.errors.keys().collect();
+                    files.sort_by_key(|f| f.path());
+                    for file in files {
+                        ui.text_colored([1.0, 0.3, 0.3, 1.0], format!("{} failed to reload, keeping the old config:", file.path()));
+                        ui.text_wrapped(&cw.errors[file]);
+                    }
+                });
+        }
+
+        // Always pops up on a failed reload, otherwise only alongside the other editing windows
+        if camera.move_state == CameraState::Locked || !rdr.shader_errors.is_empty() {
+            ui.window("Shaders")
+                .size([500.0, 250.0], imgui::Condition::FirstUseEver)
+                .position([550.0, 270.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    ui.checkbox("Hot Reload", &mut rdr.shader_hot_reload);
+                    ui.same_line();
+                    if ui.button("Reload All") {
+                        rdr.reload_all_shaders();
+                    }
+
+                    if rdr.shader_errors.is_empty() {
+                        ui.text_colored([0.3, 1.0, 0.3, 1.0], "All shaders compiled");
+                        return;
+                    }
+
+                    let mut paths: Vec<&String> = rdr.shader_errors.keys().collect();
+                    paths.sort();
+                    for path in paths {
+                        ui.text_colored([1.0, 0.3, 0.3, 1.0], path);
+                        ui.text_wrapped(&rdr.shader_errors[path]);
+                        ui.separator();
+                    }
+                });
+        }
+
+        // Everything here is already running on a placeholder, this just says what and why
+        if !load_errors.is_empty() {
+            let mut dismissed = false;
+            ui.window("Load Errors")
+                .size([600.0, 250.0], imgui::Condition::FirstUseEver)
+                .position([550.0, 530.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    ui.text_colored([1.0, 0.3, 0.3, 1.0], format!("{} problems while loading, using fallbacks", load_errors.len()));
+                    ui.same_line();
+                    dismissed = ui.button("Dismiss");
+                    ui.separator();
+
+                    for e in load_errors.iter() {
+                        ui.text_wrapped(e.to_string());
+                    }
+                });
+            if dismissed {
+                load_errors.clear();
+            }
+        }
+
+        if rdr.skeleton_debug.enabled && rdr.skeleton_debug.show_labels {
+            let draw_list = ui.get_foreground_draw_list();
+            let inspected = rdr.skeleton_debug.inspected_entity(em);
+            for label in bone_labels(em, camera, width, height, inspected, rdr.skeleton_debug.selected_bone) {
+                draw_list.add_text(label.screen_pos, label.color, &label.name);
+            }
+        }
 
         ui.window("Some Info")
             .size([400.0, 150.0], imgui::Condition::FirstUseEver)
@@ -238,3 +683,12 @@ impl ImguiManager {
 
 }
 
+fn transform_text(ui: &imgui::Ui, label: &str, m: &Mat4) {
+    let (scale, rot, pos) = m.to_scale_rotation_translation();
+    let (y, x, z) = rot.to_euler(glam::EulerRot::YXZ);
+
+    ui.text(label);
+    ui.text(format!("  pos: {:.3} {:.3} {:.3}", pos.x, pos.y, pos.z));
+    ui.text(format!("  rot: {:.1} {:.1} {:.1}", x.to_degrees(), y.to_degrees(), z.to_degrees()));
+    ui.text(format!("  scale: {:.3} {:.3} {:.3}", scale.x, scale.y, scale.z));
+}